@@ -1,17 +1,235 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
-//! Generates a sprite sheet of random characters.
+//! Generates a sprite sheet of random characters, checks art files with `lint`, converts
+//! full-colour art into key colours with `import`, exports saved characters with `export`, or
+//! regenerates ART.md's key tables with `keys`.
 //! Effectively a proof of concept API style usage of the FECC core library.
 use fecc_core::asset::AssetType;
+use fecc_core::asset::category::{AssetCategory, Canvas};
+use fecc_core::bundle::FeccBundle;
 use fecc_core::character::{Character, Colourable};
-use fecc_core::export::export_character;
-use fecc_core::file_io::{load_asset_libraries, load_colours_from_csv, load_image_bytes};
+use fecc_core::export::{Anchor, ExportSize, export_character, parse_dimensions};
+use fecc_core::file_io::asset_root::AssetRoot;
+use fecc_core::file_io::{
+    load_asset_categories, load_asset_libraries, load_colours_from_csv, load_image_bytes,
+    load_recolour_schemes, register_bundled_assets,
+};
+use fecc_core::import::import_file;
+use fecc_core::lint::lint_path;
 use fecc_core::random::{randomize_assets, randomize_colours};
+use fecc_core::recolour::scheme::{KeySlot, key_tables_markdown, replace_key_tables};
 use fecc_core::types::Point;
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::sync::Arc;
 use strum::IntoEnumIterator;
 
+const USAGE: &str = "Usage: fecc_cli [lint [--json] <art file or directory>...]
+       fecc_cli import <image> <name> <type> [output directory]
+       fecc_cli export [--size <preset>] [--portrait <w>x<h>] [--token <w>x<h>] [--scale <n>]
+                       [--anchor <anchor>] <character.fecc or .feccz> [output directory]
+       fecc_cli keys";
+
+/// The art guide, whose key tables are generated by `keys`.
+const ART_GUIDE: &str = "art/ART.md";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        None => generate_sprite_sheet().await,
+        Some((command, args)) if command == "lint" => lint(args).await,
+        Some((command, args)) if command == "import" => import(args).await,
+        Some((command, args)) if command == "export" => export(args).await,
+        Some((command, [])) if command == "keys" => keys().await,
+        Some(_) => Err(USAGE.into()),
+    }
+}
+
+/// Registers the asset categories and recolour schemes in `assets/csv`.
+///
+/// Art of user-defined categories is only recognised once they're registered.
+async fn register_categories_and_schemes() {
+    if let Ok(categories) = load_asset_categories().await {
+        AssetCategory::register(categories);
+    }
+    if let Ok(slots) = load_recolour_schemes().await {
+        KeySlot::register(slots);
+    }
+}
+
+/// Checks art files for mistakes, printing the problems found as text, or as JSON with `--json`.
+///
+/// Fails if any file has a problem, so it can be used in scripts.
+async fn lint(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--json").collect();
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    register_categories_and_schemes().await;
+
+    let mut reports = Vec::new();
+    for path in paths {
+        reports.extend(lint_path(Path::new(path))?);
+    }
+    let failing = reports.iter().filter(|report| !report.is_clean()).count();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in reports.iter().filter(|report| !report.is_clean()) {
+            println!("{}:", report.path.display());
+            for issue in &report.issues {
+                println!("  {issue}");
+            }
+        }
+        println!("{failing} of {} file(s) have problems.", reports.len());
+    }
+
+    if failing > 0 {
+        return Err(format!("{failing} art file(s) have problems").into());
+    }
+    Ok(())
+}
+
+/// Converts a full-colour image into art of a type, written as `Name_Type.png` to the output
+/// directory or the current one, and prints the mapping and colours used as JSON.
+async fn import(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let [source, name, asset_type, rest @ ..] = args else {
+        return Err(USAGE.into());
+    };
+    let dir = match rest {
+        [] => Path::new("."),
+        [dir] => Path::new(dir),
+        _ => return Err(USAGE.into()),
+    };
+
+    register_categories_and_schemes().await;
+    let asset_type: AssetType = asset_type.parse()?;
+
+    let (path, imported) =
+        import_file(Path::new(source), name, asset_type, dir).map_err(|e| e.to_string())?;
+    eprintln!("Wrote {}", path.display());
+    let colours: BTreeMap<_, _> = imported.colours.into_iter().collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({
+            "mappings": imported.mappings,
+            "colours": colours,
+            "outline": imported.outline,
+        }))?
+    );
+    Ok(())
+}
+
+/// Exports the portrait and token of a saved character as `Name_portrait.png` and
+/// `Name_token.png`, in the output directory or the current one.
+///
+/// The size starts from a preset, `Original` by default, which the other options change.
+async fn export(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut size = ExportSize::default();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--size" => {
+                let name = value()?;
+                size = ExportSize::preset(name).ok_or_else(|| {
+                    let presets: Vec<&str> =
+                        ExportSize::PRESETS.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "Unknown size '{name}', expected one of: {}",
+                        presets.join(", ")
+                    )
+                })?;
+            }
+            "--portrait" => size.portrait = parse_dimensions(value()?)?,
+            "--token" => size.token = parse_dimensions(value()?)?,
+            "--scale" => {
+                size.scale = value()?
+                    .parse()
+                    .ok()
+                    .filter(|&scale| scale > 0)
+                    .ok_or("The scale must be a whole number above 0")?;
+            }
+            "--anchor" => size.anchor = value()?.parse::<Anchor>()?,
+            _ => paths.push(arg),
+        }
+    }
+    let (source, dir) = match paths.as_slice() {
+        [source] => (Path::new(source), Path::new(".")),
+        [source, dir] => (Path::new(source), Path::new(dir)),
+        _ => return Err(USAGE.into()),
+    };
+
+    register_categories_and_schemes().await;
+    let mut asset_libraries = load_asset_libraries(&AssetRoot::configured())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let bundle = FeccBundle::from_bytes(&std::fs::read(source)?)?;
+    register_bundled_assets(&mut asset_libraries, bundle.assets);
+    let mut character = bundle.file.into_normalised_character();
+    let report = character.resolve_assets(&asset_libraries);
+    for missing in &report.missing {
+        eprintln!(
+            "Warning: '{}' wasn't found and is left out",
+            missing.layer.part.asset.id
+        );
+    }
+
+    for layer in &mut character.layers {
+        let asset = &mut layer.part.asset;
+        if asset.image_data.is_none() {
+            let bytes = load_image_bytes(&asset.path)
+                .await
+                .map_err(|e| e as Box<dyn std::error::Error>)?;
+            asset.image_data = Some(Arc::new(image::load_from_memory(&bytes)?.to_rgba8()));
+        }
+    }
+
+    // Draw the character on canvases the size of its art.
+    let art_size = |canvas: Canvas| {
+        let side = canvas.art_size() as f32;
+        Point::new(side, side)
+    };
+    character.denormalise(art_size(Canvas::Portrait), art_size(Canvas::Token));
+
+    let name = if character.name.is_empty() {
+        source
+            .file_stem()
+            .map_or_else(|| "character".into(), |stem| stem.to_string_lossy())
+            .into_owned()
+    } else {
+        character.name.clone()
+    };
+    for (canvas, suffix) in [(Canvas::Portrait, "portrait"), (Canvas::Token, "token")] {
+        let image = size
+            .export(&character, canvas, art_size(canvas))
+            .ok_or("Failed to export character image")?;
+        let path = dir.join(format!("{name}_{suffix}.png"));
+        image.save(&path)?;
+        eprintln!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Rewrites the key tables in ART.md from the recolour schemes and categories in `assets/csv`.
+async fn keys() -> Result<(), Box<dyn std::error::Error>> {
+    AssetCategory::register(load_asset_categories().await.map_err(|e| e.to_string())?);
+    KeySlot::register(load_recolour_schemes().await.map_err(|e| e.to_string())?);
+
+    let tables = key_tables_markdown(&KeySlot::all(), &AssetCategory::all());
+    let guide = std::fs::read_to_string(ART_GUIDE)?;
+    std::fs::write(ART_GUIDE, replace_key_tables(&guide, &tables)?)?;
+    eprintln!("Wrote {ART_GUIDE}");
+    Ok(())
+}
+
+/// Generates a sprite sheet of random characters, saved to `sprites.png`.
+async fn generate_sprite_sheet() -> Result<(), Box<dyn std::error::Error>> {
     simple_logger::init_with_level(log::Level::Info)?;
 
     log::info!("Copyright (C) 2025 aidan-es");
@@ -21,7 +239,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Full License: https://www.gnu.org/licenses/agpl-3.0.html");
     log::info!("Starting character sprite sheet generation...");
 
-    let asset_libraries = load_asset_libraries()
+    let asset_libraries = load_asset_libraries(&AssetRoot::configured())
         .await
         .expect("Failed to load asset libraries");
 
@@ -55,18 +273,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut sprite_sheet = image::RgbaImage::new(sheet_width, sheet_height);
 
-    let types_to_randomize: Vec<AssetType> = AssetType::get_selectable_part_types()
-        .filter(|&t| t != AssetType::Accessory)
+    let types_to_randomize: Vec<AssetType> = AssetType::randomised()
+        .into_iter()
+        .filter(|t| t.category().canvas == Canvas::Portrait)
         .collect();
 
     let output_size = (TILE_SIZE, TILE_SIZE);
     let ui_canvas_size = Point::new(TILE_SIZE as f32, TILE_SIZE as f32);
-    let parts_to_draw = &[
-        AssetType::HairBack,
-        AssetType::Armour,
-        AssetType::Face,
-        AssetType::Hair,
-    ];
+    let parts_to_draw = &AssetType::on_canvas(Canvas::Portrait);
 
     log::info!(
         "Generating {} characters in a {}x{} grid...",
@@ -83,25 +297,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             &asset_libraries,
             &types_to_randomize,
             ui_canvas_size,
+            &[],
         );
 
         randomize_colours(&mut character, &colour_palettes);
 
-        for part_type in [
-            AssetType::HairBack,
-            AssetType::Armour,
-            AssetType::Face,
-            AssetType::Hair,
-        ] {
-            if let Some(mut part) = character.get_character_part(&part_type)
-                && part.asset.image_data.is_none()
-            {
-                let bytes = load_image_bytes(&part.asset.path)
+        for layer in &mut character.layers {
+            let asset = &mut layer.part.asset;
+            if parts_to_draw.contains(&layer.asset_type) && asset.image_data.is_none() {
+                let bytes = load_image_bytes(&asset.path)
                     .await
                     .map_err(|e| e as Box<dyn std::error::Error>)?;
                 let image = image::load_from_memory(&bytes)?.to_rgba8();
-                part.asset.image_data = Some(Arc::new(image));
-                character.set_character_part(&part_type, part);
+                asset.image_data = Some(Arc::new(image));
             }
         }
 
@@ -1,31 +1,22 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::recolour::ColourUsage;
 use image::RgbaImage;
+use indexmap::IndexMap;
+use std::collections::HashMap;
 use std::option::Option;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use strum::IntoEnumIterator as _;
-use strum_macros::{Display, EnumIter, EnumString, IntoStaticStr};
 
-/// Represents the layers of a character sprite.
+pub mod category;
+pub mod metadata;
+
+use metadata::AssetMetadata;
+
+/// The type of an asset, given by the suffix of its filename.
 ///
-/// The order of variants determines the default drawing order, from bottom to top.
-#[derive(
-    Debug,
-    PartialEq,
-    Eq,
-    Hash,
-    Clone,
-    Copy,
-    EnumIter,
-    Display,
-    Ord,
-    PartialOrd,
-    IntoStaticStr,
-    Default,
-    serde::Deserialize,
-    serde::Serialize,
-    EnumString,
-)]
+/// Besides the built-in types, any type with a registered `AssetCategory` can be loaded, see
+/// `category`. Serialised as its name, e.g. `"Face"`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Ord, PartialOrd, Default)]
 pub enum AssetType {
     HairBack,
     Armour,
@@ -34,22 +25,124 @@ pub enum AssetType {
     Accessory,
     #[default]
     Token,
+    /// A user-defined type, such as `Cape`.
+    Custom(&'static str),
 }
 
 impl AssetType {
-    /// Returns an iterator over asset types that are selectable in the UI.
+    /// Returns an iterator over asset types that are selectable in the UI, in layer order.
     ///
-    /// `HairBack` is excluded.
+    /// Companion types, such as `HairBack`, are excluded.
     pub fn get_selectable_part_types() -> impl Iterator<Item = Self> {
-        Self::iter().filter(|&p| p != Self::HairBack)
+        Self::all().filter(|p| p.companion_of().is_none())
+    }
+
+    /// Returns the type with the given name, or `None` if it isn't built in or registered.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "HairBack" => Self::HairBack,
+            "Armour" => Self::Armour,
+            "Face" => Self::Face,
+            "Hair" => Self::Hair,
+            "Accessory" => Self::Accessory,
+            "Token" => Self::Token,
+            _ => Self::custom(name)?,
+        })
+    }
+
+    /// Returns the name of the type, as used in filenames.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::HairBack => "HairBack",
+            Self::Armour => "Armour",
+            Self::Face => "Face",
+            Self::Hair => "Hair",
+            Self::Accessory => "Accessory",
+            Self::Token => "Token",
+            Self::Custom(name) => name,
+        }
+    }
+}
+
+impl std::fmt::Display for AssetType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for AssetType {
+    type Err = String;
+
+    /// Parses a built-in type, or a custom one with a registered category.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::from_name(name).ok_or_else(|| format!("Unknown asset type: '{name}'"))
+    }
+}
+
+/// The names of the built-in types, which are serialised as the variants of an enum.
+const BUILT_IN_NAMES: [&str; 6] = ["HairBack", "Armour", "Face", "Hair", "Accessory", "Token"];
+
+impl serde::Serialize for AssetType {
+    /// Serialises the type as a unit variant named after it, as it was before custom types, so
+    /// that formats such as RON write it as e.g. `Face` rather than `"Face"`.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let index = BUILT_IN_NAMES
+            .iter()
+            .position(|&name| name == self.name())
+            .unwrap_or(BUILT_IN_NAMES.len());
+        serializer.serialize_unit_variant("AssetType", index as u32, self.name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for AssetType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AssetTypeVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for AssetTypeVisitor {
+            type Value = AssetType;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("an asset type name")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                AssetType::define(name)
+                    .ok_or_else(|| E::custom(format!("Invalid asset type: '{name}'")))
+            }
+
+            fn visit_enum<A: serde::de::EnumAccess<'de>>(
+                self,
+                data: A,
+            ) -> Result<Self::Value, A::Error> {
+                use serde::de::VariantAccess as _;
+
+                let (asset_type, variant) = data.variant_seed(self)?;
+                variant.unit_variant()?;
+                Ok(asset_type)
+            }
+        }
+
+        /// Reads the name of the variant, which formats such as RON write as an identifier.
+        impl<'de> serde::de::DeserializeSeed<'de> for AssetTypeVisitor {
+            type Value = AssetType;
+
+            fn deserialize<D: serde::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                deserializer.deserialize_identifier(self)
+            }
+        }
+
+        deserializer.deserialize_enum("AssetType", &BUILT_IN_NAMES, AssetTypeVisitor)
     }
 }
 
 /// Represents a single loadable asset.
 ///
 /// Contains metadata about an asset, including its name, type, and file path.
-/// For hair assets, it may include a reference to a corresponding back part.
-/// Image data is loaded on demand.
+/// Assets of any type but a companion type may include a reference to a companion back part.
+/// Image data is loaded on demand, when the asset is first drawn.
 #[derive(Clone, serde::Deserialize, serde::Serialize, Eq, PartialEq, Default, Debug)]
 pub struct Asset {
     /// Form is `name_type`, e.g. `MyAsset_Face`.
@@ -58,8 +151,24 @@ pub struct Asset {
     pub path: PathBuf,
     pub back_part: Option<String>,
     pub asset_type: AssetType,
+    /// A hash of the asset's file contents, see `content_hash`.
+    ///
+    /// In a saved character this records the art the part was saved with, so it can be found again
+    /// after a rename. `None` for assets whose contents haven't been read.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Tags and credits from the asset's metadata sidecars, see `AssetMetadata`.
+    #[serde(default, skip_serializing_if = "AssetMetadata::is_empty")]
+    pub metadata: AssetMetadata,
+    /// The filename of the asset pack the asset was loaded from, or `None` for loose art files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
     #[serde(skip)]
     pub image_data: Option<Arc<RgbaImage>>,
+    /// The bytes of the asset's file, kept along with its image so that it can be embedded in a
+    /// bundle as it is, see `FeccBundle`.
+    #[serde(skip)]
+    pub file_bytes: Option<Arc<[u8]>>,
 }
 
 impl Asset {
@@ -75,56 +184,262 @@ impl Asset {
             path,
             back_part,
             asset_type,
+            content_hash: None,
+            metadata: AssetMetadata::default(),
+            pack: None,
             image_data: None,
+            file_bytes: None,
         }
     }
 
+    /// Computes a stable hash of an asset file's bytes, as a hex string.
+    ///
+    /// Uses 64-bit FNV-1a, which is stable across builds and platforms unlike `std`'s hashers.
+    pub fn content_hash(bytes: &[u8]) -> String {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+
+        let hash = bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        });
+        format!("{hash:016x}")
+    }
+
     /// Parses a filename to extract the asset's name and type.
     ///
-    /// Filenames are expected to be in the format `Name_Type`.
+    /// Filenames are expected to be in the format `Name_Type`, where `Type` has a registered
+    /// category.
     pub fn parse_filename(filename: &str) -> Result<(&str, AssetType), String> {
         let (name, asset_type_str) = filename
             .rsplit_once('_')
             .ok_or_else(|| format!("Filename '{filename}' does not contain '_' separator"))?;
 
-        let asset_type = match asset_type_str {
-            "Armour" => AssetType::Armour,
-            "Face" => AssetType::Face,
-            "Hair" => AssetType::Hair,
-            "Accessory" => AssetType::Accessory,
-            "Token" => AssetType::Token,
-            "HairBack" => AssetType::HairBack,
-            _ => {
-                return Err(format!("Unknown asset type in filename: {asset_type_str}"));
-            }
-        };
+        let asset_type = asset_type_str
+            .parse::<AssetType>()
+            .ok()
+            .filter(|asset_type| asset_type.is_registered())
+            .ok_or_else(|| format!("Unknown asset type in filename: {asset_type_str}"))?;
 
         Ok((name, asset_type))
     }
 
+    /// Returns the id of the companion part that an asset would be linked to, e.g. `Long_HairBack`
+    /// for `Long_Hair`, or `None` for companion parts themselves.
+    pub fn companion_id(name: &str, asset_type: AssetType) -> Option<String> {
+        asset_type
+            .companion_of()
+            .is_none()
+            .then(|| format!("{name}_{}", asset_type.companion_type()))
+    }
+
+    /// Returns whether the asset's name, tags or artist contain a lowercase search query.
+    pub fn matches_search(&self, query: &str) -> bool {
+        query.is_empty()
+            || self.name.to_lowercase().contains(query)
+            || self.metadata.matches_search(query)
+    }
+
+    /// Returns which shades of which colourables the asset's image uses, or `None` if its image
+    /// hasn't been loaded.
+    pub fn colour_usage(&self) -> Option<ColourUsage> {
+        self.image_data.as_deref().map(|image| {
+            ColourUsage::of_image(image, self.asset_type, self.metadata.uses_extended_keys())
+        })
+    }
+
+    /// Finds the companion part this asset is linked to in the libraries, if there is one.
+    pub fn find_companion<'a>(
+        &self,
+        asset_libraries: &'a HashMap<AssetType, IndexMap<String, Self>>,
+    ) -> Option<&'a Self> {
+        let id = self.back_part.as_ref()?;
+        asset_libraries
+            .get(&self.asset_type.companion_type())?
+            .get(id)
+    }
+
     /// Creates an `Asset` from a filename and image bytes.
     pub fn try_from_bytes(filename: &str, bytes: &[u8]) -> Result<Self, String> {
         let (name, asset_type) = Self::parse_filename(filename.trim_end_matches(".png"))?;
 
-        let back_part_id = if asset_type == AssetType::Hair {
-            Some(format!("{}Back.png", filename.trim_end_matches(".png")))
-        } else {
-            None
-        };
+        let back_part_id = Self::companion_id(name, asset_type);
 
         // Create a virtual path for the user asset
         let path = PathBuf::from(format!("user-asset://{filename}"));
 
         let mut asset = Self::new(name.to_owned(), path, back_part_id, asset_type);
+        asset.load_image(bytes)?;
 
+        Ok(asset)
+    }
+
+    /// Decodes the asset's image from the bytes of its file, and keeps them along with their hash.
+    pub fn load_image(&mut self, bytes: &[u8]) -> Result<(), String> {
         let image = image::load_from_memory(bytes)
             .map_err(|e| e.to_string())?
             .to_rgba8();
 
-        asset.image_data = Some(Arc::new(image));
+        self.content_hash = Some(Self::content_hash(bytes));
+        self.image_data = Some(Arc::new(image));
+        self.file_bytes = Some(Arc::from(bytes));
+        Ok(())
+    }
+}
+
+/// How the asset a saved character part refers to was found in a library.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AssetResolution<'a> {
+    /// An asset with the same contents, which may have been renamed since the part was saved.
+    Hash(&'a Asset),
+    /// An asset with the same id, whose contents may have `changed` since the part was saved.
+    Id {
+        asset: &'a Asset,
+        changed: bool,
+    },
+    Missing,
+}
 
-        Ok(asset)
+/// Why an asset was suggested as a substitute for one that is missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubstituteReason {
+    /// An asset of another type with the same contents.
+    SameContent,
+    /// An asset whose id only differs in letter case.
+    DifferentCasing,
+    /// An asset whose name is within the given edit distance.
+    SimilarName(usize),
+}
+
+/// A candidate replacement for an asset that can't be found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitute {
+    pub asset: Asset,
+    pub reason: SubstituteReason,
+}
+
+/// The most similarly named assets suggested as substitutes.
+const MAX_SIMILAR_NAMES: usize = 3;
+
+impl Asset {
+    /// Finds this asset in a library, by content hash first and then by id.
+    ///
+    /// An asset is only reported as `changed` if both it and this asset have a content hash.
+    pub fn resolve<'a>(&self, library: &'a IndexMap<String, Self>) -> AssetResolution<'a> {
+        let by_id = library.get(&self.id);
+
+        if let Some(hash) = &self.content_hash {
+            if let Some(asset) = by_id
+                && asset.content_hash.as_ref() == Some(hash)
+            {
+                return AssetResolution::Id {
+                    asset,
+                    changed: false,
+                };
+            }
+
+            if let Some(asset) = library
+                .values()
+                .find(|asset| asset.content_hash.as_ref() == Some(hash))
+            {
+                return AssetResolution::Hash(asset);
+            }
+        }
+
+        match by_id {
+            Some(asset) => AssetResolution::Id {
+                asset,
+                changed: self.content_hash.is_some()
+                    && asset.content_hash.is_some()
+                    && asset.content_hash != self.content_hash,
+            },
+            None => AssetResolution::Missing,
+        }
+    }
+
+    /// Suggests assets that could stand in for this one when it can't be resolved, best first.
+    ///
+    /// Assets with the same contents are looked for in every library, while differently cased and
+    /// similarly named assets are only looked for among assets of the same type.
+    pub fn find_substitutes(
+        &self,
+        asset_libraries: &HashMap<AssetType, IndexMap<String, Self>>,
+    ) -> Vec<Substitute> {
+        let mut substitutes: Vec<Substitute> = Vec::new();
+
+        if let Some(hash) = &self.content_hash {
+            for asset_type in AssetType::all() {
+                let Some(library) = asset_libraries.get(&asset_type) else {
+                    continue;
+                };
+                substitutes.extend(
+                    library
+                        .values()
+                        .filter(|asset| asset.content_hash.as_ref() == Some(hash))
+                        .map(|asset| Substitute {
+                            asset: asset.clone(),
+                            reason: SubstituteReason::SameContent,
+                        }),
+                );
+            }
+        }
+
+        let Some(library) = asset_libraries.get(&self.asset_type) else {
+            return substitutes;
+        };
+        let candidates = || {
+            library
+                .values()
+                .filter(|asset| asset.id != self.id)
+                .filter(|asset| !substitutes.iter().any(|s| s.asset.id == asset.id))
+        };
+
+        let casing: Vec<_> = candidates()
+            .filter(|asset| asset.id.eq_ignore_ascii_case(&self.id))
+            .map(|asset| Substitute {
+                asset: asset.clone(),
+                reason: SubstituteReason::DifferentCasing,
+            })
+            .collect();
+
+        let name = self.name.to_lowercase();
+        let max_distance = (name.chars().count() / 3).max(2);
+        let mut similar: Vec<_> = candidates()
+            .filter(|asset| !asset.id.eq_ignore_ascii_case(&self.id))
+            .map(|asset| (edit_distance(&name, &asset.name.to_lowercase()), asset))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .collect();
+        similar.sort_by_key(|&(distance, _)| distance);
+
+        substitutes.extend(casing);
+        substitutes.extend(
+            similar
+                .into_iter()
+                .take(MAX_SIMILAR_NAMES)
+                .map(|(distance, asset)| Substitute {
+                    asset: asset.clone(),
+                    reason: SubstituteReason::SimilarName(distance),
+                }),
+        );
+        substitutes
+    }
+}
+
+/// The Levenshtein distance between two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
     }
+
+    previous[b.len()]
 }
 
 impl TryFrom<&Path> for Asset {
@@ -138,17 +453,10 @@ impl TryFrom<&Path> for Asset {
 
         let (name, asset_type) = Self::parse_filename(filename)?;
 
-        let back_part_id = if asset_type == AssetType::Hair {
-            let hair_back_id = filename.to_owned() + "Back";
-            Some(hair_back_id)
-        } else {
-            None
-        };
-
         Ok(Self::new(
             name.to_owned(),
             path.to_path_buf(),
-            back_part_id,
+            Self::companion_id(name, asset_type),
             asset_type,
         ))
     }
@@ -159,6 +467,29 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_asset_type_serde() {
+        let outlines: HashMap<AssetType, u8> = HashMap::from([(AssetType::Face, 1)]);
+        assert_eq!(serde_json::to_string(&outlines).unwrap(), r#"{"Face":1}"#);
+        assert_eq!(
+            serde_json::from_str::<HashMap<AssetType, u8>>(r#"{"Face":1}"#).unwrap(),
+            outlines
+        );
+
+        // RON, as used by the GUI's stored state, writes built-in and custom types as identifiers.
+        assert_eq!(ron::to_string(&AssetType::Hair).unwrap(), "Hair");
+        assert_eq!(ron::from_str::<AssetType>("Hair").unwrap(), AssetType::Hair);
+        let cape = AssetType::define("TestSerdeCape").expect("Invalid name");
+        assert_eq!(
+            ron::from_str::<AssetType>(&ron::to_string(&cape).unwrap()).unwrap(),
+            cape
+        );
+        assert_eq!(
+            ron::from_str::<HashMap<AssetType, u8>>("{Face: 1}").unwrap(),
+            outlines
+        );
+    }
+
     #[test]
     fn test_parse_filename_valid() {
         assert_eq!(
@@ -205,6 +536,16 @@ mod tests {
 
         assert_eq!(asset.name, "Test");
         assert_eq!(asset.asset_type, AssetType::Face);
+        assert_eq!(asset.back_part, Some("Test_FaceBack".to_owned()));
+    }
+
+    #[test]
+    fn test_try_from_path_companion() {
+        let path = PathBuf::from("assets/Cape_ArmourBack.png");
+        let asset = Asset::try_from(path.as_path()).expect("Failed to create asset from path");
+
+        assert_eq!(asset.asset_type, AssetType::Armour.companion_type());
+        assert_eq!(asset.asset_type.companion_of(), Some(AssetType::Armour));
         assert_eq!(asset.back_part, None);
     }
 
@@ -243,6 +584,112 @@ mod tests {
         assert_eq!(asset.name, "Test");
         assert_eq!(asset.asset_type, AssetType::Face);
         assert!(asset.image_data.is_some());
+        assert_eq!(asset.content_hash, Some(Asset::content_hash(&bytes)));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(Asset::content_hash(b""), "cbf29ce484222325");
+        assert_eq!(Asset::content_hash(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(Asset::content_hash(b"ab"), Asset::content_hash(b"ba"));
+    }
+
+    fn hashed_asset(name: &str, hash: Option<&str>) -> Asset {
+        let mut asset = Asset::new(name.to_owned(), PathBuf::new(), None, AssetType::Face);
+        asset.content_hash = hash.map(str::to_owned);
+        asset
+    }
+
+    #[test]
+    fn test_resolve() {
+        let library: IndexMap<String, Asset> = [
+            hashed_asset("Renamed", Some("aaaa")),
+            hashed_asset("Edited", Some("cccc")),
+            hashed_asset("Unhashed", None),
+        ]
+        .into_iter()
+        .map(|asset| (asset.id.clone(), asset))
+        .collect();
+
+        assert_eq!(
+            hashed_asset("Original", Some("aaaa")).resolve(&library),
+            AssetResolution::Hash(&library["Renamed_Face"])
+        );
+        assert_eq!(
+            hashed_asset("Edited", Some("bbbb")).resolve(&library),
+            AssetResolution::Id {
+                asset: &library["Edited_Face"],
+                changed: true
+            }
+        );
+        assert_eq!(
+            hashed_asset("Edited", None).resolve(&library),
+            AssetResolution::Id {
+                asset: &library["Edited_Face"],
+                changed: false
+            }
+        );
+        assert_eq!(
+            hashed_asset("Unhashed", Some("dddd")).resolve(&library),
+            AssetResolution::Id {
+                asset: &library["Unhashed_Face"],
+                changed: false
+            }
+        );
+        assert_eq!(
+            hashed_asset("Gone", Some("eeee")).resolve(&library),
+            AssetResolution::Missing
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("marth", "marth"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_find_substitutes() {
+        let mut moved = hashed_asset("Moved", Some("aaaa"));
+        moved.asset_type = AssetType::Accessory;
+        moved.id = "Moved_Accessory".to_owned();
+
+        let asset_libraries: HashMap<AssetType, IndexMap<String, Asset>> = [
+            (
+                AssetType::Face,
+                [
+                    hashed_asset("marth", None),
+                    hashed_asset("Marht", None),
+                    hashed_asset("Ike", None),
+                ]
+                .into_iter()
+                .map(|asset| (asset.id.clone(), asset))
+                .collect(),
+            ),
+            (
+                AssetType::Accessory,
+                [(moved.id.clone(), moved.clone())].into_iter().collect(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let substitutes = hashed_asset("Marth", Some("aaaa")).find_substitutes(&asset_libraries);
+        let found: Vec<_> = substitutes
+            .iter()
+            .map(|s| (s.asset.id.as_str(), s.reason))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("Moved_Accessory", SubstituteReason::SameContent),
+                ("marth_Face", SubstituteReason::DifferentCasing),
+                ("Marht_Face", SubstituteReason::SimilarName(2)),
+            ]
+        );
     }
 
     #[test]
@@ -261,7 +708,7 @@ mod tests {
             Asset::try_from_bytes("Style_Hair.png", &bytes).expect("Failed to load from bytes");
         assert_eq!(asset.name, "Style");
         assert_eq!(asset.asset_type, AssetType::Hair);
-        assert_eq!(asset.back_part, Some("Style_HairBack.png".to_string()));
+        assert_eq!(asset.back_part, Some("Style_HairBack".to_string()));
     }
 
     #[test]
@@ -0,0 +1,510 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::asset::AssetType;
+use std::collections::BTreeSet;
+use std::sync::{LazyLock, Mutex, RwLock};
+
+/// The canvas that parts of a category are drawn on.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub enum Canvas {
+    Portrait,
+    Token,
+}
+
+impl Canvas {
+    /// The width and height, in pixels, of art drawn for this canvas.
+    pub const fn art_size(self) -> u32 {
+        match self {
+            Self::Portrait => 96,
+            Self::Token => 64,
+        }
+    }
+}
+
+/// Where a newly chosen part is placed on its canvas.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub enum Placement {
+    Centred,
+    /// Centred horizontally, with the bottom of the art along the bottom of the canvas.
+    BottomAligned,
+}
+
+/// The layout of red channel keys that art of a category is drawn with, see ART.md.
+///
+/// The keys of each scheme are defined by `recolour_schemes.csv`, see `recolour::scheme`.
+/// Serialised as its name, e.g. `"Face"`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum RecolourScheme {
+    /// Eye and beard, skin and accessory colours.
+    Face,
+    /// Hair, skin, metal, trim, cloth and leather colours.
+    Armour,
+    /// A user-defined scheme.
+    Custom(&'static str),
+}
+
+impl RecolourScheme {
+    /// Returns the scheme with the given name, or `None` if it isn't alphanumeric.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Face" => Self::Face,
+            "Armour" => Self::Armour,
+            _ if is_valid_name(name) => Self::Custom(intern(name)),
+            _ => return None,
+        })
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Face => "Face",
+            Self::Armour => "Armour",
+            Self::Custom(name) => name,
+        }
+    }
+}
+
+impl std::fmt::Display for RecolourScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for RecolourScheme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::from_name(name).ok_or_else(|| format!("Invalid recolour scheme: '{name}'"))
+    }
+}
+
+impl serde::Serialize for RecolourScheme {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RecolourScheme {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A kind of asset, such as `Face` or a user-defined `Cape`, and how its parts are handled.
+///
+/// The built-in categories can be changed, and others added, by `asset_categories.csv`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+pub struct AssetCategory {
+    /// The suffix of the category's filenames, e.g. `Cape` for `Red_Cape.png`.
+    #[serde(rename = "name")]
+    pub asset_type: AssetType,
+    pub canvas: Canvas,
+    /// Parts of categories with a lower order are drawn underneath those with a higher one.
+    pub layer_order: i32,
+    pub placement: Placement,
+    pub recolour_scheme: RecolourScheme,
+    /// Whether the category is included when randomising a whole character.
+    pub randomise: bool,
+}
+
+/// A row of `asset_categories.csv`, read before the category's name is interned.
+#[derive(serde::Deserialize)]
+struct CategoryRecord {
+    name: String,
+    canvas: Canvas,
+    layer_order: i32,
+    placement: Placement,
+    recolour_scheme: RecolourScheme,
+    randomise: bool,
+}
+
+/// The registered categories, which start as the built-in ones.
+static CATEGORIES: LazyLock<RwLock<Vec<AssetCategory>>> =
+    LazyLock::new(|| RwLock::new(AssetCategory::built_in()));
+
+/// The names of user-defined categories and recolour schemes, kept for the life of the program so
+/// `AssetType` and `RecolourScheme` can stay `Copy`.
+static CUSTOM_NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Returns whether a name can be used for a user-defined category or recolour scheme.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Returns a copy of a name that lives for the rest of the program, reusing any earlier copy.
+fn intern(name: &str) -> &'static str {
+    let mut names = CUSTOM_NAMES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    match names.get(name) {
+        Some(&name) => name,
+        None => {
+            let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+            names.insert(name);
+            name
+        }
+    }
+}
+
+impl AssetCategory {
+    const fn new(
+        asset_type: AssetType,
+        canvas: Canvas,
+        layer_order: i32,
+        placement: Placement,
+        recolour_scheme: RecolourScheme,
+        randomise: bool,
+    ) -> Self {
+        Self {
+            asset_type,
+            canvas,
+            layer_order,
+            placement,
+            recolour_scheme,
+            randomise,
+        }
+    }
+
+    /// The categories FECC ships with.
+    pub fn built_in() -> Vec<Self> {
+        use Canvas::{Portrait, Token};
+        use Placement::{BottomAligned, Centred};
+        use RecolourScheme::{Armour, Face};
+
+        vec![
+            Self::new(AssetType::HairBack, Portrait, 0, Centred, Armour, false),
+            Self::new(AssetType::Armour, Portrait, 10, BottomAligned, Armour, true),
+            Self::new(AssetType::Face, Portrait, 20, Centred, Face, true),
+            Self::new(AssetType::Hair, Portrait, 30, Centred, Armour, true),
+            Self::new(AssetType::Accessory, Portrait, 40, Centred, Face, false),
+            Self::new(AssetType::Token, Token, 50, Centred, Armour, true),
+        ]
+    }
+
+    /// Returns every registered category, in layer order.
+    ///
+    /// Includes a companion category for each category that isn't given one explicitly.
+    pub fn all() -> Vec<Self> {
+        // Cloned so the registry isn't locked while the companion types are looked up.
+        let registered = Self::registered().clone();
+        let is_registered = |name: &str| {
+            registered
+                .iter()
+                .any(|category| category.asset_type.name() == name)
+        };
+
+        let mut categories = registered.clone();
+        for category in &registered {
+            let name = category.asset_type.name();
+            let is_companion = name.strip_suffix("Back").is_some_and(is_registered);
+            if !is_companion && !is_registered(&format!("{name}Back")) {
+                categories.push(category.companion(category.asset_type.companion_type()));
+            }
+        }
+        categories.sort_by_key(|category| category.layer_order);
+        categories
+    }
+
+    /// Adds categories to the registry, replacing any registered under the same name.
+    pub fn register(categories: impl IntoIterator<Item = Self>) {
+        let mut registered = CATEGORIES
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for category in categories {
+            match registered
+                .iter_mut()
+                .find(|existing| existing.asset_type == category.asset_type)
+            {
+                Some(existing) => *existing = category,
+                None => registered.push(category),
+            }
+        }
+    }
+
+    /// Returns the registered category of an asset type.
+    ///
+    /// The companion type of a registered category, e.g. `ArmourBack`, is always registered, see
+    /// `AssetType::companion_type`.
+    pub fn get(asset_type: AssetType) -> Option<Self> {
+        let registered = Self::registered();
+        let find = |name: &str| {
+            registered
+                .iter()
+                .find(|category| category.asset_type.name() == name)
+                .copied()
+        };
+
+        find(asset_type.name()).or_else(|| {
+            find(asset_type.name().strip_suffix("Back")?).map(|parent| parent.companion(asset_type))
+        })
+    }
+
+    fn registered() -> std::sync::RwLockReadGuard<'static, Vec<Self>> {
+        CATEGORIES
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// The category of companion parts of this category's assets, drawn at the back of the stack.
+    ///
+    /// Takes the companion type rather than looking it up, so it can be used with the registry locked.
+    fn companion(self, asset_type: AssetType) -> Self {
+        Self {
+            asset_type,
+            layer_order: i32::MIN,
+            randomise: false,
+            ..self
+        }
+    }
+
+    /// Parses categories from CSV text with a header row, e.g.
+    /// `name,canvas,layer_order,placement,recolour_scheme,randomise`.
+    ///
+    /// New names are only interned once every row has parsed, as the categories are expected to be
+    /// registered.
+    pub fn parse_csv(text: &str) -> Result<Vec<Self>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(text.as_bytes());
+
+        let records: Vec<CategoryRecord> = reader
+            .deserialize()
+            .map(|record| {
+                let record: CategoryRecord = record.map_err(|e| e.to_string())?;
+                if !is_valid_name(&record.name) {
+                    return Err(format!("Invalid asset type: '{}'", record.name));
+                }
+                Ok(record)
+            })
+            .enumerate()
+            .map(|(i, record)| {
+                record.map_err(|e| format!("Invalid category on row {}: {e}", i + 1))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                Some(Self::new(
+                    AssetType::define(&record.name)?,
+                    record.canvas,
+                    record.layer_order,
+                    record.placement,
+                    record.recolour_scheme,
+                    record.randomise,
+                ))
+            })
+            .collect())
+    }
+}
+
+impl AssetType {
+    /// Returns how parts of this type are handled.
+    ///
+    /// Types without a registered category are drawn centred on the portrait, above the others.
+    pub fn category(self) -> AssetCategory {
+        AssetCategory::get(self).unwrap_or(AssetCategory {
+            asset_type: self,
+            canvas: Canvas::Portrait,
+            layer_order: i32::MAX,
+            placement: Placement::Centred,
+            recolour_scheme: RecolourScheme::Armour,
+            randomise: false,
+        })
+    }
+
+    /// Returns whether this type has a registered category, i.e. whether its art can be loaded.
+    pub fn is_registered(self) -> bool {
+        AssetCategory::get(self).is_some()
+    }
+
+    /// Returns the types of every registered category, in layer order.
+    pub fn all() -> impl Iterator<Item = Self> {
+        AssetCategory::all()
+            .into_iter()
+            .map(|category| category.asset_type)
+    }
+
+    /// Returns the types of the registered categories drawn on the given canvas, in layer order.
+    pub fn on_canvas(canvas: Canvas) -> Vec<Self> {
+        AssetCategory::all()
+            .into_iter()
+            .filter(|category| category.canvas == canvas)
+            .map(|category| category.asset_type)
+            .collect()
+    }
+
+    /// Returns the types of the registered categories included when randomising a whole
+    /// character, in layer order.
+    pub fn randomised() -> Vec<Self> {
+        AssetCategory::all()
+            .into_iter()
+            .filter(|category| category.randomise)
+            .map(|category| category.asset_type)
+            .collect()
+    }
+
+    /// Returns the type of the companion parts of assets of this type, e.g. `HairBack` for `Hair`.
+    ///
+    /// An asset `X_Type` is linked to the asset `X_TypeBack`, if there is one. Companion types
+    /// don't have companions of their own, so are returned as they are.
+    pub fn companion_type(self) -> Self {
+        if self.companion_of().is_some() {
+            return self;
+        }
+        Self::from_name(&format!("{}Back", self.name())).unwrap_or(self)
+    }
+
+    /// Returns the type that this is the companion type of, e.g. `Hair` for `HairBack`.
+    pub fn companion_of(self) -> Option<Self> {
+        let parent = self.name().strip_suffix("Back")?;
+        AssetCategory::registered()
+            .iter()
+            .map(|category| category.asset_type)
+            .find(|asset_type| asset_type.name() == parent)
+    }
+
+    /// Returns the user-defined type with the given name, if it has a registered category.
+    ///
+    /// Companion types, e.g. `CapeBack` for a registered `Cape`, are interned on first use.
+    pub(crate) fn custom(name: &str) -> Option<Self> {
+        let registered = AssetCategory::registered();
+        let find = |name: &str| {
+            registered
+                .iter()
+                .map(|category| category.asset_type)
+                .find(|asset_type| asset_type.name() == name)
+        };
+
+        find(name).or_else(|| {
+            find(name.strip_suffix("Back")?)?;
+            Some(Self::Custom(intern(name)))
+        })
+    }
+
+    /// Returns the type with the given name, interning it if it isn't built in or registered, or
+    /// `None` if it isn't alphanumeric.
+    ///
+    /// Only for names that were defined by a category, such as those in category files and saved
+    /// characters, so that names in filenames and the like aren't kept for the life of the program.
+    pub(crate) fn define(name: &str) -> Option<Self> {
+        is_valid_name(name)
+            .then(|| Self::from_name(name).unwrap_or_else(|| Self::Custom(intern(name))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::Asset;
+
+    #[test]
+    fn test_built_in_categories() {
+        assert_eq!(
+            AssetType::Armour.category().placement,
+            Placement::BottomAligned
+        );
+        assert_eq!(AssetType::Token.category().canvas, Canvas::Token);
+        assert_eq!(
+            AssetType::Accessory.category().recolour_scheme,
+            RecolourScheme::Face
+        );
+        // Other tests may register categories of their own, so only the built-in ones are checked.
+        let portrait: Vec<AssetType> = AssetType::on_canvas(Canvas::Portrait)
+            .into_iter()
+            .filter(|asset_type| !matches!(asset_type, AssetType::Custom(_)))
+            .collect();
+        assert_eq!(
+            portrait,
+            vec![
+                AssetType::HairBack,
+                AssetType::Armour,
+                AssetType::Face,
+                AssetType::Hair,
+                AssetType::Accessory,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_companion_categories() {
+        assert_eq!(AssetType::Hair.companion_type(), AssetType::HairBack);
+        assert_eq!(AssetType::HairBack.companion_of(), Some(AssetType::Hair));
+        assert_eq!(AssetType::HairBack.companion_type(), AssetType::HairBack);
+        assert!(
+            !CUSTOM_NAMES
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .contains("HairBackBack")
+        );
+        assert_eq!(AssetType::HairBack.category().layer_order, 0);
+
+        let token_back = AssetType::Token.companion_type();
+        assert_eq!(token_back.to_string(), "TokenBack");
+        assert_eq!(token_back.companion_of(), Some(AssetType::Token));
+        assert_eq!(token_back.category().canvas, Canvas::Token);
+        assert_eq!(token_back.category().layer_order, i32::MIN);
+        assert!(AssetType::on_canvas(Canvas::Token).contains(&token_back));
+        assert!(!AssetType::get_selectable_part_types().any(|t| t == token_back));
+        assert_eq!(AssetType::Face.companion_of(), None);
+    }
+
+    #[test]
+    fn test_register_parsed_categories() {
+        let csv = "name,canvas,layer_order,placement,recolour_scheme,randomise\n\
+                   TestCape, Portrait, 5, BottomAligned, Armour, false\n\
+                   TestBadge, Token, 60, Centred, Face, true\n";
+        let categories = AssetCategory::parse_csv(csv).expect("Failed to parse categories");
+        assert_eq!(categories.len(), 2);
+
+        let cape = categories[0].asset_type;
+        assert_eq!(cape.to_string(), "TestCape");
+        assert!(!cape.is_registered());
+        assert_eq!(cape.category().layer_order, i32::MAX);
+        assert!("TestCape".parse::<AssetType>().is_err());
+        assert!(Asset::parse_filename("Red_TestCape").is_err());
+
+        AssetCategory::register(categories);
+        assert!(cape.is_registered());
+        assert_eq!("TestCape".parse(), Ok(cape));
+        assert_eq!(Asset::parse_filename("Red_TestCape"), Ok(("Red", cape)));
+        assert_eq!(cape.category().placement, Placement::BottomAligned);
+        assert_eq!(cape.to_string(), "TestCape");
+
+        let portrait = AssetType::on_canvas(Canvas::Portrait);
+        let cape_index = portrait.iter().position(|&t| t == cape).unwrap();
+        let armour_index = portrait
+            .iter()
+            .position(|&t| t == AssetType::Armour)
+            .unwrap();
+        assert!(cape_index < armour_index);
+        assert!(!portrait.iter().any(|t| t.to_string() == "TestBadge"));
+    }
+
+    #[test]
+    fn test_unknown_types_are_not_interned() {
+        assert!(Asset::parse_filename("Red_TestUnknown").is_err());
+        assert!(Asset::parse_filename("Red_TestUnknownBack").is_err());
+        let names = CUSTOM_NAMES
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        assert!(!names.contains("TestUnknown"));
+        assert!(!names.contains("TestUnknownBack"));
+    }
+
+    #[test]
+    fn test_shipped_categories_match_built_in() {
+        let csv = include_str!("../../../assets/csv/asset_categories.csv");
+        assert_eq!(
+            AssetCategory::parse_csv(csv).expect("Failed to parse categories"),
+            AssetCategory::built_in()
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_invalid() {
+        let csv = "name,canvas,layer_order,placement,recolour_scheme,randomise\n\
+                   Cape,Sideways,5,Centred,Armour,false\n";
+        assert!(AssetCategory::parse_csv(csv).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use serde::{Deserialize, Serialize};
+
+/// Optional information about an asset, read from a JSON sidecar file.
+///
+/// A sidecar named after an asset, e.g. `Aias_Face.json`, describes that asset, while a
+/// `metadata.json` describes every asset in its folder. Fields left out of an asset's own sidecar
+/// are taken from its folder's, and the tags of both are combined.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AssetMetadata {
+    /// Free-form tags such as `female`, `mage` or `long`, used to search and filter assets.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub licence: Option<String>,
+    /// The game the asset was taken or adapted from, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_game: Option<String>,
+    /// Whether the asset's green channel is a second key, see `recolour::scheme::Key`. Left out
+    /// for standard art, whose green values don't matter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extended_keys: Option<bool>,
+}
+
+impl AssetMetadata {
+    /// The name of the sidecar that describes every asset in its folder.
+    pub const FOLDER_FILE: &str = "metadata.json";
+
+    /// Parses the contents of a sidecar file.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid asset metadata: {e}"))
+    }
+
+    /// Fills in the fields this metadata leaves out from its folder's, and adds the folder's tags.
+    #[must_use]
+    pub fn merged_with(mut self, folder: &Self) -> Self {
+        for tag in &folder.tags {
+            if !self.has_tag(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        self.artist = self.artist.or_else(|| folder.artist.clone());
+        self.licence = self.licence.or_else(|| folder.licence.clone());
+        self.source_game = self.source_game.or_else(|| folder.source_game.clone());
+        self.extended_keys = self.extended_keys.or(folder.extended_keys);
+        self
+    }
+
+    /// Returns whether the asset is extended art, which uses its green channel as a second key.
+    pub fn uses_extended_keys(&self) -> bool {
+        self.extended_keys == Some(true)
+    }
+
+    /// Returns whether the asset has the given tag, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Returns whether any tag or the artist contains a lowercase search query.
+    pub fn matches_search(&self, query: &str) -> bool {
+        self.tags
+            .iter()
+            .chain(&self.artist)
+            .any(|field| field.to_lowercase().contains(query))
+    }
+
+    /// Returns whether there is anything to credit the asset with.
+    pub fn has_credit(&self) -> bool {
+        self.artist.is_some() || self.licence.is_some() || self.source_game.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && !self.has_credit() && self.extended_keys.is_none()
+    }
+
+    /// Splits a comma-separated list of tags, as typed by the user.
+    pub fn parse_tags(text: &str) -> Vec<String> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merged_with_folder() {
+        let asset =
+            AssetMetadata::from_json(r#"{"tags": ["Mage", "female"], "artist": "Someone"}"#)
+                .expect("Failed to parse asset metadata");
+        let folder = AssetMetadata::from_json(
+            r#"{"tags": ["mage", "fe7"], "artist": "Iscaneus", "licence": "CC BY 4.0"}"#,
+        )
+        .expect("Failed to parse folder metadata");
+
+        let merged = asset.merged_with(&folder);
+
+        assert_eq!(merged.tags, ["Mage", "female", "fe7"]);
+        assert_eq!(merged.artist.as_deref(), Some("Someone"));
+        assert_eq!(merged.licence.as_deref(), Some("CC BY 4.0"));
+        assert_eq!(merged.source_game, None);
+        assert!(merged.matches_search("some"));
+        assert!(merged.matches_search("fe7"));
+        assert!(!merged.matches_search("knight"));
+    }
+
+    #[test]
+    fn test_extended_keys() {
+        let folder = AssetMetadata::from_json(r#"{"extended_keys": true}"#)
+            .expect("Failed to parse folder metadata");
+        assert!(!folder.is_empty());
+        assert!(
+            AssetMetadata::default()
+                .merged_with(&folder)
+                .uses_extended_keys()
+        );
+
+        // An asset can opt out of its folder's extended keys.
+        let asset = AssetMetadata::from_json(r#"{"extended_keys": false}"#)
+            .expect("Failed to parse asset metadata");
+        assert!(!asset.merged_with(&folder).uses_extended_keys());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            AssetMetadata::parse_tags(" mage, female ,,"),
+            ["mage", "female"]
+        );
+        assert!(AssetMetadata::parse_tags("  ").is_empty());
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        assert!(AssetMetadata::from_json(r#"{"tags": "mage"}"#).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::asset::Asset;
+use crate::export::credits;
+use crate::fecc_file::FeccFile;
+use std::io::{Cursor, Read as _, Write as _};
+use std::path::PathBuf;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// The name of the entry holding the character JSON within a bundle.
+const CHARACTER_ENTRY: &str = "character.fecc";
+
+/// The name of the entry listing the credits for the bundled art, if it has any, see
+/// `export::credits`.
+const CREDITS_ENTRY: &str = "CREDITS.txt";
+
+/// The directory within a bundle that the art of each referenced part is stored in.
+const ART_DIR: &str = "art";
+
+/// Every zip file starts with a local file header signature.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// A `.fecc` file together with the art its character uses.
+///
+/// Bundles are zip archives holding `character.fecc` and an `art/<id>.png` for each asset, so a
+/// character can be shared with someone who doesn't have the same art installed, along with a
+/// `CREDITS.txt` for that art.
+pub struct FeccBundle {
+    pub file: FeccFile,
+    /// The assets embedded in the bundle, with their image data and file bytes loaded.
+    pub assets: Vec<Asset>,
+}
+
+impl FeccBundle {
+    /// Creates a bundle from a `.fecc` file, embedding the art of every layer of its character.
+    ///
+    /// Fails if the art of any layer has not been loaded, see `Asset::load_image`.
+    pub fn new(file: FeccFile) -> Result<Self, String> {
+        let mut assets: Vec<Asset> = Vec::new();
+        for layer in &file.character.layers {
+            let asset = &layer.part.asset;
+            if asset.file_bytes.is_none() {
+                return Err(format!("Art for '{}' is not loaded", asset.id));
+            }
+            if !assets.iter().any(|a| a.id == asset.id) {
+                assets.push(asset.clone());
+            }
+        }
+
+        Ok(Self { file, assets })
+    }
+
+    /// Serialises the bundle to the bytes of a zip archive.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+
+        writer
+            .start_file(CHARACTER_ENTRY, options)
+            .map_err(|e| e.to_string())?;
+        writer
+            .write_all(self.file.to_json()?.as_bytes())
+            .map_err(|e| e.to_string())?;
+
+        if let Some(credits) = credits(&self.assets) {
+            writer
+                .start_file(CREDITS_ENTRY, options)
+                .map_err(|e| e.to_string())?;
+            writer
+                .write_all(credits.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+
+        // The art is stored as it was loaded, so it has the same content hash when the bundle is.
+        for asset in &self.assets {
+            let png = asset
+                .file_bytes
+                .as_ref()
+                .ok_or_else(|| format!("Art for '{}' is not loaded", asset.id))?;
+
+            writer
+                .start_file(format!("{ART_DIR}/{}.png", asset.id), options)
+                .map_err(|e| e.to_string())?;
+            writer.write_all(png).map_err(|e| e.to_string())?;
+        }
+
+        Ok(writer.finish().map_err(|e| e.to_string())?.into_inner())
+    }
+
+    /// Loads either a bundle or a plain `.fecc` file, which yields a bundle with no assets.
+    ///
+    /// Only the art of layers the character actually uses is read from a bundle; the returned
+    /// assets keep the metadata saved in the character and have a virtual `user-asset://` path.
+    /// The character's parts are given the content hashes of the bundled art, so that it is used
+    /// in place of any other art with the same id, see `register_bundled_assets`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.starts_with(ZIP_MAGIC) {
+            let json = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+            return Ok(Self {
+                file: FeccFile::from_json(json)?,
+                assets: Vec::new(),
+            });
+        }
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+
+        let mut json = String::new();
+        archive
+            .by_name(CHARACTER_ENTRY)
+            .map_err(|e| format!("Bundle has no {CHARACTER_ENTRY}: {e}"))?
+            .read_to_string(&mut json)
+            .map_err(|e| e.to_string())?;
+        let mut file = FeccFile::from_json(&json)?;
+
+        let mut assets: Vec<Asset> = Vec::new();
+        for part in file.character.layers.iter().map(|layer| &layer.part) {
+            if assets.iter().any(|a| a.id == part.asset.id) {
+                continue;
+            }
+
+            let entry_name = format!("{ART_DIR}/{}.png", part.asset.id);
+            let Ok(mut entry) = archive.by_name(&entry_name) else {
+                log::warn!("Bundle has no art for '{}'", part.asset.id);
+                continue;
+            };
+
+            let mut png = Vec::new();
+            entry.read_to_end(&mut png).map_err(|e| e.to_string())?;
+
+            let mut asset = part.asset.clone();
+            asset.path = PathBuf::from(format!("user-asset://{}.png", asset.id));
+            asset
+                .load_image(&png)
+                .map_err(|e| format!("Failed to decode {entry_name}: {e}"))?;
+            assets.push(asset);
+        }
+
+        for part in file
+            .character
+            .layers
+            .iter_mut()
+            .map(|layer| &mut layer.part)
+        {
+            if let Some(asset) = assets.iter().find(|a| a.id == part.asset.id) {
+                part.asset.content_hash.clone_from(&asset.content_hash);
+            }
+        }
+
+        Ok(Self { file, assets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetType;
+    use crate::character::{Character, CharacterPart};
+    use crate::fecc_file::CoordinateSpace;
+    use crate::types::Point;
+    use image::RgbaImage;
+
+    fn test_asset(name: &str, asset_type: AssetType, pixel: [u8; 4]) -> Asset {
+        let mut asset = Asset::new(
+            name.to_owned(),
+            PathBuf::from(format!("art/{name}_{asset_type}.png")),
+            None,
+            asset_type,
+        );
+        let mut image = RgbaImage::new(2, 2);
+        image.put_pixel(1, 1, image::Rgba(pixel));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("Failed to encode PNG");
+        asset.load_image(&png).expect("Failed to load PNG");
+        if asset_type == AssetType::Face {
+            asset.metadata.artist = Some("Iscaneus".to_owned());
+        }
+        asset
+    }
+
+    fn test_file() -> FeccFile {
+        let mut character = Character::default();
+        for asset in [
+            test_asset("Shared", AssetType::Face, [10, 20, 30, 255]),
+            test_asset("Shared", AssetType::HairBack, [40, 50, 60, 128]),
+        ] {
+            let asset_type = asset.asset_type;
+            character.set_character_part(
+                &asset_type,
+                CharacterPart {
+                    asset,
+                    position: Point::new(0.5, 0.5),
+                    scale: 0.01,
+                    rotation: 0.0,
+                    flipped: false,
+                },
+            );
+        }
+        FeccFile::new(character, CoordinateSpace::Normalised, "test")
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let mut bundle = FeccBundle::new(test_file()).expect("Failed to create bundle");
+        // Parts saved before their art changed are pointed at the bundled art when loaded.
+        for layer in &mut bundle.file.character.layers {
+            layer.part.asset.content_hash = Some("stale".to_owned());
+        }
+        let bytes = bundle.to_bytes().expect("Failed to write bundle");
+        let loaded = FeccBundle::from_bytes(&bytes).expect("Failed to read bundle");
+
+        let mut archive = ZipArchive::new(Cursor::new(&bytes)).expect("Failed to open bundle");
+        let mut credits = String::new();
+        archive
+            .by_name(CREDITS_ENTRY)
+            .expect("Bundle has no credits")
+            .read_to_string(&mut credits)
+            .expect("Failed to read credits");
+        assert_eq!(credits, "Shared (Face): art by Iscaneus\n");
+
+        assert_eq!(loaded.assets.len(), 2);
+        assert!(
+            loaded
+                .assets
+                .iter()
+                .any(|a| a.metadata.artist.as_deref() == Some("Iscaneus"))
+        );
+        for original in &bundle.assets {
+            let asset = loaded
+                .assets
+                .iter()
+                .find(|a| a.id == original.id)
+                .expect("Asset missing from bundle");
+            assert_eq!(asset.asset_type, original.asset_type);
+            assert_eq!(
+                asset.path,
+                PathBuf::from(format!("user-asset://{}.png", original.id))
+            );
+            assert_eq!(asset.image_data, original.image_data);
+            assert_eq!(asset.file_bytes, original.file_bytes);
+            assert_eq!(asset.content_hash, original.content_hash);
+        }
+        for part in loaded.file.character.layers.iter().map(|layer| &layer.part) {
+            let asset = loaded.assets.iter().find(|a| a.id == part.asset.id);
+            assert_eq!(
+                part.asset.content_hash,
+                asset.and_then(|a| a.content_hash.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_bundle_requires_loaded_art() {
+        let mut file = test_file();
+        let mut face = file
+            .character
+            .get_character_part(&AssetType::Face)
+            .expect("Face missing");
+        face.asset.file_bytes = None;
+        file.character.set_character_part(&AssetType::Face, face);
+
+        assert!(FeccBundle::new(file).is_err());
+    }
+
+    #[test]
+    fn test_plain_fecc_loads_without_assets() {
+        let json = test_file().to_json().expect("Failed to serialise");
+        let loaded = FeccBundle::from_bytes(json.as_bytes()).expect("Failed to load");
+
+        assert!(loaded.assets.is_empty());
+        assert!(
+            loaded
+                .file
+                .character
+                .get_character_part(&AssetType::Face)
+                .is_some()
+        );
+    }
+}
@@ -1,14 +1,22 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
-use crate::asset::{Asset, AssetType};
+use crate::asset::category::{Canvas, Placement};
+use crate::asset::{Asset, AssetResolution, AssetType, Substitute};
 use crate::character::Colourable::{
-    Accessory, Cloth, EyeAndBeard, Hair, Leather, Metal, Skin, Trim,
+    Accessory, Cape, Cloth, Cloth2, EyeAndBeard, Gem, Hair, Leather, Metal, Metal2, Skin, Trim,
 };
-use crate::extensions::rgba::AdjustBrightness as _;
+use crate::extensions::rgba::ColourRamp;
+use crate::recolour::scheme::KeySlot;
 use crate::types::{Point, Rgba};
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use strum::IntoEnumIterator as _;
 use strum_macros::{Display, EnumIter};
 
+pub mod history;
+
 /// Represents a distinct, colourable area of a character asset.
+///
+/// `Cloth2`, `Metal2`, `Gem` and `Cape` are only used by extended art, see `recolour::scheme::Key`.
 #[derive(
     Debug,
     PartialEq,
@@ -33,6 +41,12 @@ pub enum Colourable {
     Cloth,
     Leather,
     Accessory,
+    #[strum(to_string = "Cloth 2")]
+    Cloth2,
+    #[strum(to_string = "Metal 2")]
+    Metal2,
+    Gem,
+    Cape,
     Outline,
 }
 
@@ -82,7 +96,7 @@ impl ColourPalette {
     }
 }
 
-#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct CharacterPart {
     pub position: Point,
     pub scale: f32,
@@ -92,7 +106,53 @@ pub struct CharacterPart {
     pub asset: Asset,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default)]
+impl CharacterPart {
+    /// Creates an unrotated part for an asset, placed on a canvas of the given size according to
+    /// its category, at the largest whole scale that fits.
+    pub fn placed(asset: Asset, canvas_size: Point) -> Self {
+        let art_size = asset.asset_type.category().canvas.art_size() as f32;
+        let scale = (canvas_size.y / art_size).floor().max(1.0);
+
+        let mut position = Point::new(canvas_size.x / 2.0, canvas_size.y / 2.0);
+        if asset.asset_type.category().placement == Placement::BottomAligned {
+            position.y = canvas_size.y - (art_size * scale / 2.0);
+        }
+
+        Self {
+            position,
+            scale,
+            rotation: 0.0,
+            flipped: false,
+            asset,
+        }
+    }
+}
+
+/// A single layer of a character: a placed asset and how it is drawn.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Layer {
+    /// The kind of part this layer is, which determines how it is recoloured and outlined.
+    pub asset_type: AssetType,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    pub part: CharacterPart,
+}
+
+const fn default_visible() -> bool {
+    true
+}
+
+impl Layer {
+    pub fn new(asset_type: AssetType, part: CharacterPart) -> Self {
+        Self {
+            asset_type,
+            visible: true,
+            part,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default, PartialEq, Eq)]
 #[serde(default)]
 pub struct CharacterPartColours {
     pub lighter: Rgba,
@@ -101,6 +161,8 @@ pub struct CharacterPartColours {
     pub darker_darker: Rgba,
     pub darker_darker_darker: Rgba,
     pub base: Rgba,
+    /// How the other shades are derived from the base colour.
+    pub ramp: ColourRamp,
 }
 
 impl CharacterPartColours {
@@ -109,24 +171,109 @@ impl CharacterPartColours {
             base: *colour,
             ..Default::default()
         };
-        character_part_colours.derive_all_colours();
+        character_part_colours.derive_all_colours(false);
         character_part_colours
     }
 
-    pub fn derive_all_colours(&mut self) {
-        self.lighter = self.base.brighter();
+    /// Derives the shades from the base colour, snapping them all in GBA colour mode, see
+    /// `Character::gba_colours`.
+    pub fn derive_all_colours(&mut self, gba_colours: bool) {
+        if gba_colours {
+            self.base = self.base.to_gba();
+        }
+        self.lighter = self.ramp.lighter(&self.base);
         self.neutral = self.base;
-        self.darker = self.base.darker();
-        self.darker_darker = self.base.darker().darker();
-        self.darker_darker_darker = self.base.darker().darker().darker();
+        self.darker = self.ramp.darker(&self.base);
+        self.darker_darker = self.ramp.darker(&self.darker);
+        self.darker_darker_darker = self.ramp.darker(&self.darker_darker);
+        if gba_colours {
+            self.snap_to_gba();
+        }
     }
 
-    pub fn set(&mut self, colour: Rgba) {
+    pub fn set(&mut self, colour: Rgba, gba_colours: bool) {
         self.base = colour;
-        self.derive_all_colours();
+        self.derive_all_colours(gba_colours);
+    }
+
+    /// Changes how the shades are derived, and derives them again.
+    pub fn set_ramp(&mut self, ramp: ColourRamp, gba_colours: bool) {
+        self.ramp = ramp;
+        self.derive_all_colours(gba_colours);
+    }
+
+    pub fn shade(&self, shade: Shade) -> Rgba {
+        match shade {
+            Shade::Lighter => self.lighter,
+            Shade::Neutral => self.neutral,
+            Shade::Darker => self.darker,
+            Shade::DarkerDarker => self.darker_darker,
+            Shade::DarkerDarkerDarker => self.darker_darker_darker,
+        }
+    }
+
+    /// Sets a single shade, snapping it in GBA colour mode.
+    pub fn set_shade(&mut self, shade: Shade, colour: Rgba, gba_colours: bool) {
+        *self.shade_mut(shade) = if gba_colours { colour.to_gba() } else { colour };
+    }
+
+    fn snap_to_gba(&mut self) {
+        self.base = self.base.to_gba();
+        for shade in Shade::iter() {
+            let colour = self.shade_mut(shade);
+            *colour = colour.to_gba();
+        }
+    }
+
+    pub fn shade_mut(&mut self, shade: Shade) -> &mut Rgba {
+        match shade {
+            Shade::Lighter => &mut self.lighter,
+            Shade::Neutral => &mut self.neutral,
+            Shade::Darker => &mut self.darker,
+            Shade::DarkerDarker => &mut self.darker_darker,
+            Shade::DarkerDarkerDarker => &mut self.darker_darker_darker,
+        }
+    }
+}
+
+/// One of the shades of a colourable, from lightest to darkest. Only skin uses the darkest two.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    Clone,
+    Copy,
+    EnumIter,
+    Ord,
+    PartialOrd,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+pub enum Shade {
+    Lighter,
+    Neutral,
+    Darker,
+    DarkerDarker,
+    DarkerDarkerDarker,
+}
+
+impl Shade {
+    /// Describes the shade as ART.md does.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Lighter => "light",
+            Self::Neutral => "base",
+            Self::Darker => "dark",
+            Self::DarkerDarker => "darker",
+            Self::DarkerDarkerDarker => "darkest",
+        }
     }
 }
 
+/// The outline colour parts are drawn with until the user picks another.
+const DEFAULT_OUTLINE_COLOUR: Rgba = Rgba::new(56, 32, 64, 255);
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
 #[serde(default)]
 pub struct Outlines {
@@ -137,64 +284,155 @@ impl Outlines {
     pub fn new() -> Self {
         Self {
             outline_colours: [
-                (AssetType::Armour, Rgba::new(56, 32, 64, 255)),
-                (AssetType::Face, Rgba::new(56, 32, 64, 255)),
-                (AssetType::Hair, Rgba::new(56, 32, 64, 255)),
-                (AssetType::Accessory, Rgba::new(56, 32, 64, 255)),
-                (AssetType::Token, Rgba::new(56, 32, 64, 255)),
+                (AssetType::Armour, DEFAULT_OUTLINE_COLOUR),
+                (AssetType::Face, DEFAULT_OUTLINE_COLOUR),
+                (AssetType::Hair, DEFAULT_OUTLINE_COLOUR),
+                (AssetType::Accessory, DEFAULT_OUTLINE_COLOUR),
+                (AssetType::Token, DEFAULT_OUTLINE_COLOUR),
             ]
             .into_iter()
             .collect(),
         }
     }
 
+    /// Sets the outline colour of an asset type, which companion types share with their parent.
     pub fn set_outline_colour(&mut self, asset_type: AssetType, colour: &Rgba) {
-        if asset_type == AssetType::HairBack {
-            self.outline_colours.insert(AssetType::Hair, *colour);
-        } else {
-            self.outline_colours.insert(asset_type, *colour);
-        }
+        let asset_type = asset_type.companion_of().unwrap_or(asset_type);
+        self.outline_colours.insert(asset_type, *colour);
     }
 
+    /// Returns the outline colour of an asset type, which companion types share with their parent.
     pub fn get_outline_colour(&self, asset_type: AssetType) -> Rgba {
-        if asset_type == AssetType::HairBack {
-            *self
-                .outline_colours
-                .get(&AssetType::Hair)
-                .unwrap_or(&Rgba::BLACK)
-        } else {
-            *self
-                .outline_colours
-                .get(&asset_type)
-                .unwrap_or(&Rgba::BLACK)
-        }
+        let asset_type = asset_type.companion_of().unwrap_or(asset_type);
+        *self
+            .outline_colours
+            .get(&asset_type)
+            .unwrap_or(&DEFAULT_OUTLINE_COLOUR)
     }
 }
 
+/// A layer of a loaded character whose asset could not be found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingPart {
+    /// The index the layer had in the character as it was saved.
+    pub index: usize,
+    /// The layer as it was saved.
+    pub layer: Layer,
+    /// Candidate replacements for the part's asset, best first.
+    pub substitutes: Vec<Substitute>,
+}
+
+/// The outcome of resolving the assets of a loaded character against the asset libraries.
+#[derive(Debug, Default, PartialEq)]
+pub struct AssetResolutionReport {
+    /// The saved and current ids of parts whose art was found under a different id.
+    pub renamed: Vec<(String, String)>,
+    /// Ids of parts whose art has changed since the character was saved.
+    pub changed: Vec<String>,
+    /// Parts whose art could not be found, which are removed from the character.
+    pub missing: Vec<MissingPart>,
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Clone)]
-#[serde(default)]
+#[serde(from = "StoredCharacter")]
 pub struct Character {
     pub name: String,
-    pub armour: Option<CharacterPart>,
-    pub face: Option<CharacterPart>,
-    pub hair: Option<CharacterPart>,
-    pub hair_back: Option<CharacterPart>,
-    pub accessory: Option<CharacterPart>,
-    pub token: Option<CharacterPart>,
+    /// The character's layers, in drawing order from bottom to top.
+    pub layers: Vec<Layer>,
     pub character_colours: HashMap<Colourable, CharacterPartColours>,
     pub outline_colours: Outlines,
+    /// Whether the character's colours are snapped to ones GBA hardware can show, see
+    /// `Rgba::to_gba` and `set_gba_colours`.
+    pub gba_colours: bool,
 }
 
-impl Default for Character {
+/// A character as it is read.
+///
+/// Characters saved before they had layers, such as the one in the GUI's stored app state, have
+/// one optional field per part instead. These become layers in the default drawing order, as
+/// `.fecc` files of format version 1 do when they are migrated, see `fecc_file`.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+struct StoredCharacter {
+    name: String,
+    layers: Vec<Layer>,
+    #[serde(deserialize_with = "deserialize_character_colours")]
+    character_colours: HashMap<Colourable, CharacterPartColours>,
+    outline_colours: Outlines,
+    gba_colours: bool,
+    hair_back: Option<CharacterPart>,
+    armour: Option<CharacterPart>,
+    face: Option<CharacterPart>,
+    hair: Option<CharacterPart>,
+    accessory: Option<CharacterPart>,
+    token: Option<CharacterPart>,
+}
+
+impl Default for StoredCharacter {
     fn default() -> Self {
+        let character = Character::default();
         Self {
-            name: String::new(),
+            name: character.name,
+            layers: character.layers,
+            character_colours: character.character_colours,
+            outline_colours: character.outline_colours,
+            gba_colours: character.gba_colours,
+            hair_back: None,
             armour: None,
             face: None,
             hair: None,
-            hair_back: None,
             accessory: None,
             token: None,
+        }
+    }
+}
+
+impl From<StoredCharacter> for Character {
+    fn from(stored: StoredCharacter) -> Self {
+        let parts = [
+            (AssetType::HairBack, stored.hair_back),
+            (AssetType::Armour, stored.armour),
+            (AssetType::Face, stored.face),
+            (AssetType::Hair, stored.hair),
+            (AssetType::Accessory, stored.accessory),
+            (AssetType::Token, stored.token),
+        ];
+        let mut layers = stored.layers;
+        layers.extend(
+            parts
+                .into_iter()
+                .filter_map(|(asset_type, part)| Some(Layer::new(asset_type, part?))),
+        );
+
+        Self {
+            name: stored.name,
+            layers,
+            character_colours: stored.character_colours,
+            outline_colours: stored.outline_colours,
+            gba_colours: stored.gba_colours,
+        }
+    }
+}
+
+/// Reads a character's colours over the default ones, so that colourables added since it was saved
+/// have colours too.
+fn deserialize_character_colours<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Colourable, CharacterPartColours>, D::Error> {
+    let mut colours = Character::default().character_colours;
+    colours.extend(
+        <HashMap<Colourable, CharacterPartColours> as serde::Deserialize>::deserialize(
+            deserializer,
+        )?,
+    );
+    Ok(colours)
+}
+
+impl Default for Character {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            layers: Vec::new(),
             character_colours: [
                 (
                     Hair,
@@ -228,45 +466,273 @@ impl Default for Character {
                     Accessory,
                     CharacterPartColours::new(&Rgba::new(0, 0, 0, 255)),
                 ),
+                (
+                    Cloth2,
+                    CharacterPartColours::new(&Rgba::new(148, 57, 57, 255)),
+                ),
+                (
+                    Metal2,
+                    CharacterPartColours::new(&Rgba::new(200, 168, 72, 255)),
+                ),
+                (
+                    Gem,
+                    CharacterPartColours::new(&Rgba::new(64, 168, 232, 255)),
+                ),
+                (
+                    Cape,
+                    CharacterPartColours::new(&Rgba::new(160, 40, 48, 255)),
+                ),
             ]
             .into_iter()
             .collect(),
             outline_colours: Outlines::new(),
+            gba_colours: false,
         }
     }
 }
 
 impl Character {
-    pub fn get_character_part(&self, asset_type: &AssetType) -> Option<CharacterPart> {
-        match asset_type {
-            AssetType::Armour => self.armour.clone(),
-            AssetType::Face => self.face.clone(),
-            AssetType::Hair => self.hair.clone(),
-            AssetType::HairBack => self.hair_back.clone(),
-            AssetType::Accessory => self.accessory.clone(),
-            AssetType::Token => self.token.clone(),
+    /// Switches GBA colour mode on or off. Switching it on snaps the current colours, including
+    /// the outlines.
+    pub fn set_gba_colours(&mut self, enabled: bool) {
+        self.gba_colours = enabled;
+        if enabled {
+            self.character_colours
+                .values_mut()
+                .for_each(CharacterPartColours::snap_to_gba);
+            self.outline_colours
+                .outline_colours
+                .values_mut()
+                .for_each(|colour| *colour = colour.to_gba());
         }
     }
 
+    /// Returns the neighbouring shades of each colourable that are the same colour, so art using
+    /// them loses its shading. Only the shades used by a recolour scheme are compared.
+    ///
+    /// This happens most often in GBA colour mode, where close shades snap together.
+    pub fn collapsed_shades(&self) -> Vec<(Colourable, Shade, Shade)> {
+        let mut used_shades: BTreeMap<Colourable, BTreeSet<Shade>> = BTreeMap::new();
+        for slot in KeySlot::all() {
+            used_shades
+                .entry(slot.colourable)
+                .or_default()
+                .insert(slot.shade);
+        }
+
+        let mut collapsed = Vec::new();
+        for (colourable, shades) in used_shades {
+            let Some(colours) = self.character_colours.get(&colourable) else {
+                continue;
+            };
+            let shades: Vec<Shade> = shades.into_iter().collect();
+            collapsed.extend(
+                shades
+                    .windows(2)
+                    .filter(|pair| colours.shade(pair[0]) == colours.shade(pair[1]))
+                    .map(|pair| (colourable, pair[0], pair[1])),
+            );
+        }
+        collapsed
+    }
+
+    /// Returns the part of the lowest layer of the given type.
+    pub fn get_character_part(&self, asset_type: &AssetType) -> Option<CharacterPart> {
+        self.layer_index(asset_type)
+            .map(|index| self.layers[index].part.clone())
+    }
+
+    /// Replaces the part of the lowest layer of the given type, or adds a layer for it.
     pub fn set_character_part(&mut self, asset_type: &AssetType, character_part: CharacterPart) {
-        match asset_type {
-            AssetType::Armour => self.armour = Some(character_part),
-            AssetType::Face => self.face = Some(character_part),
-            AssetType::Hair => self.hair = Some(character_part),
-            AssetType::HairBack => self.hair_back = Some(character_part),
-            AssetType::Accessory => self.accessory = Some(character_part),
-            AssetType::Token => self.token = Some(character_part),
+        match self.layer_index(asset_type) {
+            Some(index) => self.layers[index].part = character_part,
+            None => {
+                self.add_layer(Layer::new(*asset_type, character_part));
+            }
         }
     }
 
+    /// Removes the lowest layer of the given type.
     pub fn remove_character_part(&mut self, asset_type: &AssetType) {
-        match asset_type {
-            AssetType::Armour => self.armour = None,
-            AssetType::Face => self.face = None,
-            AssetType::Hair => self.hair = None,
-            AssetType::HairBack => self.hair_back = None,
-            AssetType::Accessory => self.accessory = None,
-            AssetType::Token => self.token = None,
+        if let Some(index) = self.layer_index(asset_type) {
+            self.layers.remove(index);
+        }
+    }
+
+    /// Returns the index of the lowest layer of the given type.
+    pub fn layer_index(&self, asset_type: &AssetType) -> Option<usize> {
+        self.layers
+            .iter()
+            .position(|layer| layer.asset_type == *asset_type)
+    }
+
+    /// Inserts a layer above the other layers of its type, returning its index.
+    ///
+    /// Layers are placed according to the layer order of their category, so a new accessory is
+    /// drawn above the hair unless it is moved.
+    pub fn add_layer(&mut self, layer: Layer) -> usize {
+        let order = layer.asset_type.category().layer_order;
+        let index = self
+            .layers
+            .iter()
+            .rposition(|existing| existing.asset_type.category().layer_order <= order)
+            .map_or(0, |i| i + 1);
+        self.layers.insert(index, layer);
+        index
+    }
+
+    /// Moves a layer to a new position in the drawing order, returning whether it was moved.
+    ///
+    /// Moves that would draw a companion layer in front of the layer it belongs to are rejected,
+    /// see `can_move_layer`.
+    pub fn move_layer(&mut self, from: usize, to: usize) -> bool {
+        if !self.can_move_layer(from, to) {
+            return false;
+        }
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+        true
+    }
+
+    /// Returns whether a layer can be moved to a new position without drawing a companion layer,
+    /// such as a `HairBack`, in front of the layer it belongs to.
+    pub fn can_move_layer(&self, from: usize, to: usize) -> bool {
+        if from >= self.layers.len() || to >= self.layers.len() {
+            return false;
+        }
+        let moved_index = |index: usize| match index {
+            _ if index == from => to,
+            _ if from < index && index <= to => index - 1,
+            _ if to <= index && index < from => index + 1,
+            _ => index,
+        };
+        let is_behind =
+            |companion: usize, owner: usize| moved_index(companion) < moved_index(owner);
+
+        self.companion_layer_index(from)
+            .is_none_or(|companion| is_behind(companion, from))
+            && self
+                .companion_owner_index(from)
+                .is_none_or(|owner| is_behind(from, owner))
+    }
+
+    /// Removes a layer along with its companion layer, if it has one.
+    pub fn remove_layer(&mut self, index: usize) {
+        if index >= self.layers.len() {
+            return;
+        }
+        let companion = self.companion_layer_index(index);
+        self.layers.remove(index);
+        if let Some(companion) = companion {
+            self.layers.remove(if companion > index {
+                companion - 1
+            } else {
+                companion
+            });
+        }
+    }
+
+    /// Returns the index of the layer drawn with the given layer as its back part, e.g. the
+    /// `HairBack` of a `Hair` layer.
+    pub fn companion_layer_index(&self, index: usize) -> Option<usize> {
+        let back_part = self.layers.get(index)?.part.asset.back_part.as_ref()?;
+        self.layers
+            .iter()
+            .position(|layer| layer.part.asset.id == *back_part)
+    }
+
+    /// Returns the index of the layer that the given layer is the companion of, if any.
+    pub fn companion_owner_index(&self, index: usize) -> Option<usize> {
+        (0..self.layers.len()).find(|&owner| self.companion_layer_index(owner) == Some(index))
+    }
+
+    /// Points each part at the matching asset in the libraries, see `Asset::resolve`.
+    ///
+    /// Parts whose asset can't be found are removed and reported as missing, along with suggested
+    /// substitutes, so the rest of the character can still be used.
+    pub fn resolve_assets(
+        &mut self,
+        asset_libraries: &HashMap<AssetType, IndexMap<String, Asset>>,
+    ) -> AssetResolutionReport {
+        let mut report = AssetResolutionReport::default();
+
+        let mut resolved = Vec::with_capacity(self.layers.len());
+        for (index, mut layer) in std::mem::take(&mut self.layers).into_iter().enumerate() {
+            let asset_type = layer.asset_type;
+            let part = &mut layer.part;
+
+            let resolution = asset_libraries
+                .get(&asset_type)
+                .map_or(AssetResolution::Missing, |library| {
+                    part.asset.resolve(library)
+                });
+
+            let asset = match resolution {
+                AssetResolution::Hash(asset) => {
+                    if asset.id != part.asset.id {
+                        report
+                            .renamed
+                            .push((part.asset.id.clone(), asset.id.clone()));
+                    }
+                    asset
+                }
+                AssetResolution::Id { asset, changed } => {
+                    if changed {
+                        report.changed.push(asset.id.clone());
+                    }
+                    asset
+                }
+                AssetResolution::Missing => {
+                    report.missing.push(MissingPart {
+                        index,
+                        substitutes: layer.part.asset.find_substitutes(asset_libraries),
+                        layer,
+                    });
+                    continue;
+                }
+            };
+
+            part.asset = asset.clone();
+            resolved.push(layer);
+        }
+        self.layers = resolved;
+
+        report
+    }
+
+    /// Converts part positions and scales from canvas pixels to fractions of the canvas size.
+    ///
+    /// Canvases with a zero dimension are skipped.
+    pub fn normalise(&mut self, portrait_size: Point, token_size: Point) {
+        self.scale_parts(portrait_size, token_size, |value, size| value / size);
+    }
+
+    /// Converts normalised part positions and scales back to canvas pixels.
+    ///
+    /// Canvases with a zero dimension are skipped.
+    pub fn denormalise(&mut self, portrait_size: Point, token_size: Point) {
+        self.scale_parts(portrait_size, token_size, |value, size| value * size);
+    }
+
+    fn scale_parts(
+        &mut self,
+        portrait_size: Point,
+        token_size: Point,
+        convert: impl Fn(f32, f32) -> f32,
+    ) {
+        for layer in &mut self.layers {
+            let size = if layer.asset_type.category().canvas == Canvas::Token {
+                token_size
+            } else {
+                portrait_size
+            };
+
+            if size.x > 0.0 && size.y > 0.0 {
+                let part = &mut layer.part;
+                part.position.x = convert(part.position.x, size.x);
+                part.position.y = convert(part.position.y, size.y);
+                part.scale = convert(part.scale, size.y);
+            }
         }
     }
 }
@@ -275,6 +741,7 @@ impl Character {
 mod tests {
     use super::*;
     use crate::asset::{Asset, AssetType};
+    use crate::extensions::rgba::{AdjustBrightness as _, RampStrategy};
     use crate::types::Rgba;
 
     #[test]
@@ -328,7 +795,7 @@ mod tests {
         let mut colours = CharacterPartColours::new(&initial_base);
 
         let new_base = Rgba::new(200, 200, 200, 255);
-        colours.set(new_base);
+        colours.set(new_base, false);
 
         assert_eq!(colours.base, new_base);
         assert_ne!(colours.base, initial_base);
@@ -337,6 +804,106 @@ mod tests {
         assert_eq!(colours.lighter, new_base.brighter());
     }
 
+    #[test]
+    fn test_character_part_colours_ramp() {
+        let mut colours = CharacterPartColours::new(&Rgba::new(200, 40, 40, 255));
+        let ramp = ColourRamp::new(RampStrategy::HueShift, 150);
+        colours.set_ramp(ramp, false);
+
+        assert_eq!(colours.lighter, ramp.lighter(&colours.base));
+        assert_eq!(colours.darker_darker, ramp.darker(&colours.darker));
+
+        // Characters saved before ramps could be chosen use the original shading.
+        let saved: CharacterPartColours =
+            serde_json::from_str(r#"{"base": {"r": 200, "g": 40, "b": 40, "a": 255}}"#)
+                .expect("Failed to parse colours");
+        assert_eq!(saved.ramp, ColourRamp::default());
+    }
+
+    #[test]
+    fn test_character_part_colours_gba() {
+        let mut colours = CharacterPartColours::new(&Rgba::new(100, 101, 102, 255));
+        colours.derive_all_colours(true);
+        assert_eq!(colours.base, Rgba::new(99, 99, 99, 255));
+        for shade in Shade::iter() {
+            assert_eq!(colours.shade(shade), colours.shade(shade).to_gba());
+        }
+
+        colours.set(Rgba::new(250, 10, 130, 255), true);
+        assert_eq!(colours.neutral, Rgba::new(247, 8, 132, 255));
+        assert_eq!(colours.darker, colours.darker.to_gba());
+
+        colours.set_shade(Shade::Lighter, Rgba::new(1, 2, 3, 255), true);
+        assert_eq!(colours.lighter, Rgba::BLACK);
+
+        colours.set_shade(Shade::Lighter, Rgba::new(1, 2, 3, 255), false);
+        assert_eq!(colours.lighter, Rgba::new(1, 2, 3, 255));
+        assert_eq!(colours.neutral, Rgba::new(247, 8, 132, 255));
+    }
+
+    #[test]
+    fn test_character_gba_colours() {
+        let mut character = Character::default();
+        assert!(!character.gba_colours);
+
+        character.set_gba_colours(true);
+        assert!(character.gba_colours);
+        assert_eq!(
+            character
+                .outline_colours
+                .get_outline_colour(AssetType::Face),
+            Rgba::new(57, 33, 66, 255)
+        );
+        let hair = &character.character_colours[&Hair];
+        assert_eq!(hair.base, hair.base.to_gba());
+        assert_eq!(hair.darker, hair.darker.to_gba());
+
+        // Switching the mode off leaves the colours as they are.
+        let snapped = hair.clone();
+        character.set_gba_colours(false);
+        assert!(!character.gba_colours);
+        assert_eq!(character.character_colours[&Hair], snapped);
+    }
+
+    #[test]
+    fn test_collapsed_shades() {
+        let mut character = Character::default();
+        // The default accessory colour is black, which can't be darkened.
+        assert_eq!(
+            character.collapsed_shades(),
+            [(Accessory, Shade::Neutral, Shade::Darker)]
+        );
+
+        let hair = character.character_colours.get_mut(&Hair).unwrap();
+        hair.lighter = hair.neutral;
+        // Skin uses every shade, so its darkest shades are compared too.
+        let skin = character.character_colours.get_mut(&Skin).unwrap();
+        skin.darker_darker_darker = skin.darker_darker;
+        // The outline only has one shade.
+        assert_eq!(
+            character.collapsed_shades(),
+            [
+                (Hair, Shade::Lighter, Shade::Neutral),
+                (Skin, Shade::DarkerDarker, Shade::DarkerDarkerDarker),
+                (Accessory, Shade::Neutral, Shade::Darker),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_saved_colours_gain_new_colourables() {
+        let saved: Character = serde_json::from_str(
+            r#"{"character_colours": {"Hair": {"base": {"r": 1, "g": 2, "b": 3, "a": 255}}}}"#,
+        )
+        .expect("Failed to parse character");
+
+        assert_eq!(saved.character_colours[&Hair].base, Rgba::new(1, 2, 3, 255));
+        assert_eq!(
+            saved.character_colours[&Gem],
+            Character::default().character_colours[&Gem]
+        );
+    }
+
     #[test]
     fn test_outlines_hair_logic() {
         let mut outlines = Outlines::new();
@@ -357,6 +924,11 @@ mod tests {
         // Check the default outline colour for Face (defined in new())
         let default_color = Rgba::new(56, 32, 64, 255);
         assert_eq!(outlines.get_outline_colour(AssetType::Face), default_color);
+        // User-defined categories have no entry until their outline is changed.
+        assert_eq!(
+            outlines.get_outline_colour(AssetType::Custom("TestCloak")),
+            default_color
+        );
     }
 
     #[test]
@@ -376,11 +948,163 @@ mod tests {
         };
 
         character.set_character_part(&AssetType::Face, part.clone());
-        assert!(character.face.is_some());
         assert!(character.get_character_part(&AssetType::Face).is_some());
 
         character.remove_character_part(&AssetType::Face);
-        assert!(character.face.is_none());
         assert!(character.get_character_part(&AssetType::Face).is_none());
     }
+
+    #[test]
+    fn test_layer_stack() {
+        let layer = |name: &str, asset_type: AssetType, back_part: Option<&str>| {
+            let asset = Asset::new(
+                name.to_owned(),
+                std::path::PathBuf::new(),
+                back_part.map(str::to_owned),
+                asset_type,
+            );
+            Layer::new(
+                asset_type,
+                CharacterPart {
+                    position: Point::new(0.0, 0.0),
+                    scale: 1.0,
+                    rotation: 0.0,
+                    flipped: false,
+                    asset,
+                },
+            )
+        };
+
+        let mut character = Character::default();
+        assert_eq!(
+            character.add_layer(layer("Glasses", AssetType::Accessory, None)),
+            0
+        );
+        assert_eq!(character.add_layer(layer("Face", AssetType::Face, None)), 0);
+        assert_eq!(
+            character.add_layer(layer("Long", AssetType::Hair, Some("Long_HairBack"))),
+            1
+        );
+        assert_eq!(
+            character.add_layer(layer("Long", AssetType::HairBack, None)),
+            0
+        );
+        assert_eq!(
+            character.add_layer(layer("Earring", AssetType::Accessory, None)),
+            4
+        );
+        assert_eq!(character.companion_layer_index(2), Some(0));
+        assert_eq!(character.companion_owner_index(0), Some(2));
+
+        // Draw the earring behind the hair.
+        assert!(character.move_layer(4, 2));
+        assert_eq!(character.layers[2].part.asset.id, "Earring_Accessory");
+        assert_eq!(character.layers[3].part.asset.id, "Long_Hair");
+
+        // The hair's back part stays behind the hair.
+        assert!(!character.move_layer(0, 3));
+        assert!(!character.move_layer(3, 0));
+        assert!(character.move_layer(0, 2));
+        assert!(character.move_layer(2, 0));
+        assert_eq!(character.layers[0].part.asset.id, "Long_HairBack");
+
+        character.remove_layer(3);
+        let ids: Vec<_> = character
+            .layers
+            .iter()
+            .map(|layer| layer.part.asset.id.as_str())
+            .collect();
+        assert_eq!(ids, ["Face_Face", "Earring_Accessory", "Glasses_Accessory"]);
+    }
+
+    #[test]
+    fn test_normalise_round_trip() {
+        let mut character = Character::default();
+        let part = CharacterPart {
+            position: Point::new(50.0, 100.0),
+            scale: 2.0,
+            rotation: 0.0,
+            flipped: false,
+            asset: Asset::new(
+                "test".to_string(),
+                std::path::PathBuf::new(),
+                None,
+                AssetType::Face,
+            ),
+        };
+        character.set_character_part(&AssetType::Face, part);
+
+        let portrait_size = Point::new(200.0, 200.0);
+        character.normalise(portrait_size, Point::ZERO);
+        let face = character.get_character_part(&AssetType::Face).unwrap();
+        assert_eq!(face.position, Point::new(0.25, 0.5));
+        assert_eq!(face.scale, 0.01);
+
+        character.denormalise(portrait_size, Point::ZERO);
+        let face = character.get_character_part(&AssetType::Face).unwrap();
+        assert_eq!(face.position, Point::new(50.0, 100.0));
+        assert_eq!(face.scale, 2.0);
+    }
+
+    #[test]
+    fn test_resolve_assets() {
+        let asset = |name: &str, asset_type: AssetType, hash: &str| {
+            let mut asset =
+                Asset::new(name.to_owned(), std::path::PathBuf::new(), None, asset_type);
+            asset.content_hash = Some(hash.to_owned());
+            asset
+        };
+        let part = |asset: Asset| CharacterPart {
+            position: Point::new(0.5, 0.5),
+            scale: 0.01,
+            rotation: 0.0,
+            flipped: true,
+            asset,
+        };
+
+        let mut character = Character::default();
+        character.set_character_part(&AssetType::Face, part(asset("Old", AssetType::Face, "1")));
+        character.set_character_part(&AssetType::Hair, part(asset("Same", AssetType::Hair, "2")));
+        character.set_character_part(
+            &AssetType::Token,
+            part(asset("Gone", AssetType::Token, "3")),
+        );
+
+        let asset_libraries: HashMap<AssetType, IndexMap<String, Asset>> = [
+            asset("New", AssetType::Face, "1"),
+            asset("Same", AssetType::Hair, "4"),
+        ]
+        .into_iter()
+        .map(|asset| {
+            (
+                asset.asset_type,
+                [(asset.id.clone(), asset)].into_iter().collect(),
+            )
+        })
+        .collect();
+
+        let report = character.resolve_assets(&asset_libraries);
+
+        assert_eq!(
+            report.renamed,
+            vec![("Old_Face".to_owned(), "New_Face".to_owned())]
+        );
+        assert_eq!(report.changed, vec!["Same_Hair".to_owned()]);
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].index, 2);
+        assert_eq!(report.missing[0].layer.asset_type, AssetType::Token);
+        assert_eq!(report.missing[0].layer.part.asset.id, "Gone_Token");
+        let face = character.get_character_part(&AssetType::Face).unwrap();
+        assert_eq!(face.asset.id, "New_Face");
+        assert!(face.flipped);
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Hair)
+                .unwrap()
+                .asset
+                .content_hash,
+            Some("4".to_owned())
+        );
+        assert!(character.get_character_part(&AssetType::Token).is_none());
+    }
 }
@@ -0,0 +1,528 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::asset::AssetType;
+use crate::character::{Character, CharacterPart, CharacterPartColours, Colourable, Layer};
+use crate::types::Rgba;
+use std::collections::VecDeque;
+use strum::IntoEnumIterator as _;
+
+/// The default number of entries kept by an `EditHistory`.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
+/// A single reversible change to a character.
+#[derive(Clone)]
+pub enum Edit {
+    Layers {
+        before: Vec<Layer>,
+        after: Vec<Layer>,
+    },
+    Colours {
+        colourable: Colourable,
+        before: Option<CharacterPartColours>,
+        after: Option<CharacterPartColours>,
+    },
+    Outline {
+        asset_type: AssetType,
+        before: Rgba,
+        after: Rgba,
+    },
+    /// Switching GBA colour mode, see `Character::gba_colours`. The snapped colours are separate
+    /// edits.
+    GbaColours { before: bool, after: bool },
+}
+
+impl Edit {
+    fn apply(&self, character: &mut Character) {
+        match self {
+            Self::Layers { after, .. } => character.layers.clone_from(after),
+            Self::Colours {
+                colourable, after, ..
+            } => set_or_remove_colours(character, *colourable, after.as_ref()),
+            Self::Outline {
+                asset_type, after, ..
+            } => character
+                .outline_colours
+                .set_outline_colour(*asset_type, after),
+            Self::GbaColours { after, .. } => character.gba_colours = *after,
+        }
+    }
+
+    fn revert(&self, character: &mut Character) {
+        match self {
+            Self::Layers { before, .. } => character.layers.clone_from(before),
+            Self::Colours {
+                colourable, before, ..
+            } => set_or_remove_colours(character, *colourable, before.as_ref()),
+            Self::Outline {
+                asset_type, before, ..
+            } => character
+                .outline_colours
+                .set_outline_colour(*asset_type, before),
+            Self::GbaColours { before, .. } => character.gba_colours = *before,
+        }
+    }
+
+    /// Returns true if both edits change the layers, or the same colourable or outline.
+    fn has_same_target(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Layers { .. }, Self::Layers { .. }) => true,
+            (Self::Outline { asset_type: a, .. }, Self::Outline { asset_type: b, .. }) => a == b,
+            (Self::Colours { colourable: a, .. }, Self::Colours { colourable: b, .. }) => a == b,
+            (Self::GbaColours { .. }, Self::GbaColours { .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Replaces the resulting state of this edit with that of `other`, keeping the original state.
+    fn absorb(&mut self, other: Self) {
+        match (self, other) {
+            (
+                Self::Layers { after, .. },
+                Self::Layers {
+                    after: new_after, ..
+                },
+            ) => *after = new_after,
+            (
+                Self::Colours { after, .. },
+                Self::Colours {
+                    after: new_after, ..
+                },
+            ) => {
+                *after = new_after;
+            }
+            (
+                Self::Outline { after, .. },
+                Self::Outline {
+                    after: new_after, ..
+                },
+            ) => {
+                *after = new_after;
+            }
+            (
+                Self::GbaColours { after, .. },
+                Self::GbaColours {
+                    after: new_after, ..
+                },
+            ) => {
+                *after = new_after;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_or_remove_colours(
+    character: &mut Character,
+    colourable: Colourable,
+    colours: Option<&CharacterPartColours>,
+) {
+    match colours {
+        Some(colours) => {
+            character
+                .character_colours
+                .insert(colourable, colours.clone());
+        }
+        None => {
+            character.character_colours.remove(&colourable);
+        }
+    }
+}
+
+/// Compares two parts by the asset they use and how it is placed, ignoring loaded image data.
+fn is_same_placement(a: &CharacterPart, b: &CharacterPart) -> bool {
+    a.asset.id == b.asset.id
+        && a.position == b.position
+        && a.scale == b.scale
+        && a.rotation == b.rotation
+        && a.flipped == b.flipped
+}
+
+/// Compares two layer stacks by their order, visibility and placement.
+fn is_same_layers(a: &[Layer], b: &[Layer]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.asset_type == b.asset_type
+                && a.visible == b.visible
+                && is_same_placement(&a.part, &b.part)
+        })
+}
+
+/// Identifies edits that should be merged with the previous entry, e.g. the steps of a drag.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MergeKey {
+    /// Transforming the layer at the given index.
+    Transform(usize),
+    Colour(Colourable),
+    Outline(AssetType),
+}
+
+struct HistoryEntry {
+    edits: Vec<Edit>,
+    merge_key: Option<MergeKey>,
+}
+
+/// A bounded undo/redo stack of part and colour changes made to a character.
+///
+/// Changes are recorded by comparing the character before and after an edit. Consecutive
+/// entries sharing a `MergeKey` are combined into one until `end_merge` is called.
+pub struct EditHistory {
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    capacity: usize,
+    merge_open: bool,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl EditHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            capacity: capacity.max(1),
+            merge_open: false,
+        }
+    }
+
+    /// Computes the edits that turn `before` into `after`.
+    pub fn diff(before: &Character, after: &Character) -> Vec<Edit> {
+        let mut edits = Vec::new();
+
+        if !is_same_layers(&before.layers, &after.layers) {
+            edits.push(Edit::Layers {
+                before: before.layers.clone(),
+                after: after.layers.clone(),
+            });
+        }
+
+        if before.gba_colours != after.gba_colours {
+            edits.push(Edit::GbaColours {
+                before: before.gba_colours,
+                after: after.gba_colours,
+            });
+        }
+
+        for colourable in Colourable::iter() {
+            let before_colours = before.character_colours.get(&colourable);
+            let after_colours = after.character_colours.get(&colourable);
+            if before_colours != after_colours {
+                edits.push(Edit::Colours {
+                    colourable,
+                    before: before_colours.cloned(),
+                    after: after_colours.cloned(),
+                });
+            }
+        }
+
+        for asset_type in AssetType::all().filter(|t| t.companion_of().is_none()) {
+            let before_outline = before.outline_colours.get_outline_colour(asset_type);
+            let after_outline = after.outline_colours.get_outline_colour(asset_type);
+            if before_outline != after_outline {
+                edits.push(Edit::Outline {
+                    asset_type,
+                    before: before_outline,
+                    after: after_outline,
+                });
+            }
+        }
+
+        edits
+    }
+
+    /// Records the changes between `before` and `after` as a single undoable entry.
+    ///
+    /// Nothing is recorded if the characters are equivalent. If `merge_key` matches that of the
+    /// previous entry, and the merge has not been ended, the changes are folded into that entry.
+    pub fn record(&mut self, before: &Character, after: &Character, merge_key: Option<MergeKey>) {
+        let edits = Self::diff(before, after);
+        if edits.is_empty() {
+            return;
+        }
+        self.redo_stack.clear();
+
+        if let Some(key) = merge_key
+            && self.merge_open
+            && let Some(last) = self.undo_stack.back_mut()
+            && last.merge_key == Some(key)
+        {
+            for edit in edits {
+                if let Some(existing) = last.edits.iter_mut().find(|e| e.has_same_target(&edit)) {
+                    existing.absorb(edit);
+                } else {
+                    last.edits.push(edit);
+                }
+            }
+            return;
+        }
+
+        self.undo_stack.push_back(HistoryEntry { edits, merge_key });
+        if self.undo_stack.len() > self.capacity {
+            self.undo_stack.pop_front();
+        }
+        self.merge_open = merge_key.is_some();
+    }
+
+    /// Stops further edits from merging into the latest entry, e.g. when a drag is released.
+    pub fn end_merge(&mut self) {
+        self.merge_open = false;
+    }
+
+    /// Reverts the latest entry. Returns false if there was nothing to undo.
+    pub fn undo(&mut self, character: &mut Character) -> bool {
+        let Some(entry) = self.undo_stack.pop_back() else {
+            return false;
+        };
+        for edit in entry.edits.iter().rev() {
+            edit.revert(character);
+        }
+        self.redo_stack.push(entry);
+        self.merge_open = false;
+        true
+    }
+
+    /// Re-applies the most recently undone entry. Returns false if there was nothing to redo.
+    pub fn redo(&mut self, character: &mut Character) -> bool {
+        let Some(entry) = self.redo_stack.pop() else {
+            return false;
+        };
+        for edit in &entry.edits {
+            edit.apply(character);
+        }
+        self.undo_stack.push_back(entry);
+        self.merge_open = false;
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.merge_open = false;
+    }
+
+    /// Applies `f` to every part stored in the history.
+    ///
+    /// Used to keep recorded parts consistent when the character's parts are rescaled,
+    /// e.g. when the canvas changes size.
+    pub fn for_each_part_mut(&mut self, mut f: impl FnMut(AssetType, &mut CharacterPart)) {
+        for entry in self.undo_stack.iter_mut().chain(self.redo_stack.iter_mut()) {
+            for edit in &mut entry.edits {
+                if let Edit::Layers { before, after } = edit {
+                    for layer in before.iter_mut().chain(after.iter_mut()) {
+                        f(layer.asset_type, &mut layer.part);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::Asset;
+    use crate::types::Point;
+
+    fn make_part(name: &str, asset_type: AssetType, x: f32) -> CharacterPart {
+        CharacterPart {
+            position: Point::new(x, 0.0),
+            scale: 1.0,
+            rotation: 0.0,
+            flipped: false,
+            asset: Asset::new(name.to_owned(), std::path::PathBuf::new(), None, asset_type),
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_part() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+
+        let before = character.clone();
+        character.set_character_part(&AssetType::Face, make_part("A", AssetType::Face, 0.0));
+        history.record(&before, &character, None);
+
+        assert!(history.undo(&mut character));
+        assert!(character.get_character_part(&AssetType::Face).is_none());
+        assert!(history.redo(&mut character));
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Face)
+                .unwrap()
+                .asset
+                .id,
+            "A_Face"
+        );
+        assert!(!history.redo(&mut character));
+    }
+
+    #[test]
+    fn test_undo_layer_changes() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+        character.set_character_part(&AssetType::Hair, make_part("H", AssetType::Hair, 0.0));
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            make_part("A", AssetType::Accessory, 0.0),
+        ));
+
+        let before = character.clone();
+        character.move_layer(1, 0);
+        character.layers[1].visible = false;
+        history.record(&before, &character, None);
+
+        assert!(history.undo(&mut character));
+        assert_eq!(character.layers[0].asset_type, AssetType::Hair);
+        assert!(character.layers[1].visible);
+    }
+
+    #[test]
+    fn test_undo_colour() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+        let original = character.character_colours[&Colourable::Hair].clone();
+
+        let before = character.clone();
+        character
+            .character_colours
+            .get_mut(&Colourable::Hair)
+            .unwrap()
+            .set(Rgba::new(1, 2, 3, 255), false);
+        history.record(&before, &character, None);
+
+        history.undo(&mut character);
+        assert_eq!(character.character_colours[&Colourable::Hair], original);
+    }
+
+    #[test]
+    fn test_undo_gba_colours() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+        let original = character.character_colours[&Colourable::Hair].clone();
+
+        let before = character.clone();
+        character.set_gba_colours(true);
+        history.record(&before, &character, None);
+
+        history.undo(&mut character);
+        assert!(!character.gba_colours);
+        assert_eq!(character.character_colours[&Colourable::Hair], original);
+
+        history.redo(&mut character);
+        assert!(character.gba_colours);
+    }
+
+    #[test]
+    fn test_no_change_not_recorded() {
+        let mut history = EditHistory::default();
+        let character = Character::default();
+        history.record(&character, &character, None);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_drag_steps_merge() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+        character.set_character_part(&AssetType::Face, make_part("A", AssetType::Face, 0.0));
+
+        let key = Some(MergeKey::Transform(0));
+        for x in 1..=5 {
+            let before = character.clone();
+            character
+                .set_character_part(&AssetType::Face, make_part("A", AssetType::Face, x as f32));
+            history.record(&before, &character, key);
+        }
+        history.end_merge();
+
+        assert!(history.undo(&mut character));
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Face)
+                .unwrap()
+                .position
+                .x,
+            0.0
+        );
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_end_merge_splits_entries() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+        character.set_character_part(&AssetType::Face, make_part("A", AssetType::Face, 0.0));
+        let key = Some(MergeKey::Transform(0));
+
+        let before = character.clone();
+        character.set_character_part(&AssetType::Face, make_part("A", AssetType::Face, 1.0));
+        history.record(&before, &character, key);
+        history.end_merge();
+
+        let before = character.clone();
+        character.set_character_part(&AssetType::Face, make_part("A", AssetType::Face, 2.0));
+        history.record(&before, &character, key);
+
+        history.undo(&mut character);
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Face)
+                .unwrap()
+                .position
+                .x,
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_capacity_bounded() {
+        let mut history = EditHistory::new(2);
+        let mut character = Character::default();
+
+        for x in 0..3 {
+            let before = character.clone();
+            character
+                .set_character_part(&AssetType::Face, make_part("A", AssetType::Face, x as f32));
+            history.record(&before, &character, None);
+        }
+
+        assert!(history.undo(&mut character));
+        assert!(history.undo(&mut character));
+        assert!(!history.undo(&mut character));
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Face)
+                .unwrap()
+                .position
+                .x,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut history = EditHistory::default();
+        let mut character = Character::default();
+
+        let before = character.clone();
+        character.set_character_part(&AssetType::Face, make_part("A", AssetType::Face, 0.0));
+        history.record(&before, &character, None);
+        history.undo(&mut character);
+
+        let before = character.clone();
+        character.set_character_part(&AssetType::Hair, make_part("B", AssetType::Hair, 0.0));
+        history.record(&before, &character, None);
+
+        assert!(!history.can_redo());
+    }
+}
@@ -1,29 +1,125 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
-use crate::asset::AssetType;
-use crate::character::Character;
+use crate::asset::category::Canvas;
+use crate::asset::{Asset, AssetType};
+use crate::character::{Character, Layer};
+use crate::export::layered::{ImageLayer, LayeredImage};
 use crate::recolour::recolour;
 use crate::types::Point;
 use image::{Rgba, RgbaImage, imageops};
 use imageproc::geometric_transformations::{Interpolation, rotate_about_center};
 use serde::{Deserialize, Serialize};
-use strum_macros::Display;
+use std::collections::HashSet;
+use strum::IntoEnumIterator as _;
+use strum_macros::{Display, EnumIter};
+
+pub mod indexed;
+pub mod layered;
 
 /// Defines the output dimensions for the exported character images.
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Display)]
-pub enum ExportSize {
+///
+/// The character is drawn as large as fits each canvas, placed by the anchor if the canvas isn't
+/// square, and then every pixel is scaled up to a square of `scale` pixels.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, remote = "Self")]
+pub struct ExportSize {
+    /// The width and height of the portrait, before scaling.
+    pub portrait: (u32, u32),
+    /// The width and height of the token, before scaling.
+    pub token: (u32, u32),
+    pub scale: u32,
+    pub anchor: Anchor,
+}
+
+impl Default for ExportSize {
+    fn default() -> Self {
+        Self::ORIGINAL
+    }
+}
+
+/// How an `ExportSize` is stored.
+///
+/// Earlier versions only had the `HALF`, `ORIGINAL` and `DOUBLE` sizes, and stored their names.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "ExportSize")]
+enum StoredExportSize {
     Half,
     Original,
     Double,
+    Custom(#[serde(with = "ExportSize")] ExportSize),
+}
+
+impl Serialize for ExportSize {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StoredExportSize::Custom(*self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExportSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match StoredExportSize::deserialize(deserializer)? {
+            StoredExportSize::Half => Self::HALF,
+            StoredExportSize::Original => Self::ORIGINAL,
+            StoredExportSize::Double => Self::DOUBLE,
+            StoredExportSize::Custom(size) => size,
+        })
+    }
 }
 
 impl ExportSize {
+    pub const HALF: Self = Self::square((48, 48), (32, 32), 1);
+    pub const ORIGINAL: Self = Self::square((96, 96), (64, 64), 1);
+    pub const DOUBLE: Self = Self::square((96, 96), (64, 64), 2);
+    pub const TRIPLE: Self = Self::square((96, 96), (64, 64), 3);
+    pub const QUADRUPLE: Self = Self::square((96, 96), (64, 64), 4);
+    /// A portrait with room above the character's head, as in FE dialogue boxes.
+    pub const HEADROOM: Self = Self {
+        portrait: (96, 128),
+        anchor: Anchor::Bottom,
+        ..Self::ORIGINAL
+    };
+
+    /// The sizes offered in the UI, with their names.
+    pub const PRESETS: [(&'static str, Self); 6] = [
+        ("Half", Self::HALF),
+        ("Original", Self::ORIGINAL),
+        ("Double", Self::DOUBLE),
+        ("Triple", Self::TRIPLE),
+        ("Quadruple", Self::QUADRUPLE),
+        ("Headroom", Self::HEADROOM),
+    ];
+
+    const fn square(portrait: (u32, u32), token: (u32, u32), scale: u32) -> Self {
+        Self {
+            portrait,
+            token,
+            scale,
+            anchor: Anchor::Centre,
+        }
+    }
+
+    /// Returns the preset with the given name, ignoring case.
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|&(_, size)| size)
+    }
+
+    /// Returns the name of the matching preset, or `Custom`.
+    pub fn name(&self) -> &'static str {
+        Self::PRESETS
+            .iter()
+            .find(|(_, size)| size == self)
+            .map_or("Custom", |&(name, _)| name)
+    }
+
     /// Returns a formatted string for display in the UI.
     ///
     /// The string includes the export size name, portrait dimensions, and token dimensions.
     pub fn display_name(&self) -> String {
         format!(
             "{} ({}x{}) ({}x{})",
-            self,
+            self.name(),
             self.portrait().0,
             self.portrait().1,
             self.token().0,
@@ -31,28 +127,164 @@ impl ExportSize {
         )
     }
 
-    /// Returns the portrait dimensions for the selected export size.
+    /// Returns the dimensions of the exported portrait, after scaling.
     pub fn portrait(&self) -> (u32, u32) {
-        match self {
-            Self::Half => (48, 48),
-            Self::Original => (96, 96),
-            Self::Double => (192, 192),
-        }
+        self.size(Canvas::Portrait)
     }
 
-    /// Returns the token dimensions for the selected export size.
+    /// Returns the dimensions of the exported token, after scaling.
     pub fn token(&self) -> (u32, u32) {
-        match self {
-            Self::Half => (32, 32),
-            Self::Original => (64, 64),
-            Self::Double => (128, 128),
+        self.size(Canvas::Token)
+    }
+
+    /// Returns the dimensions of the exported image of a canvas, after scaling.
+    pub fn size(&self, canvas: Canvas) -> (u32, u32) {
+        let (width, height) = self.unscaled(canvas);
+        (width * self.scale.max(1), height * self.scale.max(1))
+    }
+
+    fn unscaled(&self, canvas: Canvas) -> (u32, u32) {
+        let (width, height) = match canvas {
+            Canvas::Portrait => self.portrait,
+            Canvas::Token => self.token,
+        };
+        (width.max(1), height.max(1))
+    }
+
+    /// Exports a character's portrait or token at this size, see `export_character`.
+    pub fn export(
+        &self,
+        character: &Character,
+        canvas: Canvas,
+        ui_canvas_size: Point,
+    ) -> Option<RgbaImage> {
+        let side = self.art_side(canvas);
+        let image = export_character(
+            character,
+            &AssetType::on_canvas(canvas),
+            (side, side),
+            ui_canvas_size,
+        )?;
+        Some(self.place(&image, canvas))
+    }
+
+    /// Exports a character's portrait or token at this size with each part on its own layer, see
+    /// `export_layers`.
+    pub fn export_layers(
+        &self,
+        character: &Character,
+        canvas: Canvas,
+        ui_canvas_size: Point,
+    ) -> Option<LayeredImage> {
+        let side = self.art_side(canvas);
+        let mut layered = export_layers(
+            character,
+            &AssetType::on_canvas(canvas),
+            (side, side),
+            ui_canvas_size,
+        )?;
+        for layer in &mut layered.layers {
+            layer.image = self.place(&layer.image, canvas);
         }
+        (layered.width, layered.height) = self.size(canvas);
+        Some(layered)
+    }
+
+    /// The size of the square the character is drawn in, before scaling.
+    fn art_side(&self, canvas: Canvas) -> u32 {
+        let (width, height) = self.unscaled(canvas);
+        width.min(height)
+    }
+
+    /// Places an image of the character on the canvas by the anchor, and scales it up.
+    fn place(&self, image: &RgbaImage, canvas: Canvas) -> RgbaImage {
+        let (width, height) = self.unscaled(canvas);
+        let mut placed = RgbaImage::new(width, height);
+        let (x, y) = self.anchor.offset(
+            width.saturating_sub(image.width()),
+            height.saturating_sub(image.height()),
+        );
+        imageops::replace(&mut placed, image, i64::from(x), i64::from(y));
+
+        let (scaled_width, scaled_height) = self.size(canvas);
+        if (scaled_width, scaled_height) == (width, height) {
+            return placed;
+        }
+        imageops::resize(
+            &placed,
+            scaled_width,
+            scaled_height,
+            imageops::FilterType::Nearest,
+        )
     }
 }
 
+/// Where the character is placed on an export canvas that isn't square.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Display, EnumIter)]
+pub enum Anchor {
+    #[strum(to_string = "Top Left")]
+    TopLeft,
+    Top,
+    #[strum(to_string = "Top Right")]
+    TopRight,
+    Left,
+    #[default]
+    Centre,
+    Right,
+    #[strum(to_string = "Bottom Left")]
+    BottomLeft,
+    Bottom,
+    #[strum(to_string = "Bottom Right")]
+    BottomRight,
+}
+
+impl Anchor {
+    /// Returns the position of the character's top-left corner, given how much wider and taller
+    /// the canvas is than the character.
+    pub const fn offset(self, spare_width: u32, spare_height: u32) -> (u32, u32) {
+        let x = match self {
+            Self::TopLeft | Self::Left | Self::BottomLeft => 0,
+            Self::Top | Self::Centre | Self::Bottom => spare_width / 2,
+            Self::TopRight | Self::Right | Self::BottomRight => spare_width,
+        };
+        let y = match self {
+            Self::TopLeft | Self::Top | Self::TopRight => 0,
+            Self::Left | Self::Centre | Self::Right => spare_height / 2,
+            Self::BottomLeft | Self::Bottom | Self::BottomRight => spare_height,
+        };
+        (x, y)
+    }
+}
+
+impl std::str::FromStr for Anchor {
+    type Err = String;
+
+    /// Parses an anchor's name, ignoring case, with words joined by spaces, `-` or `_`, e.g.
+    /// `bottom-left`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.replace(['-', '_'], " ");
+        Self::iter()
+            .find(|anchor| anchor.to_string().eq_ignore_ascii_case(&name))
+            .ok_or_else(|| format!("Invalid anchor: '{name}'"))
+    }
+}
+
+/// Parses dimensions written as `<width>x<height>`, e.g. `96x128`.
+pub fn parse_dimensions(text: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid dimensions '{text}', expected e.g. 96x128");
+    let (width, height) = text.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
 /// Exports a character portrait or token as an `RgbaImage`.
 ///
-/// Composites the character's parts into a single image, applying
+/// Composites the character's visible layers of the given types into a single image, in the
+/// order of the character's layer stack, applying
 /// the necessary transformations to match their appearance on the UI canvas. It
 /// handles the conversion from UI coordinates to the final output image coordinates.
 pub fn export_character(
@@ -65,113 +297,471 @@ pub fn export_character(
         return None; // Avoid division by zero (ext.) if canvas hasn't been drawn yet
     }
 
-    // Create an oversized buffer to prevent clipping during rotation and scaling.
+    let mut image = RgbaImage::new(output_size.0, output_size.1);
+    for layer in character
+        .layers
+        .iter()
+        .filter(|layer| layer.visible && parts_to_draw.contains(&layer.asset_type))
+    {
+        if let Some((part_image, x, y)) =
+            render_layer(character, layer, output_size, ui_canvas_size)
+        {
+            overlay(&mut image, &part_image, x, y);
+        }
+    }
+    Some(image)
+}
+
+/// Exports a character portrait or token with each of its layers of the given types as a
+/// separate image, for editing in other programs, see `export_character`.
+///
+/// Hidden layers are included, but marked as hidden.
+pub fn export_layers(
+    character: &Character,
+    parts_to_draw: &[AssetType],
+    output_size: (u32, u32),
+    ui_canvas_size: Point,
+) -> Option<LayeredImage> {
+    if ui_canvas_size.x == 0.0 || ui_canvas_size.y == 0.0 {
+        return None;
+    }
+
+    let layers = character
+        .layers
+        .iter()
+        .filter(|layer| parts_to_draw.contains(&layer.asset_type))
+        .filter_map(|layer| {
+            let (part_image, x, y) = render_layer(character, layer, output_size, ui_canvas_size)?;
+            let mut image = RgbaImage::new(output_size.0, output_size.1);
+            overlay(&mut image, &part_image, x, y);
+            Some(ImageLayer {
+                name: format!("{} ({})", layer.part.asset.name, layer.asset_type),
+                visible: layer.visible,
+                image,
+            })
+        })
+        .collect();
+
+    Some(LayeredImage {
+        width: output_size.0,
+        height: output_size.1,
+        layers,
+    })
+}
+
+/// Recolours and transforms the art of a layer as it appears on the UI canvas, returning it with
+/// the position of its top-left corner on the output image.
+///
+/// Returns `None` if the art hasn't loaded, or is scaled down to nothing.
+fn render_layer(
+    character: &Character,
+    layer: &Layer,
+    output_size: (u32, u32),
+    ui_canvas_size: Point,
+) -> Option<(RgbaImage, i64, i64)> {
+    let part = &layer.part;
+    let mut part_image = (**part.asset.image_data.as_ref()?).clone();
+
+    recolour(
+        &mut part_image,
+        layer.asset_type,
+        part.asset.metadata.uses_extended_keys(),
+        &character.character_colours,
+        &character.outline_colours,
+    );
+
+    // The overall scaling factor from UI canvas to exported image.
+    let export_scale = output_size.0 as f32 / ui_canvas_size.x;
+
+    // Scale the asset image based on its UI scale and the export scale.
+    let final_scale_factor = part.scale * export_scale;
+    let scaled_width = (part_image.width() as f32 * final_scale_factor).round() as u32;
+    let scaled_height = (part_image.height() as f32 * final_scale_factor).round() as u32;
+
+    if scaled_width == 0 || scaled_height == 0 {
+        return None;
+    }
+
+    let mut scaled_image = imageops::resize(
+        &part_image,
+        scaled_width,
+        scaled_height,
+        imageops::FilterType::Nearest,
+    );
+
+    if part.flipped {
+        scaled_image = imageops::flip_horizontal(&scaled_image);
+    }
+
+    let rotated_image = rotate_about_center(
+        &scaled_image,
+        part.rotation,
+        Interpolation::Nearest,
+        Rgba([0, 0, 0, 0]),
+    );
+
+    // Parts are placed on an oversized buffer centred on the output, so that the top-left corner
+    // is rounded the same way however far a part hangs off the edge.
     let buffer_dim = output_size.0.max(output_size.1) * 2;
-    let mut buffer = RgbaImage::new(buffer_dim, buffer_dim);
     let buffer_centre_x = buffer_dim / 2;
     let buffer_centre_y = buffer_dim / 2;
+    let crop_x = buffer_centre_x - (output_size.0 / 2);
+    let crop_y = buffer_centre_y - (output_size.1 / 2);
 
-    // The overall scaling factor from UI canvas to exported image.
-    let export_scale = output_size.0 as f32 / ui_canvas_size.x;
+    let target_centre_on_output_x = part.position.x * export_scale;
+    let target_centre_on_output_y = part.position.y * export_scale;
 
-    for part_type in parts_to_draw {
-        if let Some(part) = character.get_character_part(part_type)
-            && let Some(original_image_data) = part.asset.image_data.as_ref()
-        {
-            let mut part_image = (**original_image_data).clone();
+    // Use integer division for output_size to match the crop of the buffer.
+    let top_left_x = (buffer_centre_x as f32 - ((output_size.0 / 2) as f32))
+        + target_centre_on_output_x
+        - (rotated_image.width() as f32 / 2.0);
+    let top_left_y = (buffer_centre_y as f32 - ((output_size.1 / 2) as f32))
+        + target_centre_on_output_y
+        - (rotated_image.height() as f32 / 2.0);
 
-            recolour(
-                &mut part_image,
-                *part_type,
-                &character.character_colours,
-                &character.outline_colours,
-            );
+    Some((
+        rotated_image,
+        top_left_x as i64 - i64::from(crop_x),
+        top_left_y as i64 - i64::from(crop_y),
+    ))
+}
 
-            // Scale the asset image based on its UI scale and the export scale.
-            let final_scale_factor = part.scale * export_scale;
-            let scaled_width = (part_image.width() as f32 * final_scale_factor).round() as u32;
-            let scaled_height = (part_image.height() as f32 * final_scale_factor).round() as u32;
+/// The colours used by some exported images, see `analyse_colours`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ColourAnalysis {
+    /// The number of distinct colours, not counting fully transparent pixels.
+    pub unique_colours: usize,
+    /// Whether any pixel is neither fully opaque nor fully transparent.
+    pub semi_transparent: bool,
+}
 
-            if scaled_width == 0 || scaled_height == 0 {
-                continue;
-            }
+/// Counts the colours used across some images, e.g. a character's portrait and token.
+pub fn analyse_colours<'a>(images: impl IntoIterator<Item = &'a RgbaImage>) -> ColourAnalysis {
+    let mut unique_colours = HashSet::new();
+    let mut semi_transparent = false;
+    for pixel in images.into_iter().flat_map(RgbaImage::pixels) {
+        let alpha = pixel[3];
+        if alpha != 0 {
+            unique_colours.insert(pixel.0);
+        }
+        semi_transparent |= alpha != 0 && alpha != u8::MAX;
+    }
+    ColourAnalysis {
+        unique_colours: unique_colours.len(),
+        semi_transparent,
+    }
+}
 
-            let mut scaled_image = imageops::resize(
-                &part_image,
-                scaled_width,
-                scaled_height,
-                imageops::FilterType::Nearest,
-            );
+/// A GBA Fire Emblem mug sheet, the 128x112 layout that FEBuilder and FEditor import portraits
+/// from.
+///
+/// The sheet holds the 96x80 portrait, a 32x32 minimug, two 32x16 eye-blink frames and six 32x16
+/// mouth frames. FECC art has no separate eyes or mouths, so the frames are cut from the portrait
+/// at the given positions, ready to be redrawn.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MugSheet {
+    /// The top-left corner of the eyes on the 96x80 portrait.
+    pub eyes: (u32, u32),
+    /// The top-left corner of the mouth on the 96x80 portrait.
+    pub mouth: (u32, u32),
+    /// The top-left corner of the minimug's face on the 96x80 portrait.
+    pub minimug: (u32, u32),
+}
 
-            if part.flipped {
-                scaled_image = imageops::flip_horizontal(&scaled_image);
-            }
+impl Default for MugSheet {
+    fn default() -> Self {
+        Self {
+            eyes: (32, 32),
+            mouth: (32, 48),
+            minimug: (32, 24),
+        }
+    }
+}
 
-            let rotated_image = rotate_about_center(
-                &scaled_image,
-                part.rotation,
-                Interpolation::Nearest,
-                Rgba([0, 0, 0, 0]),
-            );
+impl MugSheet {
+    /// The dimensions of the sheet.
+    pub const SIZE: (u32, u32) = (128, 112);
+    /// The dimensions of the portrait, the bottom of a 96x96 FECC portrait.
+    pub const PORTRAIT: (u32, u32) = (96, 80);
+    /// The dimensions of the minimug.
+    pub const MINIMUG: (u32, u32) = (32, 32);
+    /// The dimensions of each eye and mouth frame.
+    pub const FRAME: (u32, u32) = (32, 16);
+    /// The most opaque colours a GBA palette holds, as its first colour is transparency.
+    pub const MAX_COLOURS: usize = 15;
+
+    const MINIMUG_AT: (u32, u32) = (96, 16);
+    const EYE_FRAMES_AT: [(u32, u32); 2] = [(96, 48), (96, 64)];
+    const MOUTH_FRAMES_AT: [(u32, u32); 6] =
+        [(0, 80), (32, 80), (64, 80), (0, 96), (32, 96), (64, 96)];
+
+    /// Returns a formatted string for display in the UI.
+    pub fn display_name() -> String {
+        format!("GBA Mug Sheet ({}x{})", Self::SIZE.0, Self::SIZE.1)
+    }
+
+    /// Composites the character's portrait into a mug sheet.
+    ///
+    /// Fails if the canvas hasn't been drawn yet, or if the portrait uses more colours than a GBA
+    /// palette holds or has semi-transparent pixels.
+    pub fn export(
+        &self,
+        character: &Character,
+        ui_canvas_size: Point,
+    ) -> Result<RgbaImage, String> {
+        let full = ExportSize::ORIGINAL
+            .export(character, Canvas::Portrait, ui_canvas_size)
+            .ok_or("The portrait hasn't been drawn yet.")?;
+        let portrait = imageops::crop_imm(
+            &full,
+            0,
+            full.height() - Self::PORTRAIT.1,
+            Self::PORTRAIT.0,
+            Self::PORTRAIT.1,
+        )
+        .to_image();
 
-            let target_centre_on_output_x = part.position.x * export_scale;
-            let target_centre_on_output_y = part.position.y * export_scale;
-
-            // Calculate the top-left corner for overlaying the rotated image.
-            // Use integer division for output_size to match crop_imm's behaviour.
-            let top_left_x = (buffer_centre_x as f32 - ((output_size.0 / 2) as f32))
-                + target_centre_on_output_x
-                - (rotated_image.width() as f32 / 2.0);
-            let top_left_y = (buffer_centre_y as f32 - ((output_size.1 / 2) as f32))
-                + target_centre_on_output_y
-                - (rotated_image.height() as f32 / 2.0);
-
-            imageops::overlay(
-                &mut buffer,
-                &rotated_image,
-                top_left_x as i64,
-                top_left_y as i64,
+        let analysis = analyse_colours([&portrait]);
+        if analysis.semi_transparent {
+            return Err(
+                "The portrait has semi-transparent pixels, which a GBA mug can't have.".into(),
             );
         }
+        if analysis.unique_colours > Self::MAX_COLOURS {
+            return Err(format!(
+                "The portrait uses {} colours, but a GBA mug can only use {} and transparency.",
+                analysis.unique_colours,
+                Self::MAX_COLOURS
+            ));
+        }
+
+        let mut sheet = RgbaImage::new(Self::SIZE.0, Self::SIZE.1);
+        imageops::replace(&mut sheet, &portrait, 0, 0);
+        let mut copy = |from: (u32, u32), size: (u32, u32), to: (u32, u32)| {
+            let region = imageops::crop_imm(&portrait, from.0, from.1, size.0, size.1).to_image();
+            imageops::replace(&mut sheet, &region, i64::from(to.0), i64::from(to.1));
+        };
+        copy(self.minimug, Self::MINIMUG, Self::MINIMUG_AT);
+        for to in Self::EYE_FRAMES_AT {
+            copy(self.eyes, Self::FRAME, to);
+        }
+        for to in Self::MOUTH_FRAMES_AT {
+            copy(self.mouth, Self::FRAME, to);
+        }
+        Ok(sheet)
     }
+}
 
-    let crop_x = buffer_centre_x - (output_size.0 / 2);
-    let crop_y = buffer_centre_y - (output_size.1 / 2);
+/// Draws `top` over `bottom` with its top-left corner at the given position, clipping it to
+/// `bottom`.
+///
+/// Unlike `imageops::overlay`, the blended channels are rounded, so an opaque pixel stays opaque
+/// under a semi-transparent one.
+fn overlay(bottom: &mut RgbaImage, top: &RgbaImage, x: i64, y: i64) {
+    for (top_x, top_y, pixel) in top.enumerate_pixels() {
+        let (Ok(bottom_x), Ok(bottom_y)) = (
+            u32::try_from(x + i64::from(top_x)),
+            u32::try_from(y + i64::from(top_y)),
+        ) else {
+            continue;
+        };
+        if let Some(bottom_pixel) = bottom.get_pixel_mut_checked(bottom_x, bottom_y) {
+            blend(bottom_pixel, *pixel);
+        }
+    }
+}
 
-    let final_image =
-        imageops::crop_imm(&buffer, crop_x, crop_y, output_size.0, output_size.1).to_image();
+/// Composites `top` over `bottom`, using the "over" operator on unpremultiplied colours.
+fn blend(bottom: &mut Rgba<u8>, top: Rgba<u8>) {
+    match top[3] {
+        0 => return,
+        u8::MAX => {
+            *bottom = top;
+            return;
+        }
+        _ => {}
+    }
 
-    Some(final_image)
+    let top_alpha = f32::from(top[3]) / 255.0;
+    let bottom_alpha = f32::from(bottom[3]) / 255.0 * (1.0 - top_alpha);
+    let alpha = top_alpha + bottom_alpha;
+    for channel in 0..3 {
+        let blended =
+            f32::from(top[channel]) * top_alpha + f32::from(bottom[channel]) * bottom_alpha;
+        bottom[channel] = (blended / alpha).round() as u8;
+    }
+    bottom[3] = (alpha * 255.0).round() as u8;
+}
+
+/// Lists the credits for the art of a character's visible layers of the given types, see
+/// `credits`.
+pub fn credits_text(character: &Character, parts_to_draw: &[AssetType]) -> Option<String> {
+    credits(
+        character
+            .layers
+            .iter()
+            .filter(|layer| layer.visible && parts_to_draw.contains(&layer.asset_type))
+            .map(|layer| &layer.part.asset),
+    )
+}
+
+/// Lists the artist, source game and licence of each asset with any of them recorded in its
+/// metadata, one line per asset, e.g. `Aias (Face): art by Iscaneus, licensed under CC BY 4.0`.
+///
+/// Returns `None` if no asset has any credits recorded.
+pub fn credits<'a>(assets: impl IntoIterator<Item = &'a Asset>) -> Option<String> {
+    let mut credited: Vec<&str> = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+
+    for asset in assets {
+        let metadata = &asset.metadata;
+        if !metadata.has_credit() || credited.contains(&asset.id.as_str()) {
+            continue;
+        }
+        credited.push(&asset.id);
+
+        let details: Vec<String> = [
+            metadata
+                .artist
+                .as_ref()
+                .map(|artist| format!("art by {artist}")),
+            metadata
+                .source_game
+                .as_ref()
+                .map(|game| format!("from {game}")),
+            metadata
+                .licence
+                .as_ref()
+                .map(|licence| format!("licensed under {licence}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        lines.push(format!(
+            "{} ({}): {}",
+            asset.name,
+            asset.asset_type,
+            details.join(", ")
+        ));
+    }
+
+    (!lines.is_empty()).then(|| lines.join("\n") + "\n")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::asset::{Asset, AssetType};
-    use crate::character::{Character, CharacterPart};
+    use crate::character::{Character, CharacterPart, Layer};
     use image::Rgba;
     use std::sync::Arc;
 
     #[test]
     fn test_export_size_display_name() {
-        assert_eq!(ExportSize::Half.display_name(), "Half (48x48) (32x32)");
+        assert_eq!(ExportSize::HALF.display_name(), "Half (48x48) (32x32)");
         assert_eq!(
-            ExportSize::Original.display_name(),
+            ExportSize::ORIGINAL.display_name(),
             "Original (96x96) (64x64)"
         );
         assert_eq!(
-            ExportSize::Double.display_name(),
+            ExportSize::DOUBLE.display_name(),
             "Double (192x192) (128x128)"
         );
+        let custom = ExportSize {
+            token: (80, 64),
+            ..ExportSize::QUADRUPLE
+        };
+        assert_eq!(custom.display_name(), "Custom (384x384) (320x256)");
     }
 
     #[test]
     fn test_export_size_dimensions() {
-        assert_eq!(ExportSize::Half.portrait(), (48, 48));
-        assert_eq!(ExportSize::Half.token(), (32, 32));
+        assert_eq!(ExportSize::HALF.portrait(), (48, 48));
+        assert_eq!(ExportSize::HALF.token(), (32, 32));
 
-        assert_eq!(ExportSize::Original.portrait(), (96, 96));
-        assert_eq!(ExportSize::Double.portrait(), (192, 192));
+        assert_eq!(ExportSize::ORIGINAL.portrait(), (96, 96));
+        assert_eq!(ExportSize::DOUBLE.portrait(), (192, 192));
+        assert_eq!(ExportSize::TRIPLE.token(), (192, 192));
+        assert_eq!(ExportSize::HEADROOM.portrait(), (96, 128));
+        assert_eq!(ExportSize::preset("quadruple"), Some(ExportSize::QUADRUPLE));
+        assert_eq!(ExportSize::preset("Huge"), None);
+    }
+
+    #[test]
+    fn test_export_size_serde() {
+        let size = ExportSize {
+            token: (80, 64),
+            anchor: Anchor::Bottom,
+            ..ExportSize::TRIPLE
+        };
+        let json = serde_json::to_string(&size).expect("Failed to serialise size");
+        assert_eq!(
+            serde_json::from_str::<ExportSize>(&json).expect("Failed to read size"),
+            size
+        );
+
+        // Sizes stored by earlier versions.
+        for (name, size) in [
+            ("Half", ExportSize::HALF),
+            ("Original", ExportSize::ORIGINAL),
+            ("Double", ExportSize::DOUBLE),
+        ] {
+            let json = format!("\"{name}\"");
+            assert_eq!(
+                serde_json::from_str::<ExportSize>(&json).expect("Failed to read preset"),
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_export_options() {
+        assert_eq!(parse_dimensions("96x128"), Ok((96, 128)));
+        assert!(parse_dimensions("96").is_err());
+        assert!(parse_dimensions("0x96").is_err());
+
+        assert_eq!("bottom-left".parse(), Ok(Anchor::BottomLeft));
+        assert_eq!("Top Right".parse(), Ok(Anchor::TopRight));
+        assert_eq!("CENTRE".parse(), Ok(Anchor::Centre));
+        assert!("middle".parse::<Anchor>().is_err());
+    }
+
+    #[test]
+    fn test_export_size_places_and_scales() {
+        let mut character = Character::default();
+        let mut part = solid_part("Dot", [250, 0, 0, 255]);
+        part.asset.image_data = Some(Arc::new(RgbaImage::from_pixel(
+            1,
+            1,
+            Rgba([250, 0, 0, 255]),
+        )));
+        part.position = Point::new(0.5, 0.5);
+        character.add_layer(Layer::new(AssetType::Accessory, part));
+        let ui_canvas = Point::new(96.0, 96.0);
+
+        // The character is drawn at the bottom of the taller canvas.
+        let size = ExportSize {
+            portrait: (96, 128),
+            anchor: Anchor::Bottom,
+            ..ExportSize::DOUBLE
+        };
+        let image = size
+            .export(&character, Canvas::Portrait, ui_canvas)
+            .unwrap();
+        assert_eq!(image.dimensions(), (192, 256));
+        let drawn: Vec<_> = image
+            .enumerate_pixels()
+            .filter(|(.., pixel)| pixel[3] != 0)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(drawn, [(0, 64), (1, 64), (0, 65), (1, 65)]);
+
+        let layered = size
+            .export_layers(&character, Canvas::Portrait, ui_canvas)
+            .unwrap();
+        assert_eq!((layered.width, layered.height), (192, 256));
+        assert_eq!(layered.flatten(), image);
     }
 
     #[test]
@@ -205,7 +795,11 @@ mod tests {
             path: std::path::PathBuf::new(),
             back_part: None,
             asset_type: AssetType::Face,
+            content_hash: None,
+            metadata: Default::default(),
+            pack: None,
             image_data: Some(Arc::new(image)),
+            file_bytes: None,
         };
 
         let part = CharacterPart {
@@ -216,7 +810,7 @@ mod tests {
             asset,
         };
 
-        character.face = Some(part);
+        character.set_character_part(&AssetType::Face, part);
 
         let ui_canvas = Point::new(100.0, 100.0);
 
@@ -237,4 +831,235 @@ mod tests {
         assert_eq!(centre_pixel[2], 0);
         assert_eq!(centre_pixel[3], 255);
     }
+
+    /// Returns a centred 10x10 accessory part of a single colour.
+    fn solid_part(name: &str, colour: [u8; 4]) -> CharacterPart {
+        let mut asset = Asset::new(
+            name.to_owned(),
+            std::path::PathBuf::new(),
+            None,
+            AssetType::Accessory,
+        );
+        asset.image_data = Some(Arc::new(RgbaImage::from_pixel(10, 10, Rgba(colour))));
+        CharacterPart {
+            position: Point::new(50.0, 50.0),
+            scale: 1.0,
+            rotation: 0.0,
+            flipped: false,
+            asset,
+        }
+    }
+
+    #[test]
+    fn test_export_respects_layer_order_and_visibility() {
+        let mut character = Character::default();
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            solid_part("Bottom", [255, 0, 0, 255]),
+        ));
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            solid_part("Top", [250, 255, 0, 255]),
+        ));
+        let ui_canvas = Point::new(100.0, 100.0);
+
+        let img =
+            export_character(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap();
+        assert_eq!(img.get_pixel(50, 50)[1], 255);
+
+        character.layers[1].visible = false;
+        let img =
+            export_character(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap();
+        assert_eq!(img.get_pixel(50, 50)[1], 0);
+
+        character.layers[1].visible = true;
+        character.move_layer(1, 0);
+        let img =
+            export_character(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap();
+        assert_eq!(img.get_pixel(50, 50)[1], 0);
+    }
+
+    #[test]
+    fn test_export_blends_semi_transparent_parts() {
+        let mut character = Character::default();
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            solid_part("Bottom", [250, 0, 0, 255]),
+        ));
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            solid_part("Top", [250, 255, 0, 128]),
+        ));
+        let ui_canvas = Point::new(100.0, 100.0);
+
+        let img =
+            export_character(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap();
+        assert_eq!(img.get_pixel(50, 50).0, [250, 128, 0, 255]);
+
+        // Over nothing, the part keeps its own alpha.
+        character.layers[0].visible = false;
+        let img =
+            export_character(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap();
+        assert_eq!(img.get_pixel(50, 50).0, [250, 255, 0, 128]);
+    }
+
+    #[test]
+    fn test_export_layers() {
+        let mut character = Character::default();
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            solid_part("Bottom", [250, 0, 0, 255]),
+        ));
+        let mut top = solid_part("Top", [250, 255, 0, 128]);
+        top.position = Point::new(53.0, 50.0);
+        character.add_layer(Layer::new(AssetType::Accessory, top));
+        character.add_layer(Layer::new(
+            AssetType::Accessory,
+            solid_part("Hidden", [0, 0, 0, 255]),
+        ));
+        character.layers[2].visible = false;
+        let ui_canvas = Point::new(100.0, 100.0);
+
+        let layered =
+            export_layers(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap();
+        assert_eq!(
+            layered
+                .layers
+                .iter()
+                .map(|layer| (layer.name.as_str(), layer.visible))
+                .collect::<Vec<_>>(),
+            [
+                ("Bottom (Accessory)", true),
+                ("Top (Accessory)", true),
+                ("Hidden (Accessory)", false)
+            ]
+        );
+        // Each layer holds only its own part, where it is on the canvas.
+        assert_eq!(layered.layers[1].image.get_pixel(46, 50)[3], 0);
+        assert_eq!(
+            layered.layers[1].image.get_pixel(50, 50).0,
+            [250, 255, 0, 128]
+        );
+        assert_eq!(
+            layered.flatten(),
+            export_character(&character, &[AssetType::Accessory], (100, 100), ui_canvas).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_analyse_colours() {
+        let mut image = RgbaImage::new(3, 1);
+        image.put_pixel(0, 0, Rgba([1, 2, 3, 255]));
+        image.put_pixel(1, 0, Rgba([1, 2, 3, 255]));
+        let other = RgbaImage::from_pixel(1, 1, Rgba([4, 5, 6, 255]));
+
+        assert_eq!(
+            analyse_colours([&image, &other]),
+            ColourAnalysis {
+                unique_colours: 2,
+                semi_transparent: false
+            }
+        );
+        image.put_pixel(2, 0, Rgba([1, 2, 3, 128]));
+        assert_eq!(
+            analyse_colours([&image]),
+            ColourAnalysis {
+                unique_colours: 2,
+                semi_transparent: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_mug_sheet() {
+        let mut character = Character::default();
+        let mut part = solid_part("Face", [250, 0, 0, 255]);
+        part.position = Point::new(48.0, 48.0);
+        character.add_layer(Layer::new(AssetType::Accessory, part));
+        let ui_canvas = Point::new(96.0, 96.0);
+
+        let sheet = MugSheet::default().export(&character, ui_canvas).unwrap();
+        assert_eq!(sheet.dimensions(), MugSheet::SIZE);
+        // The part covers 43..53 of the 96x96 portrait, and the sheet's portrait is its bottom 80
+        // rows.
+        let red = Rgba([250, 0, 0, 255]);
+        assert_eq!(*sheet.get_pixel(48, 30), red);
+        assert_eq!(sheet.get_pixel(48, 40)[3], 0);
+        // The eyes are cut from (32, 32) and the minimug from (32, 24).
+        assert_eq!(*sheet.get_pixel(112, 49), red);
+        assert_eq!(*sheet.get_pixel(112, 65), red);
+        assert_eq!(*sheet.get_pixel(112, 22), red);
+        // Nothing is drawn where the mouth is cut from.
+        assert!(
+            imageops::crop_imm(&sheet, 0, 80, 96, 32)
+                .to_image()
+                .pixels()
+                .all(|pixel| pixel[3] == 0)
+        );
+    }
+
+    #[test]
+    fn test_mug_sheet_colour_limits() {
+        let ui_canvas = Point::new(96.0, 96.0);
+        let mut image = RgbaImage::new(16, 1);
+        for (green, pixel) in image.pixels_mut().enumerate() {
+            *pixel = Rgba([250, green as u8, 0, 255]);
+        }
+        let mut part = solid_part("Face", [0, 0, 0, 0]);
+        part.position = Point::new(48.0, 48.0);
+        part.asset.image_data = Some(Arc::new(image));
+        let mut character = Character::default();
+        character.add_layer(Layer::new(AssetType::Accessory, part));
+
+        let error = MugSheet::default()
+            .export(&character, ui_canvas)
+            .unwrap_err();
+        assert!(error.contains("16 colours"), "{error}");
+
+        character.layers[0].part = solid_part("Glass", [250, 0, 0, 128]);
+        let error = MugSheet::default()
+            .export(&character, ui_canvas)
+            .unwrap_err();
+        assert!(error.contains("semi-transparent"), "{error}");
+    }
+
+    #[test]
+    fn test_credits_text() {
+        let mut character = Character::default();
+        for (name, asset_type, artist, licence, visible) in [
+            (
+                "Aias",
+                AssetType::Face,
+                Some("Iscaneus"),
+                Some("CC BY 4.0"),
+                true,
+            ),
+            ("Aias", AssetType::Hair, None, None, true),
+            ("Hidden", AssetType::Armour, Some("Someone"), None, false),
+            ("Coin", AssetType::Token, Some("Someone"), None, true),
+        ] {
+            let mut asset =
+                Asset::new(name.to_owned(), std::path::PathBuf::new(), None, asset_type);
+            asset.metadata.artist = artist.map(str::to_owned);
+            asset.metadata.licence = licence.map(str::to_owned);
+            character.layers.push(Layer {
+                asset_type,
+                visible,
+                part: CharacterPart {
+                    asset,
+                    position: Point::new(0.0, 0.0),
+                    scale: 1.0,
+                    rotation: 0.0,
+                    flipped: false,
+                },
+            });
+        }
+
+        let portrait = [AssetType::Armour, AssetType::Face, AssetType::Hair];
+        assert_eq!(
+            credits_text(&character, &portrait).as_deref(),
+            Some("Aias (Face): art by Iscaneus, licensed under CC BY 4.0\n")
+        );
+        assert_eq!(credits_text(&character, &[AssetType::Hair]), None);
+    }
 }
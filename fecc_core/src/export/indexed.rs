@@ -0,0 +1,369 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::character::{Character, Colourable, Shade};
+use crate::types::Rgba;
+use image::RgbaImage;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The most colours an indexed PNG's palette holds, including the transparent one at index 0.
+pub const MAX_PALETTE_SIZE: usize = 256;
+
+/// An image whose pixels are indices into an ordered palette, as written to indexed PNGs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    /// The transparent colour at index 0, then the character's colours in the order of their
+    /// colourables and shades, then any other colours from the most to the least used.
+    pub palette: Vec<Rgba>,
+    /// The palette index of each pixel, row by row.
+    pub indices: Vec<u8>,
+    /// The colours that were merged into others to fit the palette, in the order they were merged.
+    pub merges: Vec<ColourMerge>,
+}
+
+/// A colour that was merged into the nearest other colour when an image was quantised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColourMerge {
+    pub from: Rgba,
+    pub into: Rgba,
+    /// The shades of the character's colourables that were coloured `from`.
+    pub merged: Vec<(Colourable, Shade)>,
+    /// The shades of the character's colourables that are coloured `into`.
+    pub kept: Vec<(Colourable, Shade)>,
+}
+
+impl fmt::Display for ColourMerge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |colour: Rgba, slots: &[(Colourable, Shade)]| {
+            if slots.is_empty() {
+                format!(
+                    "#{:02x}{:02x}{:02x}{:02x}",
+                    colour.r, colour.g, colour.b, colour.a
+                )
+            } else {
+                slots
+                    .iter()
+                    .map(|(colourable, shade)| format!("{colourable} ({})", shade.label()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        };
+        write!(
+            f,
+            "{} merged into {}",
+            describe(self.from, &self.merged),
+            describe(self.into, &self.kept)
+        )
+    }
+}
+
+impl IndexedImage {
+    /// Converts an image of a character, such as an exported portrait, to an indexed image.
+    ///
+    /// Fully transparent pixels all use index 0. If `max_colours` is given and the image uses more
+    /// colours than that, not counting transparency, the nearest pair of colours is merged into
+    /// the more used of the two until it fits, and each merge is reported. Without it, images
+    /// with more colours than a palette holds are an error.
+    pub fn from_image(
+        image: &RgbaImage,
+        character: &Character,
+        max_colours: Option<usize>,
+    ) -> Result<Self, String> {
+        let slots = character_slots(character);
+
+        let mut counts: HashMap<Rgba, usize> = HashMap::new();
+        for pixel in image.pixels().filter(|pixel| pixel[3] != 0) {
+            *counts.entry(Rgba::from(pixel.0)).or_default() += 1;
+        }
+
+        let mut colours: Vec<Rgba> = Vec::new();
+        for colour in slots.values() {
+            if counts.contains_key(colour) && !colours.contains(colour) {
+                colours.push(*colour);
+            }
+        }
+        let mut others: Vec<Rgba> = counts
+            .keys()
+            .filter(|colour| !colours.contains(colour))
+            .copied()
+            .collect();
+        others.sort_by_key(|colour| (std::cmp::Reverse(counts[colour]), <[u8; 4]>::from(*colour)));
+        colours.extend(others);
+
+        let limit = match max_colours {
+            Some(max_colours) => max_colours.clamp(1, MAX_PALETTE_SIZE - 1),
+            None if colours.len() < MAX_PALETTE_SIZE => colours.len(),
+            None => {
+                return Err(format!(
+                    "The image uses {} colours, but an indexed PNG can only hold {}.",
+                    colours.len(),
+                    MAX_PALETTE_SIZE - 1
+                ));
+            }
+        };
+
+        let labels = |colour: Rgba| -> Vec<(Colourable, Shade)> {
+            slots
+                .iter()
+                .filter(|(_, slot_colour)| **slot_colour == colour)
+                .map(|(slot, _)| *slot)
+                .collect()
+        };
+        let mut merges = Vec::new();
+        let mut merged_into: HashMap<Rgba, Rgba> = HashMap::new();
+        while colours.len() > limit {
+            let (a, b) = nearest_pair(&colours);
+            // The more used colour is kept, or the earlier one if they're used as much.
+            let (keep, remove) = if counts[&colours[b]] > counts[&colours[a]] {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            let (into, from) = (colours[keep], colours[remove]);
+            *counts.entry(into).or_default() += counts[&from];
+            for target in merged_into.values_mut().filter(|target| **target == from) {
+                *target = into;
+            }
+            merged_into.insert(from, into);
+            merges.push(ColourMerge {
+                from,
+                into,
+                merged: labels(from),
+                kept: labels(into),
+            });
+            colours.remove(remove);
+        }
+
+        let palette: Vec<Rgba> = std::iter::once(Rgba::TRANSPARENT).chain(colours).collect();
+        let index_of: HashMap<Rgba, u8> = palette
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(index, colour)| (*colour, index as u8))
+            .collect();
+        let indices = image
+            .pixels()
+            .map(|pixel| {
+                if pixel[3] == 0 {
+                    return 0;
+                }
+                let colour = Rgba::from(pixel.0);
+                index_of[merged_into.get(&colour).unwrap_or(&colour)]
+            })
+            .collect();
+
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            palette,
+            indices,
+            merges,
+        })
+    }
+
+    /// Encodes the image as an indexed PNG, with 4 bits per pixel if its palette fits, so GBA
+    /// tools can read it, or 8 otherwise.
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        let (depth, data) = if self.palette.len() <= 16 {
+            let row_bytes = self.width.div_ceil(2) as usize;
+            let mut data = vec![0; row_bytes * self.height as usize];
+            for (row, indices) in self.indices.chunks(self.width as usize).enumerate() {
+                for (x, index) in indices.iter().enumerate() {
+                    let shift = if x % 2 == 0 { 4 } else { 0 };
+                    data[row * row_bytes + x / 2] |= index << shift;
+                }
+            }
+            (png::BitDepth::Four, data)
+        } else {
+            (png::BitDepth::Eight, self.indices.clone())
+        };
+
+        let rgb: Vec<u8> = self
+            .palette
+            .iter()
+            .flat_map(|colour| [colour.r, colour.g, colour.b])
+            .collect();
+        let alpha: Vec<u8> = self.palette.iter().map(|colour| colour.a).collect();
+
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(depth);
+        encoder.set_palette(rgb);
+        encoder.set_trns(alpha);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&data).map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+}
+
+/// Returns the colour of each shade of each colourable used by the character's visible parts,
+/// including the outlines of their types.
+fn character_slots(character: &Character) -> BTreeMap<(Colourable, Shade), Rgba> {
+    let mut slots = BTreeMap::new();
+    for layer in character.layers.iter().filter(|layer| layer.visible) {
+        let Some(usage) = layer.part.asset.colour_usage() else {
+            continue;
+        };
+        for colourable in usage.colourables() {
+            for (shade, _) in usage.shades(colourable) {
+                let colour = if colourable == Colourable::Outline {
+                    character
+                        .outline_colours
+                        .get_outline_colour(layer.asset_type)
+                } else if let Some(colours) = character.character_colours.get(&colourable) {
+                    colours.shade(shade)
+                } else {
+                    continue;
+                };
+                slots.entry((colourable, shade)).or_insert(colour);
+            }
+        }
+    }
+    slots
+}
+
+/// Returns the indices of the two most similar colours, the lower index first.
+fn nearest_pair(colours: &[Rgba]) -> (usize, usize) {
+    let distance = |a: Rgba, b: Rgba| {
+        <[u8; 4]>::from(a)
+            .into_iter()
+            .zip(<[u8; 4]>::from(b))
+            .map(|(a, b)| u32::from(a.abs_diff(b)).pow(2))
+            .sum::<u32>()
+    };
+    let mut nearest = (0, 1);
+    let mut nearest_distance = u32::MAX;
+    for a in 0..colours.len() {
+        for b in a + 1..colours.len() {
+            let distance = distance(colours[a], colours[b]);
+            if distance < nearest_distance {
+                nearest = (a, b);
+                nearest_distance = distance;
+            }
+        }
+    }
+    nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::{Asset, AssetType};
+    use crate::character::{CharacterPart, Layer};
+    use crate::types::Point;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn character_with_hair() -> Character {
+        // Outline, and the light and base hair keys.
+        let mut art = RgbaImage::new(3, 1);
+        art.put_pixel(0, 0, image::Rgba([0, 0, 0, 255]));
+        art.put_pixel(1, 0, image::Rgba([10, 0, 0, 255]));
+        art.put_pixel(2, 0, image::Rgba([20, 0, 0, 255]));
+        let mut asset = Asset::new("Test".to_owned(), PathBuf::new(), None, AssetType::Hair);
+        asset.image_data = Some(Arc::new(art));
+
+        let mut character = Character::default();
+        character.add_layer(Layer::new(
+            AssetType::Hair,
+            CharacterPart {
+                position: Point::new(0.0, 0.0),
+                scale: 1.0,
+                rotation: 0.0,
+                flipped: false,
+                asset,
+            },
+        ));
+        character
+            .character_colours
+            .get_mut(&Colourable::Hair)
+            .unwrap()
+            .set(Rgba::new(200, 40, 40, 255), false);
+        character
+    }
+
+    #[test]
+    fn test_indexed_image_palette_order() {
+        let character = character_with_hair();
+        let hair = &character.character_colours[&Colourable::Hair];
+        let outline = character
+            .outline_colours
+            .get_outline_colour(AssetType::Hair);
+        let other = Rgba::new(1, 2, 3, 255);
+
+        let mut image = RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba(other.into()));
+        image.put_pixel(1, 0, image::Rgba(hair.neutral.into()));
+        image.put_pixel(0, 1, image::Rgba(outline.into()));
+
+        let indexed = IndexedImage::from_image(&image, &character, None).unwrap();
+        assert_eq!(
+            indexed.palette,
+            [Rgba::TRANSPARENT, hair.neutral, outline, other]
+        );
+        assert_eq!(indexed.indices, [3, 1, 2, 0]);
+        assert!(indexed.merges.is_empty());
+    }
+
+    #[test]
+    fn test_indexed_image_quantise() {
+        let character = character_with_hair();
+        let hair = &character.character_colours[&Colourable::Hair];
+
+        let mut image = RgbaImage::new(4, 1);
+        image.put_pixel(0, 0, image::Rgba(hair.lighter.into()));
+        image.put_pixel(1, 0, image::Rgba(hair.neutral.into()));
+        image.put_pixel(2, 0, image::Rgba(hair.neutral.into()));
+        image.put_pixel(3, 0, image::Rgba([0, 0, 255, 255]));
+
+        let indexed = IndexedImage::from_image(&image, &character, Some(2)).unwrap();
+        assert_eq!(indexed.palette.len(), 3);
+        assert_eq!(indexed.indices, [1, 1, 1, 2]);
+        assert_eq!(
+            indexed.merges,
+            [ColourMerge {
+                from: hair.lighter,
+                into: hair.neutral,
+                merged: vec![(Colourable::Hair, Shade::Lighter)],
+                kept: vec![(Colourable::Hair, Shade::Neutral)],
+            }]
+        );
+        assert_eq!(
+            indexed.merges[0].to_string(),
+            "Hair (light) merged into Hair (base)"
+        );
+    }
+
+    #[test]
+    fn test_indexed_image_too_many_colours() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+        let error = IndexedImage::from_image(&image, &Character::default(), None).unwrap_err();
+        assert!(error.contains("256 colours"), "{error}");
+
+        let indexed = IndexedImage::from_image(&image, &Character::default(), Some(255)).unwrap();
+        assert_eq!(indexed.palette.len(), MAX_PALETTE_SIZE);
+        assert_eq!(indexed.merges.len(), 1);
+    }
+
+    #[test]
+    fn test_indexed_image_to_png() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(0, 0, image::Rgba([10, 20, 30, 255]));
+        image.put_pixel(2, 1, image::Rgba([40, 50, 60, 128]));
+        let indexed = IndexedImage::from_image(&image, &Character::default(), None).unwrap();
+
+        let bytes = indexed.to_png().unwrap();
+        let decoder = png::Decoder::new(std::io::Cursor::new(&bytes));
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert_eq!(info.bit_depth, png::BitDepth::Four);
+        assert_eq!(info.trns.as_deref(), Some(&[0, 255, 128][..]));
+
+        // Decoding it gives the original image back.
+        assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), image);
+    }
+}
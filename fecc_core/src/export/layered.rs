@@ -0,0 +1,375 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::export::overlay;
+use image::{RgbaImage, imageops};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write as _};
+use strum_macros::{Display, EnumIter};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// The largest width or height of the thumbnail within an OpenRaster file.
+const ORA_THUMBNAIL_SIZE: u32 = 256;
+
+/// The sizes of the headers of an Aseprite file and its frames, and the chunk types used, see
+/// <https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md>.
+const ASEPRITE_HEADER_SIZE: usize = 128;
+const ASEPRITE_FRAME_HEADER_SIZE: usize = 16;
+const ASEPRITE_LAYER_CHUNK: u16 = 0x2004;
+const ASEPRITE_CEL_CHUNK: u16 = 0x2005;
+
+/// A file format that keeps the layers of a `LayeredImage`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Display, EnumIter)]
+pub enum LayeredFormat {
+    #[default]
+    OpenRaster,
+    Aseprite,
+}
+
+impl LayeredFormat {
+    /// Returns the file extension of the format, without the dot.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::OpenRaster => "ora",
+            Self::Aseprite => "aseprite",
+        }
+    }
+}
+
+/// An image of a character with each part on its own layer, as exported by
+/// `export::export_layers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayeredImage {
+    pub width: u32,
+    pub height: u32,
+    /// The layers from the bottom to the top, each the size of the whole image.
+    pub layers: Vec<ImageLayer>,
+}
+
+/// One part of a `LayeredImage`, recoloured and transformed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageLayer {
+    /// The part's asset name and type, e.g. `Red (HairBack)`.
+    pub name: String,
+    pub visible: bool,
+    pub image: RgbaImage,
+}
+
+impl LayeredImage {
+    /// Composites the visible layers into a single image, as `export::export_character` does.
+    pub fn flatten(&self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            overlay(&mut image, &layer.image, 0, 0);
+        }
+        image
+    }
+
+    /// Encodes the image in the given format.
+    pub fn encode(&self, format: LayeredFormat) -> Result<Vec<u8>, String> {
+        match format {
+            LayeredFormat::OpenRaster => self.to_openraster(),
+            LayeredFormat::Aseprite => self.to_aseprite(),
+        }
+    }
+
+    /// Encodes the image as an OpenRaster (.ora) file, which Krita, GIMP and MyPaint can open.
+    pub fn to_openraster(&self) -> Result<Vec<u8>, String> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+
+        // The mimetype has to come first and be stored uncompressed, so the format can be
+        // recognised from the start of the file.
+        writer
+            .start_file(
+                "mimetype",
+                options.compression_method(CompressionMethod::Stored),
+            )
+            .map_err(|e| e.to_string())?;
+        writer
+            .write_all(b"image/openraster")
+            .map_err(|e| e.to_string())?;
+
+        // The stack lists layers from the top down.
+        let mut stack = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <image version=\"0.0.6\" w=\"{}\" h=\"{}\">\n<stack>\n",
+            self.width, self.height
+        );
+        for (index, layer) in self.layers.iter().enumerate().rev() {
+            let src = format!("data/layer{index}.png");
+            stack += &format!(
+                "<layer name=\"{}\" src=\"{src}\" x=\"0\" y=\"0\" opacity=\"1.0\" \
+                 visibility=\"{}\"/>\n",
+                escape_xml(&layer.name),
+                if layer.visible { "visible" } else { "hidden" }
+            );
+            write_png(&mut writer, &src, &layer.image, options)?;
+        }
+        stack += "</stack>\n</image>\n";
+        writer
+            .start_file("stack.xml", options)
+            .map_err(|e| e.to_string())?;
+        writer
+            .write_all(stack.as_bytes())
+            .map_err(|e| e.to_string())?;
+
+        let merged = self.flatten();
+        let scale = (ORA_THUMBNAIL_SIZE as f32 / self.width.max(self.height) as f32).min(1.0);
+        let thumbnail = imageops::resize(
+            &merged,
+            ((self.width as f32 * scale) as u32).max(1),
+            ((self.height as f32 * scale) as u32).max(1),
+            imageops::FilterType::Nearest,
+        );
+        write_png(&mut writer, "mergedimage.png", &merged, options)?;
+        write_png(&mut writer, "Thumbnails/thumbnail.png", &thumbnail, options)?;
+
+        Ok(writer.finish().map_err(|e| e.to_string())?.into_inner())
+    }
+
+    /// Encodes the image as an Aseprite (.aseprite) file with a single frame.
+    ///
+    /// Each layer's cel is cropped to the pixels it draws. Fails if the image is too large for
+    /// the format.
+    pub fn to_aseprite(&self) -> Result<Vec<u8>, String> {
+        let too_large = |_| {
+            format!(
+                "A {}x{} image is too large for an Aseprite file",
+                self.width, self.height
+            )
+        };
+        let width = u16::try_from(self.width).map_err(too_large)?;
+        let height = u16::try_from(self.height).map_err(too_large)?;
+
+        let mut chunks = Vec::new();
+        for layer in &self.layers {
+            let mut chunk = Vec::new();
+            // Layers can always be edited, and may be visible.
+            let flags = 2 | u16::from(layer.visible);
+            chunk.extend(flags.to_le_bytes());
+            // A normal layer at the top level, with the normal blend mode and opacity.
+            chunk.extend([0; 10]);
+            chunk.push(u8::MAX);
+            chunk.extend([0; 3]);
+            let name = layer.name.as_bytes();
+            chunk.extend((name.len() as u16).to_le_bytes());
+            chunk.extend(name);
+            chunks.push((ASEPRITE_LAYER_CHUNK, chunk));
+        }
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            // Layers that draw nothing have no cel.
+            let Some((x, y, cel_width, cel_height)) = drawn_bounds(&layer.image) else {
+                continue;
+            };
+            let mut chunk = Vec::new();
+            chunk.extend((index as u16).to_le_bytes());
+            chunk.extend((x as i16).to_le_bytes());
+            chunk.extend((y as i16).to_le_bytes());
+            chunk.push(u8::MAX);
+            // A raw cel, at the layer's z-index.
+            chunk.extend([0; 2 + 2 + 5]);
+            chunk.extend((cel_width as u16).to_le_bytes());
+            chunk.extend((cel_height as u16).to_le_bytes());
+            chunk.extend(
+                imageops::crop_imm(&layer.image, x, y, cel_width, cel_height)
+                    .to_image()
+                    .into_raw(),
+            );
+            chunks.push((ASEPRITE_CEL_CHUNK, chunk));
+        }
+
+        let mut frame = Vec::new();
+        for (chunk_type, data) in &chunks {
+            frame.extend((data.len() as u32 + 6).to_le_bytes());
+            frame.extend(chunk_type.to_le_bytes());
+            frame.extend(data);
+        }
+
+        let frame_size = ASEPRITE_FRAME_HEADER_SIZE + frame.len();
+        let file_size = ASEPRITE_HEADER_SIZE + frame_size;
+        let mut bytes = Vec::with_capacity(file_size);
+
+        bytes.extend((file_size as u32).to_le_bytes());
+        bytes.extend(0xA5E0_u16.to_le_bytes());
+        // One frame, of the image's size, in RGBA.
+        bytes.extend(1_u16.to_le_bytes());
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend(32_u16.to_le_bytes());
+        // The layers' opacity is valid.
+        bytes.extend(1_u32.to_le_bytes());
+        bytes.extend(100_u16.to_le_bytes());
+        bytes.extend([0; 4 + 4 + 1 + 3 + 2]);
+        // Square pixels, and a 16x16 grid.
+        bytes.extend([1, 1]);
+        bytes.extend([0; 4]);
+        bytes.extend(16_u16.to_le_bytes());
+        bytes.extend(16_u16.to_le_bytes());
+        bytes.resize(ASEPRITE_HEADER_SIZE, 0);
+
+        bytes.extend((frame_size as u32).to_le_bytes());
+        bytes.extend(0xF1FA_u16.to_le_bytes());
+        bytes.extend((chunks.len() as u16).to_le_bytes());
+        // The frame's duration in milliseconds.
+        bytes.extend(100_u16.to_le_bytes());
+        bytes.extend([0; 2]);
+        bytes.extend((chunks.len() as u32).to_le_bytes());
+        bytes.extend(frame);
+
+        Ok(bytes)
+    }
+}
+
+/// Returns the position and size of the smallest rectangle holding every pixel that isn't fully
+/// transparent, or `None` if there are none.
+fn drawn_bounds(image: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let drawn = || {
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[3] != 0)
+    };
+    let left = drawn().map(|(x, ..)| x).min()?;
+    let right = drawn().map(|(x, ..)| x).max()?;
+    let top = drawn().map(|(_, y, _)| y).min()?;
+    let bottom = drawn().map(|(_, y, _)| y).max()?;
+    Some((left, top, right - left + 1, bottom - top + 1))
+}
+
+fn write_png(
+    writer: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    image: &RgbaImage,
+    options: SimpleFileOptions,
+) -> Result<(), String> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    writer
+        .start_file(name, options)
+        .map_err(|e| e.to_string())?;
+    writer.write_all(&png).map_err(|e| e.to_string())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::io::Read as _;
+    use zip::ZipArchive;
+
+    fn layered_image() -> LayeredImage {
+        let mut bottom = RgbaImage::new(8, 6);
+        bottom.put_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        bottom.put_pixel(4, 3, Rgba([255, 0, 0, 255]));
+        let top = RgbaImage::from_pixel(8, 6, Rgba([0, 0, 255, 255]));
+        LayeredImage {
+            width: 8,
+            height: 6,
+            layers: vec![
+                ImageLayer {
+                    name: "Red & Gold (HairBack)".to_owned(),
+                    visible: true,
+                    image: bottom,
+                },
+                ImageLayer {
+                    name: "Blue (Hair)".to_owned(),
+                    visible: false,
+                    image: top,
+                },
+                ImageLayer {
+                    name: "Empty (Accessory)".to_owned(),
+                    visible: true,
+                    image: RgbaImage::new(8, 6),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_flatten_skips_hidden_layers() {
+        let image = layered_image();
+        assert_eq!(image.flatten(), image.layers[0].image);
+    }
+
+    #[test]
+    fn test_openraster() {
+        let image = layered_image();
+        let bytes = image.to_openraster().expect("Failed to write OpenRaster");
+        // Readers check for the uncompressed mimetype at a fixed offset.
+        assert_eq!(&bytes[30..38], b"mimetype");
+        assert_eq!(&bytes[38..54], b"image/openraster");
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).expect("Failed to open archive");
+        let mut stack = String::new();
+        archive
+            .by_name("stack.xml")
+            .expect("Missing stack")
+            .read_to_string(&mut stack)
+            .expect("Failed to read stack");
+        let empty = stack.find("Empty (Accessory)").expect("Missing layer");
+        let hidden = stack
+            .find("name=\"Blue (Hair)\" src=\"data/layer1.png\" x=\"0\" y=\"0\" opacity=\"1.0\" visibility=\"hidden\"")
+            .expect("Missing hidden layer");
+        let escaped = stack
+            .find("Red &amp; Gold (HairBack)")
+            .expect("Missing layer");
+        assert!(empty < hidden && hidden < escaped, "{stack}");
+
+        let mut png = Vec::new();
+        archive
+            .by_name("data/layer0.png")
+            .expect("Missing layer image")
+            .read_to_end(&mut png)
+            .expect("Failed to read layer image");
+        let layer = image::load_from_memory(&png).expect("Failed to decode layer image");
+        assert_eq!(layer.to_rgba8(), image.layers[0].image);
+        for name in ["mergedimage.png", "Thumbnails/thumbnail.png"] {
+            assert!(archive.by_name(name).is_ok(), "Missing {name}");
+        }
+    }
+
+    #[test]
+    fn test_aseprite() {
+        let image = layered_image();
+        let bytes = image.to_aseprite().expect("Failed to write Aseprite file");
+        let word = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let dword = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+        assert_eq!(dword(0) as usize, bytes.len());
+        assert_eq!(word(4), 0xA5E0);
+        assert_eq!((word(6), word(8), word(10), word(12)), (1, 8, 6, 32));
+        assert_eq!(dword(128) as usize, bytes.len() - 128);
+        assert_eq!(word(132), 0xF1FA);
+
+        // Three layers, then cels for the two that draw something.
+        let mut at = 144;
+        let mut chunks = Vec::new();
+        while at < bytes.len() {
+            chunks.push((word(at + 4), at + 6));
+            at += dword(at) as usize;
+        }
+        assert_eq!(at, bytes.len());
+        assert_eq!(
+            chunks.iter().map(|(kind, _)| *kind).collect::<Vec<_>>(),
+            [0x2004, 0x2004, 0x2004, 0x2005, 0x2005]
+        );
+        let (_, hidden_layer) = chunks[1];
+        assert_eq!(word(hidden_layer), 2);
+        assert_eq!(&bytes[hidden_layer + 18..hidden_layer + 29], b"Blue (Hair)");
+
+        // The first cel is cropped to the two red pixels.
+        let (_, cel) = chunks[3];
+        assert_eq!((word(cel), word(cel + 2), word(cel + 4)), (0, 2, 1));
+        assert_eq!((word(cel + 16), word(cel + 18)), (3, 3));
+        assert_eq!(&bytes[cel + 20..cel + 24], [255, 0, 0, 255]);
+    }
+}
@@ -1,8 +1,20 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
 use crate::types::Rgba;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
 
 const COLOUR_ADJUSTMENT_FACTOR: f32 = 0.7;
 
+/// The change in HSL lightness, from 0 to 1, between shades at the standard strength.
+const HSL_LIGHTNESS_STEP: f64 = 0.12;
+/// The change in OKLCH lightness, from 0 to 1, between shades at the standard strength.
+const OKLCH_LIGHTNESS_STEP: f64 = 0.1;
+/// How far, in degrees, the hue of each shade turns at the standard strength of `HueShift`.
+const HUE_SHIFT_STEP: f64 = 12.0;
+/// The OKLCH hues, in degrees, that shadows and highlights turn towards with `HueShift`.
+const SHADOW_HUE: f64 = 264.0;
+const HIGHLIGHT_HUE: f64 = 100.0;
+
 pub trait AdjustBrightness {
     fn brighter(&self) -> Self;
     fn darker(&self) -> Self;
@@ -10,42 +22,255 @@ pub trait AdjustBrightness {
 
 impl AdjustBrightness for Rgba {
     fn brighter(&self) -> Self {
-        const MIN_BRIGHT: u8 = (1.0 / (1.0 - COLOUR_ADJUSTMENT_FACTOR)) as u8;
+        brighter_by(self, COLOUR_ADJUSTMENT_FACTOR)
+    }
 
-        // Special case 1: Rgba::BLACK.brighter() should return a dark grey.
-        if self.r == 0 && self.g == 0 && self.b == 0 {
-            return Self::new(MIN_BRIGHT, MIN_BRIGHT, MIN_BRIGHT, self.a);
-        }
+    fn darker(&self) -> Self {
+        darker_by(self, COLOUR_ADJUSTMENT_FACTOR)
+    }
+}
 
-        let mut r = self.r;
-        let mut g = self.g;
-        let mut b = self.b;
+/// How the lighter and darker shades of a colour are derived from it.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, EnumIter, Display, Serialize, Deserialize,
+)]
+pub enum RampStrategy {
+    /// Scales the red, green and blue channels, which turns saturated colours muddy as they darken.
+    #[default]
+    #[strum(to_string = "Linear RGB")]
+    LinearRgb,
+    /// Steps the HSL lightness, keeping the hue and saturation.
+    #[strum(to_string = "HSL Lightness")]
+    HslLightness,
+    /// Steps the perceived lightness in OKLCH, keeping the chroma and hue.
+    #[strum(to_string = "OKLCH Lightness")]
+    OklchLightness,
+    /// Steps the OKLCH lightness, turning shadows towards blue and highlights towards yellow as
+    /// in official Fire Emblem art.
+    #[strum(to_string = "Hue Shift")]
+    HueShift,
+}
 
-        // Special case 2: Boost very dark colours.
-        if r > 0 && r < MIN_BRIGHT {
-            r = MIN_BRIGHT;
-        }
-        if g > 0 && g < MIN_BRIGHT {
-            g = MIN_BRIGHT;
+/// A strategy for deriving the shades of a colour, and how far apart they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColourRamp {
+    pub strategy: RampStrategy,
+    /// The step between shades as a percentage of the strategy's standard step, up to
+    /// `MAX_STRENGTH`.
+    pub strength: u8,
+}
+
+impl Default for ColourRamp {
+    fn default() -> Self {
+        Self {
+            strategy: RampStrategy::LinearRgb,
+            strength: 100,
         }
-        if b > 0 && b < MIN_BRIGHT {
-            b = MIN_BRIGHT;
+    }
+}
+
+impl ColourRamp {
+    pub const MAX_STRENGTH: u8 = 200;
+
+    pub const fn new(strategy: RampStrategy, strength: u8) -> Self {
+        Self { strategy, strength }
+    }
+
+    fn strength(&self) -> f64 {
+        f64::from(self.strength.min(Self::MAX_STRENGTH)) / 100.0
+    }
+
+    /// Returns the next lighter shade of a colour.
+    pub fn lighter(&self, colour: &Rgba) -> Rgba {
+        self.shade(colour, 1.0)
+    }
+
+    /// Returns the next darker shade of a colour.
+    pub fn darker(&self, colour: &Rgba) -> Rgba {
+        self.shade(colour, -1.0)
+    }
+
+    /// Steps a colour lighter for a positive direction, or darker for a negative one.
+    fn shade(&self, colour: &Rgba, direction: f64) -> Rgba {
+        let strength = self.strength();
+        match self.strategy {
+            RampStrategy::LinearRgb => {
+                let factor = COLOUR_ADJUSTMENT_FACTOR.powf(strength as f32);
+                if direction > 0.0 {
+                    brighter_by(colour, factor)
+                } else {
+                    darker_by(colour, factor)
+                }
+            }
+            RampStrategy::HslLightness => {
+                let [hue, saturation, lightness] = to_hsl(colour);
+                let lightness = lightness + direction * HSL_LIGHTNESS_STEP * strength;
+                from_hsl([hue, saturation, lightness.clamp(0.0, 1.0)], colour.a)
+            }
+            RampStrategy::OklchLightness => {
+                let [lightness, chroma, hue] = to_oklch(colour);
+                let lightness = lightness + direction * OKLCH_LIGHTNESS_STEP * strength;
+                from_oklch([lightness.clamp(0.0, 1.0), chroma, hue], colour.a)
+            }
+            RampStrategy::HueShift => {
+                let [lightness, chroma, hue] = to_oklch(colour);
+                let lightness = lightness + direction * OKLCH_LIGHTNESS_STEP * strength;
+                let target = if direction > 0.0 {
+                    HIGHLIGHT_HUE
+                } else {
+                    SHADOW_HUE
+                };
+                let hue = turn_towards(hue, target, HUE_SHIFT_STEP * strength);
+                from_oklch([lightness.clamp(0.0, 1.0), chroma, hue], colour.a)
+            }
         }
+    }
+}
+
+/// Turns a hue towards a target hue the shorter way round, by at most `step` degrees.
+fn turn_towards(hue: f64, target: f64, step: f64) -> f64 {
+    let difference = (target - hue + 540.0).rem_euclid(360.0) - 180.0;
+    (hue + difference.clamp(-step, step)).rem_euclid(360.0)
+}
 
-        let new_r = (r as f32 / COLOUR_ADJUSTMENT_FACTOR).min(255.0) as u8;
-        let new_g = (g as f32 / COLOUR_ADJUSTMENT_FACTOR).min(255.0) as u8;
-        let new_b = (b as f32 / COLOUR_ADJUSTMENT_FACTOR).min(255.0) as u8;
+fn to_unit(channel: u8) -> f64 {
+    f64::from(channel) / 255.0
+}
 
-        Self::new(new_r, new_g, new_b, self.a)
+fn from_unit(channel: f64) -> u8 {
+    (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Converts a colour to hue in degrees, saturation and lightness, each from 0 to 1.
+fn to_hsl(colour: &Rgba) -> [f64; 3] {
+    let [r, g, b] = [colour.r, colour.g, colour.b].map(to_unit);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return [0.0, 0.0, lightness];
     }
 
-    fn darker(&self) -> Self {
-        let new_r = (self.r as f32 * COLOUR_ADJUSTMENT_FACTOR) as u8;
-        let new_g = (self.g as f32 * COLOUR_ADJUSTMENT_FACTOR) as u8;
-        let new_b = (self.b as f32 * COLOUR_ADJUSTMENT_FACTOR) as u8;
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    [hue * 60.0, saturation, lightness]
+}
+
+fn from_hsl([hue, saturation, lightness]: [f64; 3], alpha: u8) -> Rgba {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let [r, g, b] = match sector as u8 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    let m = lightness - chroma / 2.0;
+    Rgba::new(from_unit(r + m), from_unit(g + m), from_unit(b + m), alpha)
+}
+
+fn to_linear(channel: u8) -> f64 {
+    let channel = to_unit(channel);
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(channel: f64) -> u8 {
+    let channel = channel.clamp(0.0, 1.0);
+    from_unit(if channel <= 0.003_130_8 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    })
+}
+
+/// Converts a colour to OKLCH lightness from 0 to 1, chroma, and hue in degrees.
+fn to_oklch(colour: &Rgba) -> [f64; 3] {
+    let [r, g, b] = [colour.r, colour.g, colour.b].map(to_linear);
+    let l = (0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b).cbrt();
+    let m = (0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b).cbrt();
+    let s = (0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b).cbrt();
+
+    let lightness = 0.210_454_255_3 * l + 0.793_617_785 * m - 0.004_072_046_8 * s;
+    let a = 1.977_998_495_1 * l - 2.428_592_205 * m + 0.450_593_709_9 * s;
+    let b = 0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766 * s;
+    [
+        lightness,
+        a.hypot(b),
+        b.atan2(a).to_degrees().rem_euclid(360.0),
+    ]
+}
+
+fn from_oklch([lightness, chroma, hue]: [f64; 3], alpha: u8) -> Rgba {
+    let (sin, cos) = hue.to_radians().sin_cos();
+    let (a, b) = (chroma * cos, chroma * sin);
+    let l = (lightness + 0.396_337_777_4 * a + 0.215_803_757_3 * b).powi(3);
+    let m = (lightness - 0.105_561_345_8 * a - 0.063_854_172_8 * b).powi(3);
+    let s = (lightness - 0.089_484_177_5 * a - 1.291_485_548 * b).powi(3);
+
+    Rgba::new(
+        from_linear(4.076_741_662_1 * l - 3.307_711_591_3 * m + 0.230_969_929_2 * s),
+        from_linear(-1.268_438_004_6 * l + 2.609_757_401_1 * m - 0.341_319_396_5 * s),
+        from_linear(-0.004_196_086_3 * l - 0.703_418_614_7 * m + 1.707_614_701 * s),
+        alpha,
+    )
+}
+
+/// Brightens a colour by dividing its channels by a factor below 1.
+fn brighter_by(colour: &Rgba, factor: f32) -> Rgba {
+    if factor >= 1.0 {
+        return *colour;
+    }
+    let min_bright = (1.0 / (1.0 - factor)) as u8;
+
+    // Special case 1: Rgba::BLACK.brighter() should return a dark grey.
+    if colour.r == 0 && colour.g == 0 && colour.b == 0 {
+        return Rgba::new(min_bright, min_bright, min_bright, colour.a);
+    }
 
-        Self::new(new_r, new_g, new_b, self.a)
+    let mut r = colour.r;
+    let mut g = colour.g;
+    let mut b = colour.b;
+
+    // Special case 2: Boost very dark colours.
+    if r > 0 && r < min_bright {
+        r = min_bright;
+    }
+    if g > 0 && g < min_bright {
+        g = min_bright;
     }
+    if b > 0 && b < min_bright {
+        b = min_bright;
+    }
+
+    let new_r = (r as f32 / factor).min(255.0) as u8;
+    let new_g = (g as f32 / factor).min(255.0) as u8;
+    let new_b = (b as f32 / factor).min(255.0) as u8;
+
+    Rgba::new(new_r, new_g, new_b, colour.a)
+}
+
+/// Darkens a colour by multiplying its channels by a factor below 1.
+fn darker_by(colour: &Rgba, factor: f32) -> Rgba {
+    let new_r = (colour.r as f32 * factor) as u8;
+    let new_g = (colour.g as f32 * factor) as u8;
+    let new_b = (colour.b as f32 * factor) as u8;
+
+    Rgba::new(new_r, new_g, new_b, colour.a)
 }
 
 #[cfg(test)]
@@ -82,6 +307,78 @@ mod tests {
         assert_eq!(c.darker(), Rgba::new(31, 105, 97, 255));
     }
 
+    #[test]
+    fn test_default_ramp_matches_adjust_brightness() {
+        let ramp = ColourRamp::default();
+        let c = Rgba::new(82, 29, 255, 255);
+        assert_eq!(ramp.lighter(&c), c.brighter());
+        assert_eq!(ramp.darker(&c), c.darker());
+        assert_eq!(ramp.lighter(&Rgba::BLACK), Rgba::BLACK.brighter());
+    }
+
+    #[test]
+    fn test_ramp_strength() {
+        let c = Rgba::new(100, 150, 200, 255);
+        let none = ColourRamp::new(RampStrategy::LinearRgb, 0);
+        assert_eq!(none.lighter(&c), c);
+        assert_eq!(none.darker(&c), c);
+
+        // A double strength step is about as far as two standard steps.
+        let double = ColourRamp::new(RampStrategy::LinearRgb, 200).darker(&c);
+        let twice = c.darker().darker();
+        for (a, b) in [
+            (double.r, twice.r),
+            (double.g, twice.g),
+            (double.b, twice.b),
+        ] {
+            assert!(a.abs_diff(b) <= 1, "{double:?} is not close to {twice:?}");
+        }
+    }
+
+    #[test]
+    fn test_hsl_round_trip() {
+        for c in [
+            Rgba::new(82, 29, 255, 255),
+            Rgba::new(200, 120, 40, 128),
+            Rgba::new(90, 90, 90, 255),
+        ] {
+            assert_eq!(from_hsl(to_hsl(&c), c.a), c);
+            assert_eq!(from_oklch(to_oklch(&c), c.a), c);
+        }
+    }
+
+    #[test]
+    fn test_lightness_ramps_keep_hue() {
+        let c = Rgba::new(200, 40, 40, 255);
+        for strategy in [RampStrategy::HslLightness, RampStrategy::OklchLightness] {
+            let ramp = ColourRamp::new(strategy, 100);
+            let (lighter, darker) = (ramp.lighter(&c), ramp.darker(&c));
+            assert!(to_hsl(&lighter)[2] > to_hsl(&c)[2], "{strategy} lighter");
+            assert!(to_hsl(&darker)[2] < to_hsl(&c)[2], "{strategy} darker");
+            // Still red, rather than muddy brown.
+            assert!(darker.r > darker.g * 2 && darker.g.abs_diff(darker.b) <= 2);
+        }
+    }
+
+    #[test]
+    fn test_hue_shift_ramp() {
+        let c = Rgba::new(60, 160, 60, 255);
+        let ramp = ColourRamp::new(RampStrategy::HueShift, 100);
+        let hue = to_oklch(&c)[2];
+
+        // Green turns towards yellow in highlights and towards blue in shadows.
+        assert!(to_oklch(&ramp.lighter(&c))[2] < hue);
+        assert!(to_oklch(&ramp.darker(&c))[2] > hue);
+        assert!(to_oklch(&ramp.darker(&c))[0] < to_oklch(&c)[0]);
+    }
+
+    #[test]
+    fn test_turn_towards() {
+        assert_eq!(turn_towards(350.0, 10.0, 5.0), 355.0);
+        assert_eq!(turn_towards(10.0, 350.0, 30.0), 350.0);
+        assert_eq!(turn_towards(100.0, 264.0, 12.0), 112.0);
+    }
+
     #[test]
     fn test_darker_zero() {
         let c = Rgba::new(0, 0, 0, 255);
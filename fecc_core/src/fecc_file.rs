@@ -0,0 +1,290 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::character::Character;
+use crate::types::Point;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// The version of the `.fecc` format written by this build.
+pub const CURRENT_FORMAT_VERSION: u32 = 2;
+
+/// Upgrades a `.fecc` document by one format version.
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// Migrations indexed by the format version they upgrade from.
+const MIGRATIONS: [Migration; CURRENT_FORMAT_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// The coordinate space a saved character's part positions and scales are stored in.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum CoordinateSpace {
+    /// Positions and scales are fractions of the canvas size.
+    Normalised,
+    /// Positions and scales are in pixels of canvases of the given sizes.
+    Canvas {
+        portrait_size: Point,
+        token_size: Point,
+    },
+}
+
+/// The versioned envelope around a `Character` saved as a `.fecc` file.
+#[derive(Serialize, Deserialize)]
+pub struct FeccFile {
+    pub format_version: u32,
+    pub coordinate_space: CoordinateSpace,
+    /// The version of the application that wrote the file.
+    pub app_version: String,
+    pub character: Character,
+}
+
+impl FeccFile {
+    pub fn new(character: Character, coordinate_space: CoordinateSpace, app_version: &str) -> Self {
+        Self {
+            format_version: CURRENT_FORMAT_VERSION,
+            coordinate_space,
+            app_version: app_version.to_owned(),
+            character,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    /// Parses a `.fecc` file of any known format version, migrating it to the current one.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let value = migrate(value)?;
+        let mut file: Self = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        // Colourables added since the file was saved are given unsnapped default colours.
+        if file.character.gba_colours {
+            file.character.set_gba_colours(true);
+        }
+        Ok(file)
+    }
+
+    /// Returns the character with its part positions and scales in normalised coordinates.
+    pub fn into_normalised_character(self) -> Character {
+        let mut character = self.character;
+        if let CoordinateSpace::Canvas {
+            portrait_size,
+            token_size,
+        } = self.coordinate_space
+        {
+            character.normalise(portrait_size, token_size);
+        }
+        character
+    }
+}
+
+/// Reads the format version of a `.fecc` document.
+///
+/// Files written before the envelope was introduced have no version and are treated as version 0.
+fn format_version(value: &Value) -> Result<u32, String> {
+    match value.get("format_version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("Invalid format version: {version}")),
+    }
+}
+
+/// Applies each migration in turn until the document is at `CURRENT_FORMAT_VERSION`.
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let mut version = format_version(&value)?;
+    if version > CURRENT_FORMAT_VERSION {
+        return Err(format!(
+            "File format version {version} is newer than the supported version {CURRENT_FORMAT_VERSION}"
+        ));
+    }
+
+    while version < CURRENT_FORMAT_VERSION {
+        value = MIGRATIONS[version as usize](value)
+            .map_err(|e| format!("Failed to migrate from format version {version}: {e}"))?;
+        version += 1;
+    }
+
+    Ok(value)
+}
+
+/// Version 0 files are a bare `Character`, which was always saved with normalised positions.
+fn migrate_v0_to_v1(value: Value) -> Result<Value, String> {
+    if !value.is_object() {
+        return Err("Expected a character object".to_owned());
+    }
+
+    Ok(json!({
+        "format_version": 1,
+        "coordinate_space": "Normalised",
+        "app_version": "unknown",
+        "character": value,
+    }))
+}
+
+/// Version 1 characters had one optional field per part, which become layers in the default
+/// drawing order.
+fn migrate_v1_to_v2(mut value: Value) -> Result<Value, String> {
+    const PART_FIELDS: [(&str, &str); 6] = [
+        ("hair_back", "HairBack"),
+        ("armour", "Armour"),
+        ("face", "Face"),
+        ("hair", "Hair"),
+        ("accessory", "Accessory"),
+        ("token", "Token"),
+    ];
+
+    let character = value
+        .get_mut("character")
+        .and_then(Value::as_object_mut)
+        .ok_or("Expected a character object")?;
+
+    let mut layers = Vec::new();
+    for (field, asset_type) in PART_FIELDS {
+        if let Some(part) = character.remove(field)
+            && !part.is_null()
+        {
+            layers.push(json!({
+                "asset_type": asset_type,
+                "visible": true,
+                "part": part,
+            }));
+        }
+    }
+    character.insert("layers".to_owned(), Value::Array(layers));
+    value["format_version"] = json!(2);
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetType;
+    use crate::character::Colourable;
+    use crate::types::Rgba;
+
+    const V0_FIXTURE: &str = include_str!("../fixtures/fecc_v0.fecc");
+    const V1_FIXTURE: &str = include_str!("../fixtures/fecc_v1.fecc");
+
+    #[test]
+    fn test_load_v0_fixture() {
+        let file = FeccFile::from_json(V0_FIXTURE).expect("Failed to load v0 fixture");
+
+        assert_eq!(file.format_version, CURRENT_FORMAT_VERSION);
+        assert_eq!(file.coordinate_space, CoordinateSpace::Normalised);
+        assert_eq!(file.app_version, "unknown");
+        assert_eq!(file.character.name, "Fixture");
+
+        let face = file
+            .character
+            .get_character_part(&AssetType::Face)
+            .expect("Face missing");
+        assert_eq!(face.asset.id, "Aias_Face");
+        assert_eq!(face.position, Point::new(0.5, 0.5));
+        assert_eq!(
+            file.character.character_colours[&Colourable::Hair].base,
+            Rgba::new(224, 216, 64, 255)
+        );
+    }
+
+    #[test]
+    fn test_load_v1_fixture() {
+        let file = FeccFile::from_json(V1_FIXTURE).expect("Failed to load v1 fixture");
+
+        assert_eq!(file.app_version, "0.1.0");
+        assert_eq!(
+            file.coordinate_space,
+            CoordinateSpace::Canvas {
+                portrait_size: Point::new(200.0, 200.0),
+                token_size: Point::new(100.0, 100.0),
+            }
+        );
+
+        let character = file.into_normalised_character();
+        let face = character
+            .get_character_part(&AssetType::Face)
+            .expect("Face missing");
+        assert_eq!(face.position, Point::new(0.5, 0.25));
+        assert_eq!(face.scale, 0.01);
+        let token = character
+            .get_character_part(&AssetType::Token)
+            .expect("Token missing");
+        assert_eq!(token.position, Point::new(0.5, 0.5));
+    }
+
+    #[test]
+    fn test_v1_parts_become_layers() {
+        let character = FeccFile::from_json(V1_FIXTURE)
+            .expect("Failed to load v1 fixture")
+            .character;
+
+        let layers: Vec<_> = character
+            .layers
+            .iter()
+            .map(|layer| {
+                (
+                    layer.asset_type,
+                    layer.part.asset.id.as_str(),
+                    layer.visible,
+                )
+            })
+            .collect();
+        assert_eq!(
+            layers,
+            vec![
+                (AssetType::Face, "Aias_Face", true),
+                (AssetType::Token, "Mercenary_Token", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let character = Character {
+            name: "Round Trip".to_owned(),
+            ..Default::default()
+        };
+        let file = FeccFile::new(character, CoordinateSpace::Normalised, "1.2.3");
+
+        let json = file.to_json().expect("Failed to serialise");
+        let loaded = FeccFile::from_json(&json).expect("Failed to load");
+
+        assert_eq!(loaded.format_version, CURRENT_FORMAT_VERSION);
+        assert_eq!(loaded.app_version, "1.2.3");
+        assert_eq!(loaded.character.name, "Round Trip");
+        assert!(
+            loaded
+                .character
+                .get_character_part(&AssetType::Face)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_gba_colours_snapped_on_load() {
+        let json = r#"{"format_version": 2, "coordinate_space": "Normalised", "app_version": "1",
+            "character": {"gba_colours": true, "character_colours": {}}}"#;
+        let character = FeccFile::from_json(json)
+            .expect("Failed to load")
+            .into_normalised_character();
+
+        assert!(character.gba_colours);
+        let hair = &character.character_colours[&Colourable::Hair];
+        assert_eq!(hair.neutral, hair.neutral.to_gba());
+        assert_ne!(
+            hair.neutral,
+            Character::default().character_colours[&Colourable::Hair].neutral
+        );
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let json = r#"{"format_version": 999, "coordinate_space": "Normalised", "app_version": "9", "character": {}}"#;
+        assert!(FeccFile::from_json(json).is_err());
+    }
+
+    #[test]
+    fn test_invalid_v0_rejected() {
+        assert!(FeccFile::from_json("[1, 2, 3]").is_err());
+    }
+}
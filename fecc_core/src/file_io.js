@@ -39,9 +39,9 @@ export async function fetch_image_bytes(url) {
 }
 
 /**
- * Fetches the list of asset files from a manifest.
+ * Fetches the lists of asset files and metadata sidecars from a manifest.
  * @param {string} url - The URL of the asset manifest JSON file.
- * @returns {Promise<string[]|null>} A promise that resolves with an array of file paths, or null if an error occurs.
+ * @returns {Promise<{files: string[], metadata: string[]}|null>} A promise that resolves with the manifest, or null if an error occurs.
  */
 export async function fetch_asset_list(url) {
     try {
@@ -50,8 +50,7 @@ export async function fetch_asset_list(url) {
             console.error(`HTTP error! status: ${response.status} for ${url}`);
             return null;
         }
-        const data = await response.json();
-        return data.files;
+        return await response.json();
     } catch (e) {
         console.error(`Failed to fetch asset list at ${url}:`, e);
         return null;
@@ -69,6 +68,8 @@ export function trigger_download(bytes, filename) {
         let mime_type = "application/octet-stream";
         if (filename.endsWith(".png")) {
             mime_type = "image/png";
+        } else if (filename.endsWith(".txt")) {
+            mime_type = "text/plain";
         }
 
         const blob = new Blob([bytes], {type: mime_type});
@@ -1,6 +1,10 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::asset::category::AssetCategory;
+use crate::asset::metadata::AssetMetadata;
 use crate::asset::{Asset, AssetType};
+use crate::recolour::scheme::KeySlot;
 use crate::types::Rgba;
+use asset_root::AssetRoot;
 use indexmap::IndexMap;
 #[cfg(target_arch = "wasm32")]
 use js_sys;
@@ -8,84 +12,263 @@ use js_sys;
 use serde_wasm_bindgen;
 use std::collections::HashMap;
 use std::error::Error;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
-/// Asynchronously loads all character assets from the `art` directory into libraries. (Be it local or remote)
+pub mod asset_root;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watcher;
+
+/// The file, within `assets/csv`, that defines asset categories beyond the built-in ones.
+const ASSET_CATEGORIES_FILE: &str = "asset_categories.csv";
+/// The file, within `assets/csv`, that defines the red channel keys of each recolour scheme.
+const RECOLOUR_SCHEMES_FILE: &str = "recolour_schemes.csv";
+
+/// Asynchronously loads all character assets from the given roots into libraries. (Be it local or remote)
 ///
 /// Handles asset loading for both native and WebAssembly (WASM) builds.
-/// For native builds, it scans the `art` directory directly. For WASM, it fetches a
-/// manifest file and then loads the assets listed within it.
-pub async fn load_asset_libraries()
--> Result<HashMap<AssetType, IndexMap<String, Asset>>, Box<dyn Error + Send + Sync>> {
-    let mut asset_libraries: HashMap<AssetType, IndexMap<String, Asset>> = [
-        (AssetType::Armour, IndexMap::new()),
-        (AssetType::Face, IndexMap::new()),
-        (AssetType::Hair, IndexMap::new()),
-        (AssetType::HairBack, IndexMap::new()),
-        (AssetType::Accessory, IndexMap::new()),
-        (AssetType::Token, IndexMap::new()),
-    ]
-    .into_iter()
-    .collect();
-
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        let path_pattern = "art/*.png";
-        for path in glob::glob(path_pattern)
-            .expect("Failed to read glob pattern")
-            .flatten()
-        {
-            add_asset_to_library(&mut asset_libraries, &path);
+/// For native builds, it scans each root directly, highest priority first, see `AssetRoot`. For
+/// WASM, it fetches a manifest file and then loads the bundled assets listed within it.
+///
+/// The asset categories and recolour schemes files are loaded and registered first, so there is a
+/// library for every category and art of user-defined categories is recognised and recoloured.
+pub async fn load_asset_libraries(
+    roots: &[AssetRoot],
+) -> Result<HashMap<AssetType, IndexMap<String, Asset>>, Box<dyn Error + Send + Sync>> {
+    match load_asset_categories().await {
+        Ok(categories) => AssetCategory::register(categories),
+        Err(e) => log::warn!("Using the built-in asset categories only: {e}"),
+    }
+    match load_recolour_schemes().await {
+        Ok(slots) => KeySlot::register(slots),
+        Err(e) => log::warn!("Using the built-in recolour schemes only: {e}"),
+    }
+    for category in AssetCategory::all() {
+        if category.recolour_scheme.layout(true).is_empty() {
+            log::warn!(
+                "{} art won't be recoloured, as the {} recolour scheme has no keys",
+                category.asset_type,
+                category.recolour_scheme
+            );
         }
     }
 
+    let mut asset_libraries: HashMap<AssetType, IndexMap<String, Asset>> = AssetType::all()
+        .map(|asset_type| (asset_type, IndexMap::new()))
+        .collect();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    load_asset_roots(roots, &mut asset_libraries, &HashMap::new());
+
     #[cfg(target_arch = "wasm32")]
     {
-        let asset_list_val = wasm::fetch_asset_list("assets/asset_manifest.json")
+        for root in roots.iter().filter(|&root| *root != AssetRoot::bundled()) {
+            log::warn!("Art can't be loaded from {:?} on the web", root.path());
+        }
+
+        let manifest_val = wasm::fetch_asset_list("assets/asset_manifest.json")
             .await
             .map_err(|e| e.as_string().unwrap_or_else(|| "JS error".to_string()))?;
 
-        let files: Vec<String> =
-            serde_wasm_bindgen::from_value(asset_list_val).map_err(|e| e.to_string())?;
+        let manifest: AssetManifest =
+            serde_wasm_bindgen::from_value(manifest_val).map_err(|e| e.to_string())?;
+
+        // Only sidecars listed in the manifest are fetched, to avoid a failed request per asset.
+        let mut sidecars: HashMap<String, AssetMetadata> = HashMap::new();
+        for filename in manifest.metadata {
+            if let Some(metadata) = fetch_metadata(&filename).await {
+                sidecars.insert(filename, metadata);
+            }
+        }
+        let folder_metadata = sidecars
+            .remove(AssetMetadata::FOLDER_FILE)
+            .unwrap_or_default();
 
-        for filename in files {
+        for filename in manifest.files {
             let path = std::path::PathBuf::from(format!("art/{}", filename));
-            add_asset_to_library(&mut asset_libraries, &path);
+            let metadata = sidecars
+                .remove(&format!("{}.json", filename.trim_end_matches(".png")))
+                .unwrap_or_default()
+                .merged_with(&folder_metadata);
+            add_asset_to_library(&mut asset_libraries, &path, metadata, None, || Ok(None));
         }
     }
 
     Ok(asset_libraries)
 }
 
-/// Parses an asset from a path and adds it to the appropriate library.
+/// Loads the art in the roots into the libraries, highest priority first.
+///
+/// Art whose path is in `known_hashes` isn't read, see `AssetRoot::load`.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_asset_roots(
+    roots: &[AssetRoot],
+    asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
+    known_hashes: &HashMap<std::path::PathBuf, String>,
+) {
+    for root in roots {
+        if let Err(e) = root.load(asset_libraries, known_hashes) {
+            log::warn!("Failed to load art from {:?}: {e}", root.path());
+        }
+    }
+}
+
+/// The list of art files the web build can fetch, generated by `fecc_gui/build.rs`.
+#[cfg(target_arch = "wasm32")]
+#[derive(serde::Deserialize)]
+struct AssetManifest {
+    files: Vec<String>,
+    /// The metadata sidecars in the art directory.
+    #[serde(default)]
+    metadata: Vec<String>,
+}
+
+/// Reads a metadata sidecar, returning `None` if there isn't one or it is invalid.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn read_metadata(path: &Path) -> Option<AssetMetadata> {
+    let json = std::fs::read_to_string(path).ok()?;
+    AssetMetadata::from_json(&json)
+        .inspect_err(|e| log::warn!("Ignoring {path:?}: {e}"))
+        .ok()
+}
+
+/// Fetches a metadata sidecar from the art directory, returning `None` if it is invalid.
+#[cfg(target_arch = "wasm32")]
+async fn fetch_metadata(filename: &str) -> Option<AssetMetadata> {
+    let json = wasm::fetch_text(&format!("art/{filename}"))
+        .await
+        .ok()?
+        .as_string()?;
+    AssetMetadata::from_json(&json)
+        .inspect_err(|e| log::warn!("Ignoring art/{filename}: {e}"))
+        .ok()
+}
+
+/// Parses an asset from a path and adds it to the appropriate library, along with its metadata, the
+/// pack it came from and the hash of its contents, see `Asset::content_hash`.
+///
+/// Images are decoded when the asset is first drawn. Native art is hashed as its library loads, but
+/// the web build only fetches art when it is drawn, so its assets are hashed then, see
+/// `Asset::load_image`. Until then they can't be resolved by hash, so a renamed asset is only found
+/// once its thumbnail has loaded.
+///
+/// Assets whose id is already in the library are skipped, as they were loaded from a root with a
+/// higher priority. `hash_contents` is only called for assets that are added, so that overridden art
+/// isn't read.
 fn add_asset_to_library(
     asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
-    path: &PathBuf,
+    path: &Path,
+    metadata: AssetMetadata,
+    pack: Option<String>,
+    hash_contents: impl FnOnce() -> Result<Option<String>, Box<dyn Error + Send + Sync>>,
 ) {
-    match Asset::try_from(path.as_path()) {
-        Ok(asset) => {
-            if let Some(library) = asset_libraries.get_mut(&asset.asset_type) {
-                library.insert(asset.id.clone(), asset);
-            }
+    let mut asset = match Asset::try_from(path) {
+        Ok(asset) => Asset {
+            metadata,
+            pack,
+            ..asset
+        },
+        Err(e) => {
+            log::warn!("Skipping file {path:?}: {e}");
+            return;
         }
+    };
+
+    let Some(library) = asset_libraries.get_mut(&asset.asset_type) else {
+        return;
+    };
+    if let Some(existing) = library.get(&asset.id) {
+        log::debug!("{path:?} is overridden by {:?}", existing.path);
+        return;
+    }
+    match hash_contents() {
+        Ok(content_hash) => asset.content_hash = content_hash,
         Err(e) => {
             log::warn!("Skipping file {path:?}: {e}");
+            return;
         }
     }
+    library.insert(asset.id.clone(), asset);
 }
 
-/// Asynchronously loads a list of colours from a CSV file.
+/// Adds the art of a loaded bundle to the appropriate libraries, see `FeccBundle`.
+///
+/// Bundled art that is already in a library is skipped. Where a library has other art under the
+/// same id, such as the recipient's own version of it, the bundled asset is added under an id
+/// scoped to its contents instead, e.g. `Long_Hair@<hash>`, so that the character resolves to it
+/// by hash rather than to the other art. Links to companion parts are updated to match.
+pub fn register_bundled_assets(
+    asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
+    assets: Vec<Asset>,
+) {
+    let mut scoped_ids: HashMap<String, String> = HashMap::new();
+    let mut added: Vec<Asset> = Vec::new();
+    for mut asset in assets {
+        let library = asset_libraries.entry(asset.asset_type).or_default();
+        if let Some(existing) = library.get(&asset.id)
+            && existing.content_hash != asset.content_hash
+            && let Some(hash) = &asset.content_hash
+        {
+            let id = format!("{}@{hash}", asset.id);
+            scoped_ids.insert(asset.id.clone(), id.clone());
+            asset.id = id;
+        }
+        if !library.contains_key(&asset.id) {
+            added.push(asset);
+        }
+    }
+
+    for mut asset in added {
+        if let Some(back_part) = asset.back_part.as_mut()
+            && let Some(id) = scoped_ids.get(back_part)
+        {
+            back_part.clone_from(id);
+        }
+        asset_libraries
+            .entry(asset.asset_type)
+            .or_default()
+            .insert(asset.id.clone(), asset);
+    }
+}
+
+/// Asynchronously loads the asset categories defined in `assets/csv/asset_categories.csv`.
+pub async fn load_asset_categories() -> Result<Vec<AssetCategory>, Box<dyn Error + Send + Sync>> {
+    let text = load_csv_text(ASSET_CATEGORIES_FILE).await?;
+    Ok(AssetCategory::parse_csv(&text)?)
+}
+
+/// Asynchronously loads the recolour scheme keys defined in `assets/csv/recolour_schemes.csv`.
+pub async fn load_recolour_schemes() -> Result<Vec<KeySlot>, Box<dyn Error + Send + Sync>> {
+    let text = load_csv_text(RECOLOUR_SCHEMES_FILE).await?;
+    Ok(KeySlot::parse_csv(&text)?)
+}
+
+/// Asynchronously loads the text of a CSV file in `assets/csv`.
+#[cfg(not(target_arch = "wasm32"))]
+async fn load_csv_text(path: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+    Ok(tokio::fs::read_to_string(format!("assets/csv/{path}")).await?)
+}
+
+/// Asynchronously loads the text of a CSV file in `assets/csv` (WASM version).
+#[cfg(target_arch = "wasm32")]
+async fn load_csv_text(path: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let url = format!("assets/csv/{}", path);
+    Ok(wasm::fetch_text(&url)
+        .await
+        .map_err(|e| e.as_string().unwrap_or_else(|| "JS error".to_string()))?
+        .as_string()
+        .ok_or("Failed to get file content as string from JS")?)
+}
+
+/// Asynchronously loads a list of colours from a CSV file in `assets/csv`.
 ///
 /// On native builds, it reads from the local filesystem.
 /// On WASM, it fetches the file via a JavaScript call.
-#[cfg(not(target_arch = "wasm32"))]
 pub async fn load_colours_from_csv(path: &str) -> Result<Vec<Rgba>, Box<dyn Error + Send + Sync>> {
-    let full_path = format!("assets/csv/{path}");
-    let content = tokio::fs::read_to_string(full_path).await?;
+    let text = load_csv_text(path).await?;
     let mut reader = csv::ReaderBuilder::new()
         .has_headers(false)
         .trim(csv::Trim::All)
-        .from_reader(content.as_bytes());
+        .from_reader(text.as_bytes());
     parse_colours(&mut reader)
 }
 
@@ -110,27 +293,14 @@ mod wasm {
     }
 }
 
-/// Asynchronously loads a list of colours from a CSV file
-#[cfg(target_arch = "wasm32")]
-pub async fn load_colours_from_csv(path: &str) -> Result<Vec<Rgba>, Box<dyn Error + Send + Sync>> {
-    let url = format!("assets/csv/{}", path);
-    let text = wasm::fetch_text(&url)
-        .await
-        .map_err(|e| e.as_string().unwrap_or_else(|| "JS error".to_string()))?
-        .as_string()
-        .ok_or("Failed to get file content as string from JS")?;
-
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .trim(csv::Trim::All)
-        .from_reader(text.as_bytes());
-
-    parse_colours(&mut reader)
-}
-
 /// Asynchronously loads the raw bytes of an image file.
+///
+/// Art loaded from a pack is read from within it.
 #[cfg(not(target_arch = "wasm32"))]
 pub async fn load_image_bytes(path: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    if let Some((pack, entry)) = asset_root::split_pack_path(path) {
+        return asset_root::read_pack_entry(pack, &entry);
+    }
     Ok(tokio::fs::read(path).await?)
 }
 
@@ -186,7 +356,13 @@ mod tests {
         libraries.insert(AssetType::Face, IndexMap::new());
 
         let path = PathBuf::from("assets/Test_Face.png");
-        add_asset_to_library(&mut libraries, &path);
+        add_asset_to_library(
+            &mut libraries,
+            &path,
+            AssetMetadata::default(),
+            None,
+            || Ok(None),
+        );
 
         assert!(
             libraries
@@ -196,17 +372,142 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_add_asset_to_library_with_metadata() {
+        let mut libraries = HashMap::new();
+        libraries.insert(AssetType::Face, IndexMap::new());
+
+        let metadata = AssetMetadata {
+            tags: vec!["mage".to_owned()],
+            artist: Some("Iscaneus".to_owned()),
+            ..Default::default()
+        };
+        let path = PathBuf::from("assets/Test_Face.png");
+        add_asset_to_library(&mut libraries, &path, metadata.clone(), None, || Ok(None));
+
+        assert_eq!(libraries[&AssetType::Face]["Test_Face"].metadata, metadata);
+    }
+
+    #[test]
+    fn test_add_asset_to_library_with_hash() {
+        let mut libraries = HashMap::new();
+        libraries.insert(AssetType::Face, IndexMap::new());
+
+        let hash = Asset::content_hash(b"Test_Face");
+        let path = PathBuf::from("assets/Test_Face.png");
+        add_asset_to_library(
+            &mut libraries,
+            &path,
+            AssetMetadata::default(),
+            None,
+            || Ok(Some(hash.clone())),
+        );
+
+        let asset = &libraries[&AssetType::Face]["Test_Face"];
+        assert_eq!(asset.content_hash, Some(hash));
+        assert!(asset.image_data.is_none());
+
+        let path = PathBuf::from("packs/Pack.zip/Test_Face.png");
+        add_asset_to_library(
+            &mut libraries,
+            &path,
+            AssetMetadata::default(),
+            None,
+            || panic!("Overridden art was read"),
+        );
+        assert_eq!(
+            libraries[&AssetType::Face]["Test_Face"].path,
+            PathBuf::from("assets/Test_Face.png")
+        );
+    }
+
     #[test]
     fn test_add_asset_to_library_invalid_type() {
         let mut libraries = HashMap::new();
         libraries.insert(AssetType::Face, IndexMap::new());
 
         let path = PathBuf::from("assets/Test_Unknown.png");
-        add_asset_to_library(&mut libraries, &path);
+        add_asset_to_library(
+            &mut libraries,
+            &path,
+            AssetMetadata::default(),
+            None,
+            || Ok(None),
+        );
 
         assert!(libraries.get(&AssetType::Face).unwrap().is_empty());
     }
 
+    #[test]
+    fn test_register_bundled_assets() {
+        let local_asset = |name: &str, asset_type: AssetType, hash: &str| {
+            let mut asset =
+                Asset::try_from(PathBuf::from(format!("art/{name}_{asset_type}.png")).as_path())
+                    .unwrap();
+            asset.content_hash = Some(hash.to_owned());
+            asset
+        };
+        let bundled = |asset: &Asset, hash: &str| Asset {
+            path: PathBuf::from(format!("user-asset://{}.png", asset.id)),
+            content_hash: Some(hash.to_owned()),
+            ..asset.clone()
+        };
+
+        let same = local_asset("Same", AssetType::Face, "1");
+        let long = local_asset("Long", AssetType::Hair, "2");
+        let long_back = local_asset("Long", AssetType::HairBack, "3");
+        let mut libraries = HashMap::new();
+        for asset in [same.clone(), long.clone(), long_back.clone()] {
+            libraries
+                .entry(asset.asset_type)
+                .or_insert_with(IndexMap::new)
+                .insert(asset.id.clone(), asset);
+        }
+
+        register_bundled_assets(
+            &mut libraries,
+            vec![
+                bundled(&same, "1"),
+                bundled(&long, "4"),
+                bundled(&long_back, "5"),
+            ],
+        );
+
+        assert_eq!(libraries[&AssetType::Face].len(), 1);
+        assert_eq!(libraries[&AssetType::Face]["Same_Face"].path, same.path);
+
+        // The recipient's hair is kept, and the bundled hair with the same id is added alongside.
+        let hair = &libraries[&AssetType::Hair];
+        assert_eq!(hair["Long_Hair"], long);
+        let bundled_hair = &hair["Long_Hair@4"];
+        assert_eq!(bundled_hair.content_hash.as_deref(), Some("4"));
+        assert_eq!(bundled_hair.back_part.as_deref(), Some("Long_HairBack@5"));
+        assert_eq!(libraries[&AssetType::HairBack]["Long_HairBack"], long_back);
+        assert!(libraries[&AssetType::HairBack].contains_key("Long_HairBack@5"));
+
+        let mut character = crate::character::Character::default();
+        character.set_character_part(
+            &AssetType::Hair,
+            crate::character::CharacterPart {
+                asset: bundled(&long, "4"),
+                position: crate::types::Point::new(0.5, 0.5),
+                scale: 1.0,
+                rotation: 0.0,
+                flipped: false,
+            },
+        );
+        let report = character.resolve_assets(&libraries);
+        assert!(report.missing.is_empty());
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Hair)
+                .expect("Hair missing")
+                .asset
+                .id,
+            "Long_Hair@4"
+        );
+    }
+
     #[test]
     fn test_parse_colours_valid() {
         let csv_data = "FF0000\n00FF00\n0000FF";
@@ -0,0 +1,373 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+#[cfg(not(target_arch = "wasm32"))]
+use super::{add_asset_to_library, read_metadata};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::asset::metadata::AssetMetadata;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::asset::{Asset, AssetType};
+#[cfg(not(target_arch = "wasm32"))]
+use indexmap::IndexMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::error::Error;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use zip::ZipArchive;
+
+/// The name of the application's directory within the user's config directory.
+#[cfg(not(target_arch = "wasm32"))]
+const APP_DIR: &str = "FE Character Creator";
+
+/// The file, within the application's config directory, that lists the asset roots to load.
+#[cfg(not(target_arch = "wasm32"))]
+const ASSET_ROOTS_FILE: &str = "asset_roots.txt";
+
+/// A place art is loaded from, see `load_asset_libraries`.
+///
+/// When more than one root has an asset with the same id, the one from the root with the highest
+/// priority is used. Roots are listed highest priority first, and the loose art files in a
+/// directory take priority over the packs within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetRoot {
+    /// A directory of art files, along with any `.zip` packs directly within it.
+    Directory(PathBuf),
+    /// A `.zip` archive of art files, such as a community art pack.
+    ///
+    /// Art and metadata sidecars can be anywhere within the archive. Assets loaded from a pack
+    /// are tagged with its filename and have a path within it, e.g. `art/Capes.zip/Red_Cape.png`.
+    Pack(PathBuf),
+}
+
+impl AssetRoot {
+    /// The directory the art that comes with the application is in.
+    pub fn bundled() -> Self {
+        Self::Directory(PathBuf::from("art"))
+    }
+
+    /// Returns a pack for a path to a `.zip` file, or a directory for any other path.
+    pub fn from_path(path: PathBuf) -> Self {
+        if is_zip(&path) {
+            Self::Pack(path)
+        } else {
+            Self::Directory(path)
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Directory(path) | Self::Pack(path) => path,
+        }
+    }
+
+    /// Parses a list of roots, one path per line, ignoring blank lines and `#` comments.
+    pub fn parse_list(text: &str) -> Vec<Self> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| Self::from_path(PathBuf::from(line)))
+            .collect()
+    }
+
+    /// Returns the user's own art directory, in the application's config directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn user_art_dir() -> Option<PathBuf> {
+        dirs_next::config_dir().map(|dir| dir.join(APP_DIR).join("art"))
+    }
+
+    /// Returns the roots to load art from, highest priority first.
+    ///
+    /// These are listed in `asset_roots.txt` in the application's config directory if it exists,
+    /// see `parse_list`. Otherwise, the user's art directory is used ahead of the bundled art.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn configured() -> Vec<Self> {
+        let listed = dirs_next::config_dir()
+            .map(|dir| dir.join(APP_DIR).join(ASSET_ROOTS_FILE))
+            .and_then(|path| std::fs::read_to_string(path).ok());
+        if let Some(text) = listed {
+            return Self::parse_list(&text);
+        }
+
+        Self::user_art_dir()
+            .map(Self::Directory)
+            .into_iter()
+            .chain([Self::bundled()])
+            .collect()
+    }
+
+    /// Returns the roots to load art from. The web build can only load the bundled art.
+    #[cfg(target_arch = "wasm32")]
+    pub fn configured() -> Vec<Self> {
+        vec![Self::bundled()]
+    }
+
+    /// Loads the art in this root into the libraries, skipping assets already loaded from a root
+    /// with a higher priority.
+    ///
+    /// Art whose path is in `known_hashes` is given that content hash rather than being read, see
+    /// `AssetWatcher::reload`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn load(
+        &self,
+        asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
+        known_hashes: &HashMap<PathBuf, String>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self {
+            Self::Directory(dir) => {
+                load_directory(dir, asset_libraries, known_hashes)?;
+
+                for pack in glob_files(dir, "zip")? {
+                    if let Err(e) = Self::Pack(pack.clone()).load(asset_libraries, known_hashes) {
+                        log::warn!("Failed to load asset pack {pack:?}: {e}");
+                    }
+                }
+                Ok(())
+            }
+            Self::Pack(path) => load_pack(path, asset_libraries, known_hashes),
+        }
+    }
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Lists the files in a directory with the given extension, in name order.
+#[cfg(not(target_arch = "wasm32"))]
+fn glob_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, Box<dyn Error + Send + Sync>> {
+    let pattern = format!(
+        "{}/*.{extension}",
+        glob::Pattern::escape(&dir.to_string_lossy())
+    );
+    Ok(glob::glob(&pattern)?.flatten().collect())
+}
+
+/// Loads the art files directly within a directory, with their metadata sidecars.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_directory(
+    dir: &Path,
+    asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
+    known_hashes: &HashMap<PathBuf, String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let folder_metadata = read_metadata(&dir.join(AssetMetadata::FOLDER_FILE)).unwrap_or_default();
+
+    for path in glob_files(dir, "png")? {
+        let metadata = read_metadata(&path.with_extension("json"))
+            .unwrap_or_default()
+            .merged_with(&folder_metadata);
+        add_asset_to_library(asset_libraries, &path, metadata, None, || {
+            if let Some(hash) = known_hashes.get(&path) {
+                return Ok(Some(hash.clone()));
+            }
+            Ok(Some(Asset::content_hash(&std::fs::read(&path)?)))
+        });
+    }
+    Ok(())
+}
+
+/// Loads every art file within a pack, with the metadata sidecars next to them in the pack.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_pack(
+    path: &Path,
+    asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
+    known_hashes: &HashMap<PathBuf, String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let pack_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    let mut archive = ZipArchive::new(std::fs::File::open(path)?)?;
+
+    let mut sidecars: HashMap<String, AssetMetadata> = HashMap::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if !entry.is_file() || !entry.name().ends_with(".json") {
+            continue;
+        }
+        let name = entry.name().to_owned();
+        let mut json = String::new();
+        entry.read_to_string(&mut json)?;
+        match AssetMetadata::from_json(&json) {
+            Ok(metadata) => {
+                sidecars.insert(name, metadata);
+            }
+            Err(e) => log::warn!("Ignoring {name} in {path:?}: {e}"),
+        }
+    }
+
+    for index in 0..archive.len() {
+        let Some(stem) = archive
+            .name_for_index(index)
+            .and_then(|name| name.strip_suffix(".png"))
+            .map(str::to_owned)
+        else {
+            continue;
+        };
+
+        let folder_file = match stem.rsplit_once('/') {
+            Some((folder, _)) => format!("{folder}/{}", AssetMetadata::FOLDER_FILE),
+            None => AssetMetadata::FOLDER_FILE.to_owned(),
+        };
+        let metadata = sidecars
+            .get(&format!("{stem}.json"))
+            .cloned()
+            .unwrap_or_default()
+            .merged_with(&sidecars.get(&folder_file).cloned().unwrap_or_default());
+
+        let asset_path = path.join(format!("{stem}.png"));
+        add_asset_to_library(
+            asset_libraries,
+            &asset_path,
+            metadata,
+            pack_name.clone(),
+            || {
+                if let Some(hash) = known_hashes.get(&asset_path) {
+                    return Ok(Some(hash.clone()));
+                }
+                let mut bytes = Vec::new();
+                archive.by_index(index)?.read_to_end(&mut bytes)?;
+                Ok(Some(Asset::content_hash(&bytes)))
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Splits the path of an asset loaded from a pack into the path of the pack and the name of the
+/// asset's entry within it, or returns `None` for any other path.
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) fn split_pack_path(path: &Path) -> Option<(&Path, String)> {
+    let pack = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_zip(ancestor) && ancestor.is_file())?;
+    let entry = path
+        .strip_prefix(pack)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((pack, entry))
+}
+
+/// Reads the bytes of an entry within a pack.
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) fn read_pack_entry(
+    pack: &Path,
+    entry: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut archive = ZipArchive::new(std::fs::File::open(pack)?)?;
+    let mut bytes = Vec::new();
+    archive.by_name(entry)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write as _;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    /// Creates an empty temporary directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fecc_{name}_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+        dir
+    }
+
+    fn png_bytes(pixel: [u8; 4]) -> Vec<u8> {
+        let mut image = image::RgbaImage::new(1, 1);
+        image.put_pixel(0, 0, image::Rgba(pixel));
+        let mut bytes = Vec::new();
+        image
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .expect("Failed to encode PNG");
+        bytes
+    }
+
+    fn write_pack(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut writer = ZipWriter::new(std::fs::File::create(path).expect("Failed to create"));
+        for (name, bytes) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .expect("Failed to start entry");
+            writer.write_all(bytes).expect("Failed to write entry");
+        }
+        writer.finish().expect("Failed to finish pack");
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let roots = AssetRoot::parse_list("# Highest first\n  ~/packs/Capes.ZIP \n\nart\n");
+        assert_eq!(
+            roots,
+            [
+                AssetRoot::Pack(PathBuf::from("~/packs/Capes.ZIP")),
+                AssetRoot::Directory(PathBuf::from("art")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_roots_by_priority() {
+        let user_dir = temp_dir("user_art");
+        let bundled_dir = temp_dir("bundled_art");
+
+        std::fs::write(user_dir.join("Shared_Face.png"), png_bytes([1, 0, 0, 255]))
+            .expect("Failed to write art");
+        write_pack(
+            &user_dir.join("Pack.zip"),
+            &[
+                ("capes/Red_Face.png", &png_bytes([2, 0, 0, 255])),
+                ("capes/Red_Face.json", br#"{"tags": ["red"]}"#),
+                ("capes/metadata.json", br#"{"artist": "Someone"}"#),
+                ("Shared_Face.png", &png_bytes([3, 0, 0, 255])),
+            ],
+        );
+        std::fs::write(bundled_dir.join("Red_Face.png"), png_bytes([4, 0, 0, 255]))
+            .expect("Failed to write art");
+
+        let mut libraries = HashMap::from([(AssetType::Face, IndexMap::new())]);
+        for root in [
+            AssetRoot::Directory(user_dir.clone()),
+            AssetRoot::Directory(bundled_dir.clone()),
+        ] {
+            root.load(&mut libraries, &HashMap::new())
+                .expect("Failed to load root");
+        }
+
+        let faces = &libraries[&AssetType::Face];
+        assert_eq!(faces.len(), 2);
+        assert_eq!(faces["Shared_Face"].path, user_dir.join("Shared_Face.png"));
+        assert_eq!(faces["Shared_Face"].pack, None);
+
+        let red = &faces["Red_Face"];
+        assert_eq!(red.pack.as_deref(), Some("Pack.zip"));
+        assert_eq!(red.metadata.tags, ["red"]);
+        assert_eq!(red.metadata.artist.as_deref(), Some("Someone"));
+
+        let (pack, entry) = split_pack_path(&red.path).expect("Not a pack path");
+        assert_eq!(pack, user_dir.join("Pack.zip"));
+        assert_eq!(entry, "capes/Red_Face.png");
+        assert_eq!(
+            read_pack_entry(pack, &entry).expect("Failed to read entry"),
+            png_bytes([2, 0, 0, 255])
+        );
+        assert_eq!(
+            red.content_hash,
+            Some(Asset::content_hash(&png_bytes([2, 0, 0, 255])))
+        );
+        assert!(red.image_data.is_none());
+
+        std::fs::remove_dir_all(user_dir).ok();
+        std::fs::remove_dir_all(bundled_dir).ok();
+    }
+}
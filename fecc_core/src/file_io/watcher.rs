@@ -0,0 +1,292 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use super::asset_root::AssetRoot;
+use super::load_asset_roots;
+use crate::asset::{Asset, AssetType};
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The extensions of the files in a directory root that affect the assets loaded from it.
+const WATCHED_EXTENSIONS: [&str; 3] = ["png", "json", "zip"];
+
+/// The size and modification time of a file, used to tell when it has changed.
+#[derive(Debug, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Watches asset roots for art files, metadata sidecars and packs being added, changed or removed.
+///
+/// The roots are polled, with each `poll` comparing the size and modification time of every file
+/// against those at the previous one.
+pub struct AssetWatcher {
+    roots: Vec<AssetRoot>,
+    stamps: BTreeMap<PathBuf, FileStamp>,
+    /// The content hashes of the art loaded from the roots, by path, so that `reload` doesn't read
+    /// art that hasn't changed.
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl AssetWatcher {
+    pub fn new(roots: Vec<AssetRoot>) -> Self {
+        let stamps = scan(&roots);
+        Self {
+            roots,
+            stamps,
+            hashes: HashMap::new(),
+        }
+    }
+
+    /// Records the content hashes of the art in libraries loaded from the roots.
+    pub fn set_loaded(&mut self, asset_libraries: &HashMap<AssetType, IndexMap<String, Asset>>) {
+        self.hashes = asset_libraries
+            .values()
+            .flat_map(IndexMap::values)
+            .filter_map(|asset| Some((asset.path.clone(), asset.content_hash.clone()?)))
+            .collect();
+    }
+
+    /// Returns the watched files that have been added, changed or removed since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let stamps = scan(&self.roots);
+        let mut changed: Vec<PathBuf> = self
+            .stamps
+            .iter()
+            .filter(|&(path, stamp)| stamps.get(path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            stamps
+                .keys()
+                .filter(|&path| !self.stamps.contains_key(path))
+                .cloned(),
+        );
+        self.stamps = stamps;
+        changed
+    }
+
+    /// Reloads the art in the roots after `poll` found changed files.
+    ///
+    /// Only the art files that changed, and those in packs that changed, are read. Metadata
+    /// sidecars are always read again, as a folder's sidecar affects every art file in it.
+    pub fn reload(&mut self, changed: &[PathBuf]) -> HashMap<AssetType, IndexMap<String, Asset>> {
+        self.hashes
+            .retain(|path, _| !changed.iter().any(|changed| path.starts_with(changed)));
+
+        let mut asset_libraries = AssetType::all()
+            .map(|asset_type| (asset_type, IndexMap::new()))
+            .collect();
+        load_asset_roots(&self.roots, &mut asset_libraries, &self.hashes);
+        self.set_loaded(&asset_libraries);
+        asset_libraries
+    }
+}
+
+/// Stamps every watched file in the roots.
+fn scan(roots: &[AssetRoot]) -> BTreeMap<PathBuf, FileStamp> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for root in roots {
+        match root {
+            AssetRoot::Directory(dir) => {
+                let Ok(entries) = std::fs::read_dir(dir) else {
+                    continue;
+                };
+                files.extend(entries.flatten().map(|entry| entry.path()).filter(|path| {
+                    path.extension()
+                        .and_then(|extension| extension.to_str())
+                        .is_some_and(|extension| WATCHED_EXTENSIONS.contains(&extension))
+                }));
+            }
+            AssetRoot::Pack(path) => files.push(path.clone()),
+        }
+    }
+
+    files
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = std::fs::metadata(&path).ok()?;
+            let stamp = FileStamp {
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            };
+            Some((path, stamp))
+        })
+        .collect()
+}
+
+/// What changed when the asset libraries were reloaded, see `merge_reloaded_libraries`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LibraryChanges {
+    pub added: Vec<Asset>,
+    /// The assets whose file, contents or metadata changed, as they are now.
+    pub changed: Vec<Asset>,
+    pub removed: Vec<Asset>,
+}
+
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Returns whether two loads of an asset are of the same file, with the same contents and metadata.
+fn is_unchanged(old: &Asset, new: &Asset) -> bool {
+    old.path == new.path
+        && old.content_hash == new.content_hash
+        && old.metadata == new.metadata
+        && old.pack == new.pack
+}
+
+/// Replaces the asset libraries with ones reloaded from the asset roots, and returns what changed.
+///
+/// Unchanged assets keep any image data and file bytes already loaded for them. Assets that weren't
+/// loaded from an asset root, such as the art of a loaded bundle, are kept unless the reload has an
+/// asset with the same id.
+pub fn merge_reloaded_libraries(
+    asset_libraries: &mut HashMap<AssetType, IndexMap<String, Asset>>,
+    mut reloaded: HashMap<AssetType, IndexMap<String, Asset>>,
+) -> LibraryChanges {
+    let mut changes = LibraryChanges::default();
+
+    for asset_type in AssetType::all() {
+        let old_library = asset_libraries.remove(&asset_type).unwrap_or_default();
+        let library = reloaded.entry(asset_type).or_default();
+
+        for asset in library.values_mut() {
+            match old_library.get(&asset.id) {
+                Some(old) if is_unchanged(old, asset) => {
+                    asset.image_data.clone_from(&old.image_data);
+                    asset.file_bytes.clone_from(&old.file_bytes);
+                }
+                Some(_) => changes.changed.push(asset.clone()),
+                None => changes.added.push(asset.clone()),
+            }
+        }
+
+        for (id, old) in old_library {
+            if library.contains_key(&id) {
+                continue;
+            }
+            if old.path.to_string_lossy().starts_with("user-asset://") {
+                library.insert(id, old);
+            } else {
+                changes.removed.push(old);
+            }
+        }
+    }
+
+    *asset_libraries = reloaded;
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use std::sync::Arc;
+
+    fn test_asset(name: &str, hash: &str) -> Asset {
+        let mut asset = Asset::new(
+            name.to_owned(),
+            PathBuf::from(format!("art/{name}_Face.png")),
+            None,
+            AssetType::Face,
+        );
+        asset.content_hash = Some(hash.to_owned());
+        asset
+    }
+
+    fn libraries(assets: Vec<Asset>) -> HashMap<AssetType, IndexMap<String, Asset>> {
+        HashMap::from([(
+            AssetType::Face,
+            assets.into_iter().map(|a| (a.id.clone(), a)).collect(),
+        )])
+    }
+
+    #[test]
+    fn test_merge_reloaded_libraries() {
+        let mut kept = test_asset("Kept", "1");
+        kept.image_data = Some(Arc::new(RgbaImage::new(1, 1)));
+        let mut bundled = test_asset("Bundled", "2");
+        bundled.path = PathBuf::from("user-asset://Bundled_Face.png");
+        let mut current = libraries(vec![
+            kept.clone(),
+            bundled,
+            test_asset("Edited", "3"),
+            test_asset("Deleted", "4"),
+        ]);
+
+        let reloaded = libraries(vec![
+            test_asset("Kept", "1"),
+            test_asset("Edited", "5"),
+            test_asset("New", "6"),
+        ]);
+        let changes = merge_reloaded_libraries(&mut current, reloaded);
+
+        let ids = |assets: &[Asset]| assets.iter().map(|a| a.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&changes.added), ["New_Face"]);
+        assert_eq!(ids(&changes.changed), ["Edited_Face"]);
+        assert_eq!(ids(&changes.removed), ["Deleted_Face"]);
+
+        let faces = &current[&AssetType::Face];
+        assert_eq!(faces["Kept_Face"].image_data, kept.image_data);
+        assert_eq!(faces["Edited_Face"].content_hash.as_deref(), Some("5"));
+        assert!(faces.contains_key("Bundled_Face"));
+        assert!(!faces.contains_key("Deleted_Face"));
+    }
+
+    #[test]
+    fn test_watcher_polls_changes() {
+        let dir = std::env::temp_dir().join(format!("fecc_watcher_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+
+        let mut watcher = AssetWatcher::new(vec![AssetRoot::Directory(dir.clone())]);
+        assert!(watcher.poll().is_empty());
+
+        let art = dir.join("New_Face.png");
+        std::fs::write(&art, [0]).expect("Failed to write art");
+        assert_eq!(watcher.poll(), std::slice::from_ref(&art));
+        assert!(watcher.poll().is_empty());
+
+        std::fs::write(dir.join("notes.txt"), [0]).expect("Failed to write notes");
+        assert!(watcher.poll().is_empty());
+
+        std::fs::write(&art, [0, 1]).expect("Failed to write art");
+        assert_eq!(watcher.poll(), std::slice::from_ref(&art));
+
+        std::fs::remove_file(&art).expect("Failed to remove art");
+        assert_eq!(watcher.poll(), [art]);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_watcher_reloads_changed_files() {
+        let dir = std::env::temp_dir().join(format!("fecc_reload_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+        let art = dir.join("Kept_Face.png");
+        std::fs::write(&art, [0]).expect("Failed to write art");
+
+        let mut watcher = AssetWatcher::new(vec![AssetRoot::Directory(dir.clone())]);
+        let hash = |libraries: &HashMap<AssetType, IndexMap<String, Asset>>| {
+            libraries[&AssetType::Face]["Kept_Face"]
+                .content_hash
+                .clone()
+        };
+        assert_eq!(hash(&watcher.reload(&[])), Some(Asset::content_hash(&[0])));
+
+        // Files that weren't reported as changed aren't read again.
+        std::fs::write(&art, [1]).expect("Failed to write art");
+        assert_eq!(hash(&watcher.reload(&[])), Some(Asset::content_hash(&[0])));
+        assert_eq!(
+            hash(&watcher.reload(&[art])),
+            Some(Asset::content_hash(&[1]))
+        );
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::types::Rgba;
+
+/// The number of colours in a GBA palette bank. Mugs use one bank, with colour 0 as transparency.
+pub const BANK_SIZE: usize = 16;
+
+/// Reads the colours of a raw GBA palette, as used by `.gbapal` and `.dmp` files: one
+/// little-endian BGR555 value per colour with no header, see `Rgba::from_bgr555`.
+pub fn read_gba_palette(bytes: &[u8]) -> Result<Vec<Rgba>, String> {
+    if bytes.is_empty() {
+        return Err("The palette file is empty".to_owned());
+    }
+    if !bytes.len().is_multiple_of(2) {
+        return Err(format!(
+            "A GBA palette has two bytes per colour, but the file has {} bytes",
+            bytes.len()
+        ));
+    }
+
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|colour| Rgba::from_bgr555(u16::from_le_bytes([colour[0], colour[1]])))
+        .collect())
+}
+
+/// Writes colours as a raw palette file, padding it with black to a whole number of banks.
+pub fn gba_palette_bytes(colours: &[Rgba]) -> Vec<u8> {
+    let padded_len = colours.len().max(1).div_ceil(BANK_SIZE) * BANK_SIZE;
+    colours
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(Rgba::BLACK))
+        .take(padded_len)
+        .flat_map(|colour| colour.to_bgr555().to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gba_palette_round_trip() {
+        let colours = [
+            Rgba::new(255, 0, 255, 255),
+            Rgba::WHITE,
+            Rgba::new(99, 8, 132, 255),
+        ];
+
+        let bytes = gba_palette_bytes(&colours);
+        assert_eq!(bytes.len(), BANK_SIZE * 2);
+        assert_eq!(bytes[..4], [0x1f, 0x7c, 0xff, 0x7f]);
+
+        let read = read_gba_palette(&bytes).expect("Failed to read palette");
+        assert_eq!(read[..3], colours);
+        assert!(read[3..].iter().all(|&colour| colour == Rgba::BLACK));
+    }
+
+    #[test]
+    fn test_read_gba_palette_invalid() {
+        assert!(read_gba_palette(&[]).is_err());
+        assert!(read_gba_palette(&[0x1f, 0x7c, 0xff]).is_err());
+    }
+}
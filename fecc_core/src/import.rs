@@ -0,0 +1,552 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+#[cfg(not(target_arch = "wasm32"))]
+use crate::asset::Asset;
+use crate::asset::AssetType;
+use crate::character::{Character, CharacterPartColours, Colourable, Shade};
+use crate::extensions::rgba::AdjustBrightness as _;
+use crate::recolour::scheme::KEY_STEP;
+use crate::types::Rgba;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
+use std::error::Error;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use strum::IntoEnumIterator as _;
+
+/// The most distinct colours an image can have to be imported, pixel art uses far fewer.
+pub const MAX_COLOURS: usize = 256;
+
+/// A colour of a full-colour image, and the slot it is keyed to when the image is imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ColourMapping {
+    /// The opaque colour, transparency is kept as it is when the image is keyed.
+    pub colour: Rgba,
+    pub colourable: Colourable,
+    pub shade: Shade,
+}
+
+/// A full-colour image converted to key colours, see `import_image`.
+#[derive(Debug, Clone)]
+pub struct ImportedArt {
+    /// The art, keyed so that it can be recoloured.
+    pub image: RgbaImage,
+    pub mappings: Vec<ColourMapping>,
+    /// The colours of the original image, which recolour the art to look as it did.
+    pub colours: HashMap<Colourable, CharacterPartColours>,
+    pub outline: Option<Rgba>,
+}
+
+/// Converts a full-colour image into art of the given type, keyed as described in ART.md.
+///
+/// The colours are mapped as proposed by `propose_mappings`. Use `key_image` and
+/// `extract_colours` instead to import with a corrected mapping.
+pub fn import_image(image: &RgbaImage, asset_type: AssetType) -> Result<ImportedArt, String> {
+    let mappings = propose_mappings(image, asset_type)?;
+    let (colours, outline) = extract_colours(&mappings);
+    Ok(ImportedArt {
+        image: key_image(image, asset_type, &mappings),
+        mappings,
+        colours,
+        outline,
+    })
+}
+
+/// Proposes a colourable and shade for every colour of a full-colour image.
+///
+/// The darkest colour is taken to be the outline. The others are grouped into ramps of similar
+/// hue, one for each colourable of the type at most, and each ramp is given to the colourable
+/// whose default colour is closest in hue. The colours of a ramp are spread over the
+/// colourable's shades from lightest to darkest.
+pub fn propose_mappings(
+    image: &RgbaImage,
+    asset_type: AssetType,
+) -> Result<Vec<ColourMapping>, String> {
+    let mut colours = palette(image)?;
+    colours.sort_by(|a, b| luma(b.0).total_cmp(&luma(a.0)));
+    let Some((outline, _)) = colours.pop() else {
+        return Ok(Vec::new());
+    };
+    let mut mappings = vec![ColourMapping {
+        colour: opaque(outline),
+        colourable: Colourable::Outline,
+        shade: Shade::Neutral,
+    }];
+
+    let layout = asset_type.category().recolour_scheme.layout(false);
+    let colourables = layout.colourables();
+    let ramps = cluster(colours, colourables.len());
+
+    let mut pairs: Vec<(f32, usize, Colourable)> = Vec::new();
+    for (index, ramp) in ramps.iter().enumerate() {
+        for &colourable in &colourables {
+            let distance = distance(centroid(ramp), default_chromaticity(colourable));
+            pairs.push((distance, index, colourable));
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut assigned: BTreeMap<usize, Colourable> = BTreeMap::new();
+    for (_, index, colourable) in pairs {
+        if !assigned.contains_key(&index) && !assigned.values().any(|c| *c == colourable) {
+            assigned.insert(index, colourable);
+        }
+    }
+
+    for (index, colourable) in assigned {
+        let ramp = &ramps[index];
+        // Skin's darkest shades are only used when the ramp has more than three colours.
+        let shades: Vec<Shade> = Shade::iter()
+            .filter(|shade| layout.key_of(colourable, *shade).is_some())
+            .take(ramp.len().max(3))
+            .collect();
+        for (rank, (colour, _)) in ramp.iter().enumerate() {
+            // Spread the ramp's colours evenly over the shades, centring them when there are fewer.
+            let shade = shades[(2 * rank + 1) * shades.len() / (2 * ramp.len())];
+            mappings.push(ColourMapping {
+                colour: opaque(*colour),
+                colourable,
+                shade,
+            });
+        }
+    }
+    Ok(mappings)
+}
+
+/// Keys the pixels of a full-colour image by their mapped slot, keeping their transparency.
+///
+/// Colours that aren't mapped are keyed as the closest mapped colour.
+pub fn key_image(
+    image: &RgbaImage,
+    asset_type: AssetType,
+    mappings: &[ColourMapping],
+) -> RgbaImage {
+    // Imported art is standard art, keyed by its red channel alone.
+    let layout = asset_type.category().recolour_scheme.layout(false);
+    let keyed: Vec<(Rgba, u8)> = mappings
+        .iter()
+        .filter_map(|mapping| {
+            let key = layout.key_of(mapping.colourable, mapping.shade)?;
+            Some((mapping.colour, key.red * KEY_STEP))
+        })
+        .collect();
+
+    let mut keyed_image = image.clone();
+    for pixel in keyed_image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        if a == 0 {
+            continue;
+        }
+        let closest = keyed.iter().min_by_key(|(colour, _)| {
+            [
+                i32::from(colour.r) - i32::from(r),
+                i32::from(colour.g) - i32::from(g),
+                i32::from(colour.b) - i32::from(b),
+            ]
+            .iter()
+            .map(|d| d * d)
+            .sum::<i32>()
+        });
+        if let Some((_, red)) = closest {
+            pixel.0 = [*red, 0, 0, a];
+        }
+    }
+    keyed_image
+}
+
+/// Returns the colours that recolour art keyed with these mappings to look like the original
+/// image, along with its outline colour.
+///
+/// Shades without a mapped colour are derived from the colourable's neutral shade, or from its
+/// first mapped colour if it has no neutral shade. Where several colours share a shade, the first
+/// is used.
+pub fn extract_colours(
+    mappings: &[ColourMapping],
+) -> (HashMap<Colourable, CharacterPartColours>, Option<Rgba>) {
+    let mut outline = None;
+    let mut shades: BTreeMap<Colourable, BTreeMap<Shade, Rgba>> = BTreeMap::new();
+    for mapping in mappings {
+        if mapping.colourable == Colourable::Outline {
+            outline = outline.or(Some(mapping.colour));
+        } else {
+            shades
+                .entry(mapping.colourable)
+                .or_default()
+                .entry(mapping.shade)
+                .or_insert(mapping.colour);
+        }
+    }
+
+    let colours = shades
+        .into_iter()
+        .filter_map(|(colourable, shades)| {
+            let base = shades
+                .get(&Shade::Neutral)
+                .or_else(|| shades.values().next())?;
+            let mut colours = CharacterPartColours::new(base);
+            for (shade, colour) in shades {
+                *colours.shade_mut(shade) = colour;
+            }
+            Some((colourable, colours))
+        })
+        .collect();
+    (colours, outline)
+}
+
+/// Imports a full-colour image file as art named `name`, writing it as `Name_Type.png` into a
+/// directory. Returns the path written and the imported art.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn import_file(
+    source: &Path,
+    name: &str,
+    asset_type: AssetType,
+    dir: &Path,
+) -> Result<(PathBuf, ImportedArt), Box<dyn Error + Send + Sync>> {
+    let path = dir.join(format!("{name}_{asset_type}.png"));
+    // Catch names that would make the file unloadable before doing any work.
+    Asset::try_from(path.as_path())?;
+
+    let imported = import_image(&image::open(source)?.to_rgba8(), asset_type)?;
+    imported.image.save(&path)?;
+    Ok((path, imported))
+}
+
+/// How far each channel of a colour can be from a shade of another for them to be taken as being
+/// in the same ramp, allowing for art that was shaded by hand.
+const RAMP_TOLERANCE: u8 = 8;
+
+/// A colour of a reference image that could be the neutral shade of a colourable, see
+/// `extract_colour_scheme`.
+struct RampCandidate {
+    neutral: [u8; 3],
+    /// The colours of the image in the ramp, including the neutral one.
+    colours: Vec<[u8; 3]>,
+    /// Whether both the lighter and darker shades are in the image.
+    is_complete: bool,
+    /// How many of the darker shades below the darker one, as only skin uses, are in the image.
+    depth: usize,
+    pixels: usize,
+}
+
+/// Infers the colours of a character from a portrait or sprite drawn with them, such as an
+/// exported portrait.
+///
+/// A colour is taken to be a colourable's neutral shade when the image also has the lighter and
+/// darker shades derived from it by `AdjustBrightness`. These ramps are given to the colourables
+/// whose default colours are closest in hue. Colourables without a ramp in the image are left out.
+pub fn extract_colour_scheme(
+    image: &RgbaImage,
+) -> Result<HashMap<Colourable, CharacterPartColours>, String> {
+    let colours = palette(image)?;
+    let find = |target: Rgba| {
+        colours.iter().find(|(colour, _)| {
+            colour
+                .iter()
+                .zip([target.r, target.g, target.b])
+                .all(|(a, b)| a.abs_diff(b) <= RAMP_TOLERANCE)
+        })
+    };
+
+    let mut candidates: Vec<RampCandidate> = Vec::new();
+    for &(neutral, count) in &colours {
+        let base = opaque(neutral);
+        let lighter = find(base.brighter()).filter(|(colour, _)| *colour != neutral);
+        let darker = find(base.darker()).filter(|(colour, _)| *colour != neutral);
+        if lighter.is_none() && darker.is_none() {
+            continue;
+        }
+        let deeper: Vec<_> = [base.darker().darker(), base.darker().darker().darker()]
+            .into_iter()
+            .map_while(|shade| find(shade).filter(|(colour, _)| *colour != neutral))
+            .collect();
+
+        let found: Vec<_> = lighter
+            .into_iter()
+            .chain(darker)
+            .chain(deeper.iter().copied())
+            .collect();
+        candidates.push(RampCandidate {
+            neutral,
+            colours: found
+                .iter()
+                .map(|(colour, _)| *colour)
+                .chain([neutral])
+                .collect(),
+            is_complete: lighter.is_some() && darker.is_some(),
+            depth: deeper.len(),
+            pixels: count + found.iter().map(|(_, count)| count).sum::<usize>(),
+        });
+    }
+
+    // Prefer ramps with every shade, so that a lighter or darker shade isn't taken to be the
+    // neutral one, and drop any sharing a colour with a better ramp.
+    candidates.sort_by(|a, b| {
+        (b.is_complete, b.depth, b.pixels).cmp(&(a.is_complete, a.depth, a.pixels))
+    });
+    let mut ramps: Vec<RampCandidate> = Vec::new();
+    for candidate in candidates {
+        let overlaps = ramps
+            .iter()
+            .any(|ramp| candidate.colours.iter().any(|c| ramp.colours.contains(c)));
+        if !overlaps {
+            ramps.push(candidate);
+        }
+    }
+
+    let mut pairs: Vec<(bool, f32, usize, Colourable)> = Vec::new();
+    for (index, ramp) in ramps.iter().enumerate() {
+        for colourable in Colourable::iter().filter(|c| *c != Colourable::Outline) {
+            let distance = distance(chromaticity(ramp.neutral), default_chromaticity(colourable));
+            pairs.push((!ramp.is_complete, distance, index, colourable));
+        }
+    }
+    pairs.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+
+    let mut used: HashSet<usize> = HashSet::new();
+    let mut scheme: HashMap<Colourable, CharacterPartColours> = HashMap::new();
+    for (_, _, index, colourable) in pairs {
+        if !scheme.contains_key(&colourable) && used.insert(index) {
+            scheme.insert(
+                colourable,
+                CharacterPartColours::new(&opaque(ramps[index].neutral)),
+            );
+        }
+    }
+
+    if scheme.is_empty() {
+        return Err("No colour ramps were found in the image".to_owned());
+    }
+    Ok(scheme)
+}
+
+/// Counts the pixels of each colour of an image, ignoring fully transparent ones.
+fn palette(image: &RgbaImage) -> Result<Vec<([u8; 3], usize)>, String> {
+    let mut palette: BTreeMap<[u8; 3], usize> = BTreeMap::new();
+    for pixel in image.pixels().filter(|pixel| pixel.0[3] != 0) {
+        let [r, g, b, _] = pixel.0;
+        *palette.entry([r, g, b]).or_default() += 1;
+    }
+    if palette.len() > MAX_COLOURS {
+        return Err(format!(
+            "The image has {} colours, reduce it to at most {MAX_COLOURS} to use it",
+            palette.len()
+        ));
+    }
+    Ok(palette.into_iter().collect())
+}
+
+/// The chromaticity of a colourable's colour on a new character.
+fn default_chromaticity(colourable: Colourable) -> [f32; 3] {
+    let base = Character::default()
+        .character_colours
+        .get(&colourable)
+        .map_or(Rgba::BLACK, |colours| colours.base);
+    chromaticity([base.r, base.g, base.b])
+}
+
+fn opaque([r, g, b]: [u8; 3]) -> Rgba {
+    Rgba::new(r, g, b, u8::MAX)
+}
+
+fn luma([r, g, b]: [u8; 3]) -> f32 {
+    0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)
+}
+
+/// The proportions of red, green and blue in a colour, which ignore how light it is.
+fn chromaticity([r, g, b]: [u8; 3]) -> [f32; 3] {
+    let sum = f32::from(r) + f32::from(g) + f32::from(b);
+    if sum == 0.0 {
+        return [1.0 / 3.0; 3];
+    }
+    [f32::from(r) / sum, f32::from(g) / sum, f32::from(b) / sum]
+}
+
+/// The mean chromaticity of a ramp's colours, weighted by how many pixels have them.
+fn centroid(ramp: &[([u8; 3], usize)]) -> [f32; 3] {
+    let mut sum = [0.0; 3];
+    let mut total = 0.0;
+    for (colour, count) in ramp {
+        let weight = *count as f32;
+        for (sum, channel) in sum.iter_mut().zip(chromaticity(*colour)) {
+            *sum += channel * weight;
+        }
+        total += weight;
+    }
+    sum.map(|channel| channel / total.max(1.0))
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Groups colours, sorted lightest first, into at most `ramps` ramps of similar chromaticity by
+/// repeatedly merging the closest two. The colours of each ramp stay sorted lightest first.
+fn cluster(colours: Vec<([u8; 3], usize)>, ramps: usize) -> Vec<Vec<([u8; 3], usize)>> {
+    let mut clusters: Vec<Vec<([u8; 3], usize)>> =
+        colours.into_iter().map(|colour| vec![colour]).collect();
+
+    while clusters.len() > ramps.max(1) {
+        let centroids: Vec<[f32; 3]> = clusters.iter().map(|ramp| centroid(ramp)).collect();
+        let mut closest = (f32::INFINITY, 0, 1);
+        for i in 0..centroids.len() {
+            for j in i + 1..centroids.len() {
+                let distance = distance(centroids[i], centroids[j]);
+                if distance < closest.0 {
+                    closest = (distance, i, j);
+                }
+            }
+        }
+
+        let merged = clusters.remove(closest.2);
+        clusters[closest.1].extend(merged);
+        clusters[closest.1].sort_by(|a, b| luma(b.0).total_cmp(&luma(a.0)));
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba as Pixel;
+
+    /// An image with one pixel of each colour, in a row.
+    fn image_of(colours: &[[u8; 4]]) -> RgbaImage {
+        let mut image = RgbaImage::new(colours.len() as u32, 1);
+        for (x, colour) in colours.iter().enumerate() {
+            image.put_pixel(x as u32, 0, Pixel(*colour));
+        }
+        image
+    }
+
+    #[test]
+    fn test_propose_mappings() {
+        let image = image_of(&[
+            [20, 10, 30, 255],
+            [250, 240, 200, 255],
+            [220, 200, 160, 255],
+            [90, 60, 30, 255],
+            [60, 40, 20, 255],
+            [40, 60, 200, 255],
+            [0, 0, 0, 0],
+        ]);
+
+        let mappings = propose_mappings(&image, AssetType::Face).expect("Failed to map");
+        let slot = |colour: Rgba| {
+            mappings
+                .iter()
+                .find(|mapping| mapping.colour == colour)
+                .map(|mapping| (mapping.colourable, mapping.shade))
+        };
+
+        assert_eq!(mappings.len(), 6);
+        assert_eq!(
+            slot(Rgba::new(20, 10, 30, 255)),
+            Some((Colourable::Outline, Shade::Neutral))
+        );
+        assert_eq!(
+            slot(Rgba::new(250, 240, 200, 255)),
+            Some((Colourable::Skin, Shade::Lighter))
+        );
+        assert_eq!(
+            slot(Rgba::new(220, 200, 160, 255)),
+            Some((Colourable::Skin, Shade::Darker))
+        );
+        assert_eq!(
+            slot(Rgba::new(90, 60, 30, 255)),
+            Some((Colourable::EyeAndBeard, Shade::Lighter))
+        );
+        assert_eq!(
+            slot(Rgba::new(60, 40, 20, 255)),
+            Some((Colourable::EyeAndBeard, Shade::Darker))
+        );
+        assert_eq!(
+            slot(Rgba::new(40, 60, 200, 255)),
+            Some((Colourable::Accessory, Shade::Neutral))
+        );
+    }
+
+    #[test]
+    fn test_import_image_round_trip() {
+        let image = image_of(&[
+            [20, 10, 30, 255],
+            [250, 240, 200, 255],
+            [90, 60, 30, 128],
+            [0, 0, 0, 0],
+        ]);
+
+        let imported = import_image(&image, AssetType::Face).expect("Failed to import");
+
+        assert_eq!(imported.image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(imported.image.get_pixel(2, 0).0[3], 128);
+        assert_eq!(imported.image.get_pixel(3, 0).0, [0, 0, 0, 0]);
+        assert_eq!(imported.outline, Some(Rgba::new(20, 10, 30, 255)));
+
+        // Recolouring the keyed art with the extracted colours gives back the original.
+        let mut outlines = crate::character::Outlines::default();
+        outlines.set_outline_colour(AssetType::Face, &Rgba::new(20, 10, 30, 255));
+        let mut colours = Character::default().character_colours;
+        colours.extend(imported.colours);
+        let mut recoloured = imported.image.clone();
+        crate::recolour::recolour(&mut recoloured, AssetType::Face, false, &colours, &outlines);
+        for x in 0..2 {
+            assert_eq!(recoloured.get_pixel(x, 0), image.get_pixel(x, 0));
+        }
+        assert_eq!(recoloured.get_pixel(2, 0).0[..3], [90, 60, 30]);
+    }
+
+    #[test]
+    fn test_extract_colours_derives_missing_shades() {
+        let (colours, outline) = extract_colours(&[ColourMapping {
+            colour: Rgba::new(100, 100, 100, 255),
+            colourable: Colourable::Metal,
+            shade: Shade::Lighter,
+        }]);
+
+        let metal = &colours[&Colourable::Metal];
+        assert_eq!(metal.lighter, Rgba::new(100, 100, 100, 255));
+        assert_eq!(metal.base, Rgba::new(100, 100, 100, 255));
+        assert_eq!(metal.darker, CharacterPartColours::new(&metal.base).darker);
+        assert_eq!(outline, None);
+    }
+
+    #[test]
+    fn test_extract_colour_scheme() {
+        let skin = CharacterPartColours::new(&Rgba::new(240, 230, 190, 255));
+        let hair = CharacterPartColours::new(&Rgba::new(200, 190, 60, 255));
+        let image = image_of(
+            &[
+                Rgba::new(56, 32, 64, 255),
+                skin.lighter,
+                skin.neutral,
+                skin.darker,
+                skin.darker_darker,
+                skin.darker_darker_darker,
+                hair.lighter,
+                hair.neutral,
+                hair.darker,
+            ]
+            .map(<[u8; 4]>::from),
+        );
+
+        let scheme = extract_colour_scheme(&image).expect("Failed to extract colours");
+
+        assert_eq!(scheme.len(), 2);
+        assert_eq!(scheme[&Colourable::Skin], skin);
+        assert_eq!(scheme[&Colourable::Hair], hair);
+    }
+
+    #[test]
+    fn test_extract_colour_scheme_without_ramps() {
+        let image = image_of(&[[56, 32, 64, 255], [200, 20, 20, 255]]);
+        assert!(extract_colour_scheme(&image).is_err());
+    }
+
+    #[test]
+    fn test_propose_mappings_too_many_colours() {
+        let colours: Vec<[u8; 4]> = (0..=MAX_COLOURS)
+            .map(|i| [(i % 256) as u8, (i / 256) as u8, 0, 255])
+            .collect();
+        assert!(propose_mappings(&image_of(&colours), AssetType::Face).is_err());
+    }
+}
@@ -1,9 +1,14 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
 pub mod asset;
+pub mod bundle;
 pub mod character;
 pub mod export;
 pub mod extensions;
+pub mod fecc_file;
 pub mod file_io;
+pub mod gba_palette;
+pub mod import;
+pub mod lint;
 pub mod random;
 pub mod recolour;
 pub mod types;
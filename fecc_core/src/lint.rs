@@ -0,0 +1,392 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+#[cfg(not(target_arch = "wasm32"))]
+use crate::asset::Asset;
+use crate::asset::AssetType;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::asset::metadata::AssetMetadata;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::file_io::read_metadata;
+use crate::recolour::scheme::{KEY_STEP, Key};
+use image::RgbaImage;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Where and how often a problem occurs in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Pixels {
+    pub count: usize,
+    /// The `(x, y)` position of the first pixel with the problem, reading left to right.
+    pub first: (u32, u32),
+}
+
+/// A problem found in an art file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LintIssue {
+    /// The file couldn't be read or decoded as an image.
+    Unreadable { reason: String },
+    /// The filename isn't `Name_Type.png` with a known type, so the file won't be loaded.
+    InvalidFilename { reason: String },
+    /// The image isn't the size of art for its category's canvas.
+    WrongDimensions {
+        width: u32,
+        height: u32,
+        expected: u32,
+    },
+    /// Pixels whose red value isn't a multiple of 10, which are read as the key below it.
+    OffKeyRed {
+        red: u8,
+        #[serde(flatten)]
+        pixels: Pixels,
+    },
+    /// Pixels of extended art whose green value isn't a multiple of 10, which are read as the key
+    /// below it.
+    OffKeyGreen {
+        green: u8,
+        #[serde(flatten)]
+        pixels: Pixels,
+    },
+    /// Pixels keyed to a slot the asset's type doesn't have, which are never recoloured.
+    UnknownSlot {
+        key: u8,
+        /// The green key of extended art, left out when it is 0.
+        #[serde(skip_serializing_if = "is_zero")]
+        green_key: u8,
+        #[serde(flatten)]
+        pixels: Pixels,
+    },
+}
+
+const fn is_zero(key: &u8) -> bool {
+    *key == 0
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |pixels: &Pixels| {
+            format!(
+                "{} pixel(s), first at ({}, {})",
+                pixels.count, pixels.first.0, pixels.first.1
+            )
+        };
+        match self {
+            Self::Unreadable { reason } => write!(f, "can't be read: {reason}"),
+            Self::InvalidFilename { reason } => write!(f, "invalid filename: {reason}"),
+            Self::WrongDimensions {
+                width,
+                height,
+                expected,
+            } => write!(
+                f,
+                "is {width}x{height}, but should be {expected}x{expected}"
+            ),
+            Self::OffKeyRed { red, pixels } => write!(
+                f,
+                "red {red} isn't a multiple of {KEY_STEP} and is read as key {}: {}",
+                red / KEY_STEP,
+                at(pixels)
+            ),
+            Self::OffKeyGreen { green, pixels } => write!(
+                f,
+                "green {green} isn't a multiple of {KEY_STEP} and is read as green key {}: {}",
+                green / KEY_STEP,
+                at(pixels)
+            ),
+            Self::UnknownSlot {
+                key,
+                green_key: 0,
+                pixels,
+            } => write!(
+                f,
+                "key {key} (red {}) isn't a colour slot for this type and won't be recoloured: {}",
+                u16::from(*key) * u16::from(KEY_STEP),
+                at(pixels)
+            ),
+            Self::UnknownSlot {
+                key,
+                green_key,
+                pixels,
+            } => write!(
+                f,
+                "key {key} with green key {green_key} (red {}, green {}) isn't a colour slot for \
+                 this type and won't be recoloured: {}",
+                u16::from(*key) * u16::from(KEY_STEP),
+                u16::from(*green_key) * u16::from(KEY_STEP),
+                at(pixels)
+            ),
+        }
+    }
+}
+
+/// The issues found in one art file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintReport {
+    pub path: PathBuf,
+    pub issues: Vec<LintIssue>,
+}
+
+impl LintReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Pixels {
+    /// Starts counting the pixels with a problem, from the first one found.
+    fn starting_at(x: u32, y: u32) -> Self {
+        Self {
+            count: 0,
+            first: (x, y),
+        }
+    }
+}
+
+/// Checks the pixels and size of an image against the rules for art of the given type.
+///
+/// Slots and dimensions are only checked when the type is known, and green values only for extended
+/// art. Fully transparent pixels are ignored.
+pub fn lint_image(
+    image: &RgbaImage,
+    asset_type: Option<AssetType>,
+    extended_keys: bool,
+) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let category = asset_type.map(AssetType::category);
+
+    if let Some(category) = category {
+        let expected = category.canvas.art_size();
+        if image.dimensions() != (expected, expected) {
+            issues.push(LintIssue::WrongDimensions {
+                width: image.width(),
+                height: image.height(),
+                expected,
+            });
+        }
+    }
+
+    let mut off_key: BTreeMap<u8, Pixels> = BTreeMap::new();
+    let mut off_key_green: BTreeMap<u8, Pixels> = BTreeMap::new();
+    let mut unknown_slots: BTreeMap<Key, Pixels> = BTreeMap::new();
+    let layout = category.map(|category| category.recolour_scheme.layout(extended_keys));
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let [red, green, _, alpha] = pixel.0;
+        if alpha == 0 {
+            continue;
+        }
+
+        if extended_keys && green % KEY_STEP != 0 {
+            off_key_green
+                .entry(green)
+                .or_insert(Pixels::starting_at(x, y))
+                .count += 1;
+        }
+        let key = Key::of_pixel(red, green, extended_keys);
+        if red % KEY_STEP != 0 {
+            off_key
+                .entry(red)
+                .or_insert(Pixels::starting_at(x, y))
+                .count += 1;
+        } else if let Some(layout) = &layout
+            && layout.slot(key).is_none()
+        {
+            unknown_slots
+                .entry(key)
+                .or_insert(Pixels::starting_at(x, y))
+                .count += 1;
+        }
+    }
+
+    issues.extend(
+        off_key
+            .into_iter()
+            .map(|(red, pixels)| LintIssue::OffKeyRed { red, pixels }),
+    );
+    issues.extend(
+        off_key_green
+            .into_iter()
+            .map(|(green, pixels)| LintIssue::OffKeyGreen { green, pixels }),
+    );
+    issues.extend(
+        unknown_slots
+            .into_iter()
+            .map(|(key, pixels)| LintIssue::UnknownSlot {
+                key: key.red,
+                green_key: key.green,
+                pixels,
+            }),
+    );
+    issues
+}
+
+/// Checks an art file's name and contents, see `lint_image`.
+///
+/// The file is extended art if its metadata sidecars say so.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn lint_file(path: &Path) -> LintReport {
+    let mut issues = Vec::new();
+
+    let asset_type = match Asset::try_from(path) {
+        Ok(asset) => Some(asset.asset_type),
+        Err(reason) => {
+            issues.push(LintIssue::InvalidFilename { reason });
+            None
+        }
+    };
+
+    match image::open(path) {
+        Ok(image) => {
+            let folder_metadata = path
+                .parent()
+                .and_then(|dir| read_metadata(&dir.join(AssetMetadata::FOLDER_FILE)))
+                .unwrap_or_default();
+            let extended_keys = read_metadata(&path.with_extension("json"))
+                .unwrap_or_default()
+                .merged_with(&folder_metadata)
+                .uses_extended_keys();
+            issues.extend(lint_image(&image.to_rgba8(), asset_type, extended_keys));
+        }
+        Err(e) => issues.push(LintIssue::Unreadable {
+            reason: e.to_string(),
+        }),
+    }
+
+    LintReport {
+        path: path.to_path_buf(),
+        issues,
+    }
+}
+
+/// Checks an art file, or every `.png` file within a directory and its subdirectories.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn lint_path(path: &Path) -> Result<Vec<LintReport>, String> {
+    if !path.is_dir() {
+        return Ok(vec![lint_file(path)]);
+    }
+
+    let pattern = format!(
+        "{}/**/*.png",
+        glob::Pattern::escape(&path.to_string_lossy())
+    );
+    Ok(glob::glob(&pattern)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .map(|file| lint_file(&file))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_lint_image_clean() {
+        let mut image = RgbaImage::new(96, 96);
+        image.put_pixel(0, 0, Rgba([110, 3, 4, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 0, 255]));
+
+        assert!(lint_image(&image, Some(AssetType::Face), false).is_empty());
+    }
+
+    #[test]
+    fn test_lint_image_issues() {
+        let mut image = RgbaImage::new(64, 64);
+        image.put_pixel(5, 0, Rgba([15, 0, 0, 255]));
+        image.put_pixel(2, 1, Rgba([15, 0, 0, 255]));
+        image.put_pixel(0, 2, Rgba([130, 0, 0, 255]));
+        image.put_pixel(1, 2, Rgba([0, 0, 0, 128]));
+        image.put_pixel(2, 2, Rgba([215, 0, 0, 0]));
+
+        assert_eq!(
+            lint_image(&image, Some(AssetType::Face), false),
+            [
+                LintIssue::WrongDimensions {
+                    width: 64,
+                    height: 64,
+                    expected: 96
+                },
+                LintIssue::OffKeyRed {
+                    red: 15,
+                    pixels: Pixels {
+                        count: 2,
+                        first: (5, 0)
+                    }
+                },
+                LintIssue::UnknownSlot {
+                    key: 13,
+                    green_key: 0,
+                    pixels: Pixels {
+                        count: 1,
+                        first: (0, 2)
+                    }
+                },
+            ]
+        );
+
+        // Trim keys are fine in armour, and the size of token art isn't known without a type.
+        assert_eq!(lint_image(&image, Some(AssetType::Armour), false).len(), 2);
+        assert_eq!(lint_image(&image, None, false).len(), 1);
+    }
+
+    #[test]
+    fn test_lint_image_extended() {
+        let mut image = RgbaImage::new(96, 96);
+        image.put_pixel(0, 0, Rgba([70, 10, 0, 255]));
+        image.put_pixel(1, 0, Rgba([200, 15, 0, 255]));
+
+        // Green values don't matter in standard art.
+        assert!(lint_image(&image, Some(AssetType::Armour), false).is_empty());
+        assert_eq!(
+            lint_image(&image, Some(AssetType::Armour), true),
+            [
+                LintIssue::OffKeyGreen {
+                    green: 15,
+                    pixels: Pixels {
+                        count: 1,
+                        first: (1, 0)
+                    }
+                },
+                LintIssue::UnknownSlot {
+                    key: 20,
+                    green_key: 1,
+                    pixels: Pixels {
+                        count: 1,
+                        first: (1, 0)
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_issue_json() {
+        let issue = LintIssue::OffKeyRed {
+            red: 15,
+            pixels: Pixels {
+                count: 2,
+                first: (5, 0),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&issue).expect("Failed to serialise"),
+            r#"{"kind":"off_key_red","red":15,"count":2,"first":[5,0]}"#
+        );
+    }
+
+    #[test]
+    fn test_lint_file_invalid_filename() {
+        let report = lint_file(Path::new("art/Missing.png"));
+        assert!(matches!(
+            report.issues.as_slice(),
+            [
+                LintIssue::InvalidFilename { .. },
+                LintIssue::Unreadable { .. }
+            ]
+        ));
+    }
+}
@@ -1,60 +1,44 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
 use crate::asset::{Asset, AssetType};
-use crate::character::{Character, CharacterPart, CharacterPartColours, ColourPalette, Colourable};
+use crate::character::{Character, CharacterPart, ColourPalette, Colourable};
 use crate::types::Point;
 use indexmap::IndexMap;
 use rand::prelude::*;
 use std::collections::HashMap;
 
 /// Randomises the specified parts of the character using the provided asset libraries.
+///
+/// Parts are placed according to their category, see `CharacterPart::placed`. Only assets with
+/// every one of the given tags are chosen from; a part is left as it is if no asset of its type
+/// has them all.
 pub fn randomize_assets(
     character: &mut Character,
     asset_libraries: &HashMap<AssetType, IndexMap<String, Asset>>,
     types_to_randomize: &[AssetType],
     canvas_size: Point,
+    tags: &[String],
 ) {
     let mut rng = rand::rng();
-    let scale = (canvas_size.y / 96.0).floor().max(1.0);
-    let center = Point::new(canvas_size.x / 2.0, canvas_size.y / 2.0);
 
     for &asset_type in types_to_randomize {
         if let Some(library) = asset_libraries.get(&asset_type)
-            && let Some((_, random_asset)) = library.iter().choose(&mut rng)
+            && let Some(random_asset) = library
+                .values()
+                .filter(|asset| tags.iter().all(|tag| asset.metadata.has_tag(tag)))
+                .choose(&mut rng)
         {
-            let mut position = center;
+            let part = CharacterPart::placed(random_asset.clone(), canvas_size);
 
-            // Special case for Armour positioning (aligned to bottom)
-            if asset_type == AssetType::Armour {
-                let scaled_asset_height = 96.0 * scale;
-                position.y = canvas_size.y - (scaled_asset_height / 2.0);
-            }
-
-            let part = CharacterPart {
-                position,
-                scale,
-                rotation: 0.0,
-                flipped: false,
-                asset: random_asset.clone(),
-            };
-
-            if asset_type == AssetType::Hair {
-                if let Some(back_part_id) = &random_asset.back_part {
-                    if let Some(back_asset) = asset_libraries
-                        .get(&AssetType::HairBack)
-                        .and_then(|lib| lib.get(back_part_id))
-                    {
-                        character.set_character_part(
-                            &AssetType::HairBack,
-                            CharacterPart {
-                                asset: back_asset.clone(),
-                                flipped: part.flipped,
-                                ..part.clone()
-                            },
-                        );
-                    }
-                } else {
-                    character.remove_character_part(&AssetType::HairBack);
-                }
+            let companion_type = asset_type.companion_type();
+            match random_asset.find_companion(asset_libraries) {
+                Some(back_asset) => character.set_character_part(
+                    &companion_type,
+                    CharacterPart {
+                        asset: back_asset.clone(),
+                        ..part.clone()
+                    },
+                ),
+                None => character.remove_character_part(&companion_type),
             }
 
             character.set_character_part(&asset_type, part);
@@ -75,9 +59,12 @@ pub fn randomize_colours(
         if let Some(palette) = colour_palettes.get(&colourable)
             && let Some(random_color) = palette.colours().choose(&mut rng)
         {
+            // Keep the colourable's ramp, so its shading stays as the user chose.
             character
                 .character_colours
-                .insert(colourable, CharacterPartColours::new(random_color));
+                .entry(colourable)
+                .or_default()
+                .set(*random_color, character.gba_colours);
         }
     }
 }
@@ -121,10 +108,23 @@ mod tests {
         let types_to_randomize = vec![AssetType::Face];
         let canvas_size = Point::new(100.0, 100.0);
 
-        randomize_assets(&mut character, &libraries, &types_to_randomize, canvas_size);
+        randomize_assets(
+            &mut character,
+            &libraries,
+            &types_to_randomize,
+            canvas_size,
+            &[],
+        );
 
-        assert!(character.face.is_some());
-        assert_eq!(character.face.as_ref().unwrap().asset.id, face_asset.id);
+        assert!(character.get_character_part(&AssetType::Face).is_some());
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::Face)
+                .unwrap()
+                .asset
+                .id,
+            face_asset.id
+        );
     }
 
     #[test]
@@ -155,14 +155,135 @@ mod tests {
         let types_to_randomize = vec![AssetType::Hair];
         let canvas_size = Point::new(100.0, 100.0);
 
-        randomize_assets(&mut character, &libraries, &types_to_randomize, canvas_size);
+        randomize_assets(
+            &mut character,
+            &libraries,
+            &types_to_randomize,
+            canvas_size,
+            &[],
+        );
 
-        assert!(character.hair.is_some());
-        assert!(character.hair_back.is_some());
-        assert_eq!(character.hair.as_ref().unwrap().asset.id, hair_asset.id);
+        assert!(character.get_character_part(&AssetType::Hair).is_some());
+        assert!(character.get_character_part(&AssetType::HairBack).is_some());
         assert_eq!(
-            character.hair_back.as_ref().unwrap().asset.id,
+            character
+                .get_character_part(&AssetType::Hair)
+                .unwrap()
+                .asset
+                .id,
+            hair_asset.id
+        );
+        assert_eq!(
+            character
+                .get_character_part(&AssetType::HairBack)
+                .unwrap()
+                .asset
+                .id,
             hair_back_asset.id
         );
     }
+
+    #[test]
+    fn test_randomize_assets_removes_stale_companion() {
+        let mut character = Character::default();
+        let companion_type = AssetType::Armour.companion_type();
+        let library = |asset: Asset| -> IndexMap<String, Asset> {
+            [(asset.id.clone(), asset)].into_iter().collect()
+        };
+
+        let caped = Asset::new(
+            "Caped".to_string(),
+            std::path::PathBuf::new(),
+            Asset::companion_id("Caped", AssetType::Armour),
+            AssetType::Armour,
+        );
+        let cape = Asset::new(
+            "Caped".to_string(),
+            std::path::PathBuf::new(),
+            None,
+            companion_type,
+        );
+        let mut libraries = HashMap::from([
+            (AssetType::Armour, library(caped)),
+            (companion_type, library(cape.clone())),
+        ]);
+        let canvas_size = Point::new(100.0, 100.0);
+
+        randomize_assets(
+            &mut character,
+            &libraries,
+            &[AssetType::Armour],
+            canvas_size,
+            &[],
+        );
+        let back = character.get_character_part(&companion_type).unwrap();
+        assert_eq!(back.asset.id, cape.id);
+        assert_eq!(
+            back.position,
+            character
+                .get_character_part(&AssetType::Armour)
+                .unwrap()
+                .position
+        );
+
+        let plain = Asset::new(
+            "Plain".to_string(),
+            std::path::PathBuf::new(),
+            Asset::companion_id("Plain", AssetType::Armour),
+            AssetType::Armour,
+        );
+        libraries.insert(AssetType::Armour, library(plain));
+
+        randomize_assets(
+            &mut character,
+            &libraries,
+            &[AssetType::Armour],
+            canvas_size,
+            &[],
+        );
+        assert!(character.get_character_part(&companion_type).is_none());
+    }
+
+    #[test]
+    fn test_randomize_assets_with_tags() {
+        let mut character = Character::default();
+        let mut face_assets = IndexMap::new();
+        for (name, tags) in [
+            ("Knight", vec!["armoured"]),
+            ("Mage", vec!["Robed", "female"]),
+        ] {
+            let mut asset = Asset::new(
+                name.to_string(),
+                std::path::PathBuf::new(),
+                None,
+                AssetType::Face,
+            );
+            asset.metadata.tags = tags.into_iter().map(str::to_owned).collect();
+            face_assets.insert(asset.id.clone(), asset);
+        }
+        let libraries = HashMap::from([(AssetType::Face, face_assets)]);
+        let canvas_size = Point::new(100.0, 100.0);
+
+        for _ in 0..10 {
+            randomize_assets(
+                &mut character,
+                &libraries,
+                &[AssetType::Face],
+                canvas_size,
+                &["robed".to_owned()],
+            );
+            let face = character.get_character_part(&AssetType::Face).unwrap();
+            assert_eq!(face.asset.id, "Mage_Face");
+        }
+
+        randomize_assets(
+            &mut character,
+            &libraries,
+            &[AssetType::Face],
+            canvas_size,
+            &["armoured".to_owned(), "female".to_owned()],
+        );
+        let face = character.get_character_part(&AssetType::Face).unwrap();
+        assert_eq!(face.asset.id, "Mage_Face");
+    }
 }
@@ -1,100 +1,41 @@
 // Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
 use crate::asset::AssetType;
-use crate::character::Colourable::{
-    Accessory, Cloth, EyeAndBeard, Hair, Leather, Metal, Skin, Trim,
-};
-use crate::character::{CharacterPartColours, Colourable, Outlines};
-use crate::types::Rgba;
+use crate::character::{CharacterPartColours, Colourable, Outlines, Shade};
 use image::RgbaImage;
-use std::collections::HashMap;
-
-const OUTLINE_INDEX: usize = 0;
-// Used for either Eye / Beard shades or Hair shades depending on asset type.
-const MULTI_LIGHTER_SHADE_INDEX: usize = 1;
-const MULTI_NEUTRAL_SHADE_INDEX: usize = 2;
-const MULTI_DARKER_SHADE_INDEX: usize = 3;
-// Skin shades
-const SKIN_LIGHTER_SHADE_INDEX: usize = 4;
-const SKIN_NEUTRAL_SHADE_INDEX: usize = 5;
-const SKIN_DARKER_SHADE_INDEX: usize = 6;
-const SKIN_DARKER_DARKER_SHADE_INDEX: usize = 7;
-const SKIN_DARKER_DARKER_DARKER_SHADE_INDEX: usize = 8;
-// Accessory / Metal shades
-const ACC_METAL_LIGHTER_SHADE_INDEX: usize = 9;
-const ACC_METAL_NEUTRAL_SHADE_INDEX: usize = 10;
-const ACC_METAL_DARKER_SHADE_INDEX: usize = 11;
-// Trim shades
-const TRIM_LIGHTER_SHADE_INDEX: usize = 12;
-const TRIM_NEUTRAL_SHADE_INDEX: usize = 13;
-const TRIM_DARKER_SHADE_INDEX: usize = 14;
-// Cloth shades
-const CLOTH_LIGHTER_SHADE_INDEX: usize = 15;
-const CLOTH_NEUTRAL_SHADE_INDEX: usize = 16;
-const CLOTH_DARKER_SHADE_INDEX: usize = 17;
-// Leather shades
-const LEATHER_LIGHTER_SHADE_INDEX: usize = 18;
-const LEATHER_NEUTRAL_SHADE_INDEX: usize = 19;
-const LEATHER_DARKER_SHADE_INDEX: usize = 20;
+use scheme::KEY_COUNT;
+use std::collections::{BTreeMap, HashMap};
+
+pub mod cache;
+pub mod scheme;
 
 /// Recolours an RgbaImage
 ///
 /// The implementation uses a lookup table (LUT) for performance,
-/// mapping red channel values to their final colours before iterating over the pixels,
-/// avoiding repeated hash map lookups in the inner loop.
+/// mapping red channel keys to their final colours, as laid out by the asset type's recolour
+/// scheme, before iterating over the pixels, avoiding repeated hash map lookups in the inner loop.
+/// Keys without a slot, or whose colourable has no colours, are left as they are. Extended art
+/// is keyed by its green channel as well as its red one. Recoloured pixels keep their alpha,
+/// multiplied by the target colour's, so anti-aliased and semi-transparent art stays that way.
 pub fn recolour(
     image: &mut RgbaImage,
     asset_type: AssetType,
+    extended_keys: bool,
     character_colours: &HashMap<Colourable, CharacterPartColours>,
     outline_colours: &Outlines,
 ) {
-    let mut recolour_map: [Option<Rgba>; 21] = [None; 21];
-
-    // Map source colour keys (0-20) to target colours.
-    // The key is derived from the red channel: (red / 10).
-    // Indices correspond to specific shades in the palette.
-    if asset_type == AssetType::Face || asset_type == AssetType::Accessory {
-        recolour_map[OUTLINE_INDEX] = Some(outline_colours.get_outline_colour(asset_type));
-        recolour_map[MULTI_LIGHTER_SHADE_INDEX] = Some(character_colours[&EyeAndBeard].lighter);
-        recolour_map[MULTI_NEUTRAL_SHADE_INDEX] = Some(character_colours[&EyeAndBeard].neutral);
-        recolour_map[MULTI_DARKER_SHADE_INDEX] = Some(character_colours[&EyeAndBeard].darker);
-        recolour_map[SKIN_LIGHTER_SHADE_INDEX] = Some(character_colours[&Skin].lighter);
-        recolour_map[SKIN_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Skin].neutral);
-        recolour_map[SKIN_DARKER_SHADE_INDEX] = Some(character_colours[&Skin].darker);
-        recolour_map[SKIN_DARKER_DARKER_SHADE_INDEX] = Some(character_colours[&Skin].darker_darker);
-        recolour_map[SKIN_DARKER_DARKER_DARKER_SHADE_INDEX] =
-            Some(character_colours[&Skin].darker_darker_darker);
-        recolour_map[ACC_METAL_LIGHTER_SHADE_INDEX] = Some(character_colours[&Accessory].lighter);
-        recolour_map[ACC_METAL_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Accessory].neutral);
-        recolour_map[ACC_METAL_DARKER_SHADE_INDEX] = Some(character_colours[&Accessory].darker);
-    } else {
-        recolour_map[OUTLINE_INDEX] = Some(outline_colours.get_outline_colour(asset_type));
-        // Hair
-        recolour_map[MULTI_LIGHTER_SHADE_INDEX] = Some(character_colours[&Hair].lighter);
-        recolour_map[MULTI_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Hair].neutral);
-        recolour_map[MULTI_DARKER_SHADE_INDEX] = Some(character_colours[&Hair].darker);
-        // Skin
-        recolour_map[SKIN_LIGHTER_SHADE_INDEX] = Some(character_colours[&Skin].lighter);
-        recolour_map[SKIN_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Skin].neutral);
-        recolour_map[SKIN_DARKER_SHADE_INDEX] = Some(character_colours[&Skin].darker);
-        recolour_map[SKIN_DARKER_DARKER_SHADE_INDEX] = Some(character_colours[&Skin].darker_darker);
-        recolour_map[SKIN_DARKER_DARKER_DARKER_SHADE_INDEX] =
-            Some(character_colours[&Skin].darker_darker_darker);
-        // Metal
-        recolour_map[ACC_METAL_LIGHTER_SHADE_INDEX] = Some(character_colours[&Metal].lighter);
-        recolour_map[ACC_METAL_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Metal].neutral);
-        recolour_map[ACC_METAL_DARKER_SHADE_INDEX] = Some(character_colours[&Metal].darker);
-        // Trim
-        recolour_map[TRIM_LIGHTER_SHADE_INDEX] = Some(character_colours[&Trim].lighter);
-        recolour_map[TRIM_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Trim].neutral);
-        recolour_map[TRIM_DARKER_SHADE_INDEX] = Some(character_colours[&Trim].darker);
-        // Cloth
-        recolour_map[CLOTH_LIGHTER_SHADE_INDEX] = Some(character_colours[&Cloth].lighter);
-        recolour_map[CLOTH_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Cloth].neutral);
-        recolour_map[CLOTH_DARKER_SHADE_INDEX] = Some(character_colours[&Cloth].darker);
-        // Leather
-        recolour_map[LEATHER_LIGHTER_SHADE_INDEX] = Some(character_colours[&Leather].lighter);
-        recolour_map[LEATHER_NEUTRAL_SHADE_INDEX] = Some(character_colours[&Leather].neutral);
-        recolour_map[LEATHER_DARKER_SHADE_INDEX] = Some(character_colours[&Leather].darker);
+    let layout = asset_type.category().recolour_scheme.layout(extended_keys);
+    let outline_colour = outline_colours.get_outline_colour(asset_type);
+
+    // Map source colour keys (0-25, by green key then red key) to target colours.
+    // The red key is derived from the red channel: (red / 10), and likewise the green key.
+    let mut recolour_map = [[None; KEY_COUNT]; KEY_COUNT];
+    for (key, colourable, shade) in layout.slots() {
+        recolour_map[usize::from(key.green)][usize::from(key.red)] = match colourable {
+            Colourable::Outline => Some(outline_colour),
+            _ => character_colours
+                .get(&colourable)
+                .map(|colours| colours.shade(shade)),
+        };
     }
 
     for pixel in image.pixels_mut() {
@@ -105,17 +46,98 @@ pub fn recolour(
             continue;
         }
 
-        let red_key = (channels[0] / 10) as usize;
+        let key = layout.key_of_pixel(channels[0], channels[1]);
+
+        if let Some(Some(new_colour)) = recolour_map
+            .get(usize::from(key.green))
+            .and_then(|keys| keys.get(usize::from(key.red)))
+        {
+            *pixel = image::Rgba([
+                new_colour.r,
+                new_colour.g,
+                new_colour.b,
+                multiply_alpha(channels[3], new_colour.a),
+            ]);
+        }
+    }
+}
+
+/// How many pixels of some art are keyed to each shade of each colourable, including its outline.
+///
+/// Art is only changed by the colours of the colourables it uses when it's recoloured, see
+/// `cache::RecolourCache`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ColourUsage {
+    pixels: BTreeMap<(Colourable, Shade), usize>,
+}
 
-        if let Some(Some(new_colour)) = recolour_map.get(red_key) {
-            *pixel = image::Rgba([new_colour.r, new_colour.g, new_colour.b, new_colour.a]);
+impl ColourUsage {
+    /// Counts the pixels of an image keyed to each slot of its type's recolour scheme. Transparent
+    /// pixels and keys without a slot aren't counted.
+    pub fn of_image(image: &RgbaImage, asset_type: AssetType, extended_keys: bool) -> Self {
+        let layout = asset_type.category().recolour_scheme.layout(extended_keys);
+        let mut counts = [[0; KEY_COUNT]; KEY_COUNT];
+        for pixel in image.pixels().filter(|pixel| pixel[3] != 0) {
+            let key = layout.key_of_pixel(pixel[0], pixel[1]);
+            if let Some(count) = counts
+                .get_mut(usize::from(key.green))
+                .and_then(|keys| keys.get_mut(usize::from(key.red)))
+            {
+                *count += 1;
+            }
         }
+
+        let pixels = layout
+            .slots()
+            .map(|(key, colourable, shade)| {
+                let count = counts[usize::from(key.green)][usize::from(key.red)];
+                ((colourable, shade), count)
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        Self { pixels }
+    }
+
+    /// Returns the colourables the art uses.
+    pub fn colourables(&self) -> Vec<Colourable> {
+        let mut colourables: Vec<Colourable> = self
+            .pixels
+            .keys()
+            .map(|&(colourable, _)| colourable)
+            .collect();
+        colourables.dedup();
+        colourables
+    }
+
+    /// Returns whether the art uses any shade of a colourable.
+    pub fn uses(&self, colourable: Colourable) -> bool {
+        self.pixel_count(colourable) > 0
+    }
+
+    /// Returns how many pixels use any shade of a colourable.
+    pub fn pixel_count(&self, colourable: Colourable) -> usize {
+        self.shades(colourable).map(|(_, count)| count).sum()
+    }
+
+    /// Returns the shades of a colourable the art uses, and how many pixels use each, from lightest
+    /// to darkest.
+    pub fn shades(&self, colourable: Colourable) -> impl Iterator<Item = (Shade, usize)> + '_ {
+        self.pixels
+            .range((colourable, Shade::Lighter)..=(colourable, Shade::DarkerDarkerDarker))
+            .map(|(&(_, shade), &count)| (shade, count))
     }
 }
 
+/// Multiplies two alpha values, rounding to the nearest.
+const fn multiply_alpha(a: u8, b: u8) -> u8 {
+    ((a as u16 * b as u16 + 127) / 255) as u8
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::character::Colourable::{Cloth, EyeAndBeard, Hair, Leather, Metal, Skin, Trim};
+    use crate::types::Rgba;
 
     #[test]
     fn test_recolor_face() {
@@ -127,8 +149,7 @@ mod tests {
         image.put_pixel(1, 0, image::Rgba([10, 0, 0, 255]));
         // 40 -> Skin Lighter
         image.put_pixel(0, 1, image::Rgba([40, 0, 0, 255]));
-        // 255 -> Unmapped (should be ignored/unchanged if out of bounds or None,
-        // but 255/10 = 25 which is > 20, so it returns None from map)
+        // 250 -> Unmapped (should be ignored/unchanged, as the face scheme has no key 25)
         image.put_pixel(1, 1, image::Rgba([250, 0, 0, 255]));
 
         let outline_colour = Rgba::new(10, 10, 10, 255);
@@ -153,10 +174,9 @@ mod tests {
         };
         char_colours.insert(Skin, skin_parts);
 
-        // Fill others with default to avoid panic
-        char_colours.insert(Accessory, CharacterPartColours::default());
+        // Accessory has no colours, so its keys are left as they are.
 
-        recolour(&mut image, AssetType::Face, &char_colours, &outlines);
+        recolour(&mut image, AssetType::Face, false, &char_colours, &outlines);
 
         // Check Outline (Index 0)
         let p00 = image.get_pixel(0, 0);
@@ -191,14 +211,110 @@ mod tests {
         };
         char_colours.insert(Cloth, cloth_parts);
 
-        // Fill others with default to avoid panic
+        // Fill others with default colours
         for colourable in [Hair, Skin, Metal, Trim, Leather] {
             char_colours.insert(colourable, CharacterPartColours::default());
         }
 
-        recolour(&mut image, AssetType::Armour, &char_colours, &outlines);
+        recolour(
+            &mut image,
+            AssetType::Armour,
+            false,
+            &char_colours,
+            &outlines,
+        );
 
         let p00 = image.get_pixel(0, 0);
         assert_eq!(p00[0], cloth_lighter.r);
     }
+
+    #[test]
+    fn test_recolor_extended() {
+        let mut image = RgbaImage::new(2, 1);
+        // Red 70, green 10 -> Gem Neutral (7, 1) in extended art, Skin Darker Darker (7) otherwise
+        image.put_pixel(0, 0, image::Rgba([70, 10, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([70, 0, 0, 255]));
+        let mut standard = image.clone();
+
+        let character = crate::character::Character::default();
+        let colours = &character.character_colours;
+        recolour(
+            &mut image,
+            AssetType::Armour,
+            true,
+            colours,
+            &Outlines::default(),
+        );
+        recolour(
+            &mut standard,
+            AssetType::Armour,
+            false,
+            colours,
+            &Outlines::default(),
+        );
+
+        let gem = colours[&Colourable::Gem].neutral;
+        let skin = colours[&Skin].darker_darker;
+        assert_eq!(image.get_pixel(0, 0).0, [gem.r, gem.g, gem.b, gem.a]);
+        assert_eq!(image.get_pixel(1, 0).0, [skin.r, skin.g, skin.b, skin.a]);
+        assert_eq!(standard.get_pixel(0, 0), standard.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn test_colour_usage() {
+        let mut image = RgbaImage::new(5, 1);
+        image.put_pixel(0, 0, image::Rgba([160, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([0, 0, 0, 255]));
+        image.put_pixel(2, 0, image::Rgba([20, 0, 0, 255]));
+        image.put_pixel(3, 0, image::Rgba([150, 0, 0, 128]));
+        // Transparent pixels and keys without a slot don't count.
+        image.put_pixel(4, 0, image::Rgba([190, 0, 0, 0]));
+
+        let usage = ColourUsage::of_image(&image, AssetType::Armour, false);
+        assert_eq!(usage.colourables(), [Hair, Cloth, Colourable::Outline]);
+        assert_eq!(usage.pixel_count(Cloth), 2);
+        assert_eq!(
+            usage.shades(Cloth).collect::<Vec<_>>(),
+            [(Shade::Lighter, 1), (Shade::Neutral, 1)]
+        );
+        assert!(!usage.uses(Leather));
+
+        let usage = ColourUsage::of_image(&image, AssetType::Face, false);
+        assert_eq!(usage.colourables(), [EyeAndBeard, Colourable::Outline]);
+        assert_eq!(
+            ColourUsage::of_image(&RgbaImage::new(1, 1), AssetType::Face, false),
+            ColourUsage::default()
+        );
+    }
+
+    #[test]
+    fn test_recolor_keeps_alpha() {
+        let mut image = RgbaImage::new(3, 1);
+        image.put_pixel(0, 0, image::Rgba([150, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([150, 0, 0, 128]));
+        image.put_pixel(2, 0, image::Rgba([160, 0, 0, 128]));
+
+        let mut char_colours = HashMap::new();
+        char_colours.insert(
+            Cloth,
+            CharacterPartColours {
+                lighter: Rgba::new(50, 60, 70, 255),
+                neutral: Rgba::new(80, 90, 100, 128),
+                ..Default::default()
+            },
+        );
+
+        recolour(
+            &mut image,
+            AssetType::Armour,
+            false,
+            &char_colours,
+            &Outlines::default(),
+        );
+
+        assert_eq!(image.get_pixel(0, 0).0, [50, 60, 70, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [50, 60, 70, 128]);
+        // Both alphas are applied: 128 * 128 / 255.
+        assert_eq!(image.get_pixel(2, 0).0, [80, 90, 100, 64]);
+    }
 }
@@ -0,0 +1,142 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::asset::{Asset, AssetType};
+use crate::character::{Character, Colourable, Shade};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use strum::IntoEnumIterator as _;
+
+/// Caches something made from each asset's recoloured image, such as a texture, for as long as
+/// the colours the asset uses stay the same.
+///
+/// Entries are keyed by the asset's ID and a hash of only the colours of the colourables its
+/// image uses, so changing the hair colour leaves art without hair keys cached.
+#[derive(Debug)]
+pub struct RecolourCache<T> {
+    entries: HashMap<String, Entry<T>>,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    /// The colourables the asset's image uses, see `ColourUsage`.
+    colourables: Vec<Colourable>,
+    colours_hash: u64,
+    value: T,
+}
+
+impl<T> Default for RecolourCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<T> RecolourCache<T> {
+    /// Returns the value cached for an asset, unless the character's colours it uses have changed
+    /// since.
+    pub fn get(&self, asset: &Asset, character: &Character) -> Option<&T> {
+        let entry = self.entries.get(&asset.id)?;
+        (entry.colours_hash == colours_hash(&entry.colourables, asset.asset_type, character))
+            .then_some(&entry.value)
+    }
+
+    /// Caches a value made from an asset's image recoloured with the character's colours.
+    ///
+    /// The colourables the image uses are worked out the first time the asset is cached, so the
+    /// asset must be removed if its image changes.
+    pub fn insert(&mut self, asset: &Asset, character: &Character, value: T) {
+        let colourables = match self.entries.remove(&asset.id) {
+            Some(entry) => entry.colourables,
+            None => asset
+                .colour_usage()
+                .map(|usage| usage.colourables())
+                .unwrap_or_default(),
+        };
+        let colours_hash = colours_hash(&colourables, asset.asset_type, character);
+        self.entries.insert(
+            asset.id.clone(),
+            Entry {
+                colourables,
+                colours_hash,
+                value,
+            },
+        );
+    }
+
+    /// Removes an asset's cached value, e.g. because its image has changed.
+    pub fn remove(&mut self, asset_id: &str) {
+        self.entries.remove(asset_id);
+    }
+}
+
+/// Hashes the colours that art of the given type using the given colourables is recoloured with.
+fn colours_hash(colourables: &[Colourable], asset_type: AssetType, character: &Character) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for &colourable in colourables {
+        if colourable == Colourable::Outline {
+            character
+                .outline_colours
+                .get_outline_colour(asset_type)
+                .hash(&mut hasher);
+        } else if let Some(colours) = character.character_colours.get(&colourable) {
+            for shade in Shade::iter() {
+                colours.shade(shade).hash(&mut hasher);
+            }
+        } else {
+            // Keys of a colourable without colours are left as they are.
+            colourable.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Rgba;
+    use image::RgbaImage;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_recolour_cache_invalidates_used_colours_only() {
+        // Outline and hair keys.
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, image::Rgba([0, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([20, 0, 0, 255]));
+        let mut asset = Asset::new("Test".to_owned(), PathBuf::new(), None, AssetType::Hair);
+        asset.image_data = Some(Arc::new(image));
+
+        let mut character = Character::default();
+        let mut cache = RecolourCache::default();
+        assert_eq!(cache.get(&asset, &character), None);
+        cache.insert(&asset, &character, 1);
+        assert_eq!(cache.get(&asset, &character), Some(&1));
+
+        // Cloth isn't used by the art.
+        character
+            .character_colours
+            .entry(Colourable::Cloth)
+            .or_default()
+            .set(Rgba::new(1, 2, 3, 255), false);
+        assert_eq!(cache.get(&asset, &character), Some(&1));
+
+        character
+            .character_colours
+            .entry(Colourable::Hair)
+            .or_default()
+            .set(Rgba::new(1, 2, 3, 255), false);
+        assert_eq!(cache.get(&asset, &character), None);
+        cache.insert(&asset, &character, 2);
+        assert_eq!(cache.get(&asset, &character), Some(&2));
+
+        character
+            .outline_colours
+            .set_outline_colour(AssetType::Hair, &Rgba::new(4, 5, 6, 255));
+        assert_eq!(cache.get(&asset, &character), None);
+
+        cache.remove(&asset.id);
+        character = Character::default();
+        assert_eq!(cache.get(&asset, &character), None);
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright (C) 2025 aidan-es. Licensed under the GNU AGPLv3.
+use crate::asset::category::{AssetCategory, RecolourScheme};
+use crate::character::{Colourable, Shade};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{LazyLock, RwLock};
+
+/// A pixel's red value, and green value in extended art, is divided by this to give its key.
+pub const KEY_STEP: u8 = 10;
+/// The number of keys a channel can give, from 0 to 25.
+pub const KEY_COUNT: usize = 26;
+
+/// The schemes FECC ships with, which are built in so art can be recoloured before any are loaded.
+const SHIPPED_SCHEMES: &str = include_str!("../../../assets/csv/recolour_schemes.csv");
+
+/// The comments around the key tables in ART.md, which are replaced by `replace_key_tables`.
+const KEY_TABLES_START: &str = "<!-- Generated from assets/csv/recolour_schemes.csv by `fecc_cli keys`, don't edit by hand. -->";
+const KEY_TABLES_END: &str = "<!-- End of the generated key tables. -->";
+
+/// The key of a pixel, which picks the slot it is recoloured with.
+///
+/// Art flagged as extended, see `AssetMetadata::extended_keys`, uses its green channel as a second
+/// key. The green key of other art is always 0, so its green values don't matter.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, serde::Serialize)]
+pub struct Key {
+    pub red: u8,
+    pub green: u8,
+}
+
+/// Keys are ordered by their green key first, so the standard keys come before the extended ones.
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.green, self.red).cmp(&(other.green, other.red))
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Key {
+    /// Returns the key of a pixel with the given red and green values.
+    pub const fn of_pixel(red: u8, green: u8, extended: bool) -> Self {
+        Self {
+            red: red / KEY_STEP,
+            green: if extended { green / KEY_STEP } else { 0 },
+        }
+    }
+}
+
+/// A key of a recolour scheme, and the shade of a colourable it is recoloured with.
+///
+/// The schemes are defined by rows of `recolour_schemes.csv`, one for each key.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub struct KeySlot {
+    pub scheme: RecolourScheme,
+    /// The red channel key.
+    pub key: u8,
+    /// The green channel key, which is only read from extended art. Slots with a green key other
+    /// than 0 are extended slots.
+    #[serde(default)]
+    pub green_key: u8,
+    pub colourable: Colourable,
+    pub shade: Shade,
+}
+
+/// The registered slots, which start as the built-in ones.
+static SLOTS: LazyLock<RwLock<Vec<KeySlot>>> = LazyLock::new(|| RwLock::new(KeySlot::built_in()));
+
+impl KeySlot {
+    /// The slots FECC ships with, as defined by `assets/csv/recolour_schemes.csv`.
+    pub fn built_in() -> Vec<Self> {
+        Self::parse_csv(SHIPPED_SCHEMES).unwrap_or_else(|e| {
+            log::error!("The built-in recolour schemes are invalid: {e}");
+            Vec::new()
+        })
+    }
+
+    /// Replaces the slots of every scheme that the given slots are for, adding any new schemes.
+    pub fn register(slots: Vec<Self>) {
+        let mut registered = SLOTS
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        registered.retain(|existing| !slots.iter().any(|slot| slot.scheme == existing.scheme));
+        registered.extend(slots);
+    }
+
+    /// Returns every registered slot, in the order they were defined.
+    pub fn all() -> Vec<Self> {
+        SLOTS
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Parses slots from CSV text with a header row, `scheme,key,green_key,colourable,shade`.
+    ///
+    /// The `green_key` column can be left out if every slot is a standard one. Fails if a key is
+    /// out of range, or a scheme uses a key or a shade twice.
+    pub fn parse_csv(text: &str) -> Result<Vec<Self>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(text.as_bytes());
+
+        let slots: Vec<Self> = reader
+            .deserialize()
+            .enumerate()
+            .map(|(i, record)| {
+                record.map_err(|e| format!("Invalid recolour key on row {}: {e}", i + 1))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut keys = HashSet::new();
+        let mut shades = HashSet::new();
+        for (i, slot) in slots.iter().enumerate() {
+            let problem = if usize::from(slot.key.max(slot.green_key)) >= KEY_COUNT {
+                format!("keys go up to {}", KEY_COUNT - 1)
+            } else if slot.colourable == Colourable::Outline && slot.shade != Shade::Neutral {
+                "the outline only has a neutral shade".to_owned()
+            } else if !keys.insert((slot.scheme, slot.key())) {
+                format!(
+                    "key {} with green key {} is used twice in the {} scheme",
+                    slot.key, slot.green_key, slot.scheme
+                )
+            } else if !shades.insert((slot.scheme, slot.colourable, slot.shade)) {
+                format!(
+                    "{} ({}) has two keys in the {} scheme",
+                    slot.colourable,
+                    slot.shade.label(),
+                    slot.scheme
+                )
+            } else {
+                continue;
+            };
+            return Err(format!("Invalid recolour key on row {}: {problem}", i + 1));
+        }
+        Ok(slots)
+    }
+
+    pub const fn key(&self) -> Key {
+        Key {
+            red: self.key,
+            green: self.green_key,
+        }
+    }
+}
+
+/// The shades that each key of a recolour scheme is recoloured with, in standard or extended art.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KeyLayout {
+    extended: bool,
+    slots: BTreeMap<Key, (Colourable, Shade)>,
+}
+
+impl KeyLayout {
+    fn new(scheme: RecolourScheme, extended: bool, slots: &[KeySlot]) -> Self {
+        let slots = slots
+            .iter()
+            .filter(|slot| slot.scheme == scheme && (extended || slot.green_key == 0))
+            .map(|slot| (slot.key(), (slot.colourable, slot.shade)))
+            .collect();
+        Self { extended, slots }
+    }
+
+    /// Returns the key of a pixel with the given red and green values in art of this layout.
+    pub const fn key_of_pixel(&self, red: u8, green: u8) -> Key {
+        Key::of_pixel(red, green, self.extended)
+    }
+
+    /// Returns the colourable and shade a key is recoloured with, or `None` if it isn't a slot.
+    pub fn slot(&self, key: Key) -> Option<(Colourable, Shade)> {
+        self.slots.get(&key).copied()
+    }
+
+    /// Returns the key of a shade of a colourable, or `None` if the layout has no slot for it.
+    pub fn key_of(&self, colourable: Colourable, shade: Shade) -> Option<Key> {
+        self.slots
+            .iter()
+            .find(|(_, slot)| **slot == (colourable, shade))
+            .map(|(key, _)| *key)
+    }
+
+    /// Returns every key with a slot, and the colourable and shade it is recoloured with, in key
+    /// order.
+    pub fn slots(&self) -> impl Iterator<Item = (Key, Colourable, Shade)> + '_ {
+        self.slots
+            .iter()
+            .map(|(key, (colourable, shade))| (*key, *colourable, *shade))
+    }
+
+    /// Returns the colourables that art is coloured with, besides its outline, in key order.
+    pub fn colourables(&self) -> Vec<Colourable> {
+        let mut colourables = Vec::new();
+        for (_, colourable, _) in self.slots() {
+            if colourable != Colourable::Outline && !colourables.contains(&colourable) {
+                colourables.push(colourable);
+            }
+        }
+        colourables
+    }
+
+    /// Returns whether no key is recoloured, e.g. because the scheme has no registered slots.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+impl RecolourScheme {
+    /// Returns the registered layout of this scheme's keys, including the extended slots if the
+    /// art is extended.
+    pub fn layout(self, extended: bool) -> KeyLayout {
+        let slots = SLOTS
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        KeyLayout::new(self, extended, &slots)
+    }
+}
+
+/// Writes a table of keys for each scheme of the given slots, in the order they're first
+/// defined, headed by the types of art that use it.
+pub fn key_tables_markdown(slots: &[KeySlot], categories: &[AssetCategory]) -> String {
+    let mut schemes: Vec<RecolourScheme> = Vec::new();
+    for slot in slots {
+        if !schemes.contains(&slot.scheme) {
+            schemes.push(slot.scheme);
+        }
+    }
+
+    let mut tables = Vec::new();
+    for scheme in schemes {
+        let names: Vec<&str> = categories
+            .iter()
+            .filter(|category| {
+                category.recolour_scheme == scheme && category.asset_type.companion_of().is_none()
+            })
+            .map(|category| category.asset_type.name())
+            .collect();
+        let art = match names.split_last() {
+            Some((last, [])) => format!("{last} files"),
+            Some((last, rest)) => format!("{} and {last} files", rest.join(", ")),
+            None => format!("the {scheme} scheme"),
+        };
+
+        let mut pages: BTreeMap<u8, String> = BTreeMap::new();
+        for (key, colourable, shade) in KeyLayout::new(scheme, true, slots).slots() {
+            let table = pages.entry(key.green).or_insert_with(|| match key.green {
+                0 => format!("### For {art}:\n"),
+                green => format!("### For {art} with extended keys, green key {green}:\n"),
+            });
+            match colourable {
+                Colourable::Outline => table.push_str(&format!("\n{} - Outline Colour\n", key.red)),
+                _ => table.push_str(&format!(
+                    "\n{} - {colourable} Colour ({})\n",
+                    key.red,
+                    shade.label()
+                )),
+            }
+        }
+        tables.extend(pages.into_values());
+    }
+    tables.join("\n")
+}
+
+/// Replaces the generated key tables of a guide, such as ART.md, with the given tables.
+pub fn replace_key_tables(guide: &str, tables: &str) -> Result<String, String> {
+    let missing =
+        || format!("The guide has no '{KEY_TABLES_START}' ... '{KEY_TABLES_END}' section");
+    let (before, rest) = guide.split_once(KEY_TABLES_START).ok_or_else(missing)?;
+    let (_, after) = rest.split_once(KEY_TABLES_END).ok_or_else(missing)?;
+    Ok(format!(
+        "{before}{KEY_TABLES_START}\n\n{tables}\n{KEY_TABLES_END}{after}"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn key(red: u8, green: u8) -> Key {
+        Key { red, green }
+    }
+
+    #[test]
+    fn test_built_in_layouts() {
+        let slots = KeySlot::built_in();
+        let layout = KeyLayout::new(RecolourScheme::Face, false, &slots);
+        assert_eq!(
+            layout.slot(key(0, 0)),
+            Some((Colourable::Outline, Shade::Neutral))
+        );
+        assert_eq!(
+            layout.slot(key(8, 0)),
+            Some((Colourable::Skin, Shade::DarkerDarkerDarker))
+        );
+        assert_eq!(layout.slot(key(12, 0)), None);
+        assert_eq!(
+            layout.colourables(),
+            [
+                Colourable::EyeAndBeard,
+                Colourable::Skin,
+                Colourable::Accessory
+            ]
+        );
+        assert!(KeyLayout::new(RecolourScheme::Custom("TestNone"), false, &slots).is_empty());
+    }
+
+    #[test]
+    fn test_extended_layouts() {
+        let slots = KeySlot::built_in();
+        let standard = KeyLayout::new(RecolourScheme::Armour, false, &slots);
+        let extended = KeyLayout::new(RecolourScheme::Armour, true, &slots);
+
+        // Standard art ignores its green values, so they can be anything.
+        assert_eq!(standard.key_of_pixel(150, 255), key(15, 0));
+        assert_eq!(extended.key_of_pixel(150, 10), key(15, 1));
+        assert_eq!(standard.key_of(Colourable::Gem, Shade::Neutral), None);
+        assert_eq!(
+            extended.key_of(Colourable::Gem, Shade::Neutral),
+            Some(key(7, 1))
+        );
+        // The standard slots are the same in extended art.
+        assert_eq!(extended.slot(key(16, 0)), standard.slot(key(16, 0)));
+        assert_eq!(
+            &extended.colourables()[6..],
+            [
+                Colourable::Cloth2,
+                Colourable::Metal2,
+                Colourable::Gem,
+                Colourable::Cape
+            ]
+        );
+    }
+
+    #[test]
+    fn test_register_custom_scheme() {
+        let csv = "scheme,key,colourable,shade\n\
+                   TestCloak, 0, Outline, Neutral\n\
+                   TestCloak, 3, Cloth, Lighter\n\
+                   TestCloak, 4, Cloth, Neutral\n";
+        let scheme: RecolourScheme = "TestCloak".parse().expect("Failed to parse scheme");
+        assert!(scheme.layout(false).is_empty());
+
+        KeySlot::register(KeySlot::parse_csv(csv).expect("Failed to parse schemes"));
+        let layout = scheme.layout(false);
+        assert_eq!(
+            layout.key_of(Colourable::Cloth, Shade::Neutral),
+            Some(key(4, 0))
+        );
+        assert_eq!(layout.slot(key(1, 0)), None);
+        assert_eq!(layout.colourables(), [Colourable::Cloth]);
+        assert_eq!(
+            RecolourScheme::Armour
+                .layout(false)
+                .key_of(Colourable::Leather, Shade::Darker),
+            Some(key(20, 0))
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_invalid() {
+        for (csv, problem) in [
+            ("Face,26,Skin,Neutral", "keys go up to 25"),
+            (
+                "Face,0,Outline,Darker",
+                "the outline only has a neutral shade",
+            ),
+            (
+                "Face,1,Skin,Neutral\nFace,1,Hair,Neutral",
+                "key 1 with green key 0 is used twice",
+            ),
+            (
+                "Face,1,Skin,Neutral\nFace,2,Skin,Neutral",
+                "Skin (base) has two keys",
+            ),
+            ("Face,1,Skin,Pale", "unknown variant"),
+        ] {
+            let error =
+                KeySlot::parse_csv(&format!("scheme,key,colourable,shade\n{csv}")).expect_err(csv);
+            assert!(error.contains(problem), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_art_guide_key_tables_are_current() {
+        let guide = include_str!("../../../art/ART.md");
+        let tables = key_tables_markdown(&KeySlot::built_in(), &AssetCategory::built_in());
+        assert!(
+            replace_key_tables(guide, &tables).as_deref() == Ok(guide),
+            "ART.md's key tables are out of date, run `fecc_cli keys` to regenerate them"
+        );
+    }
+}
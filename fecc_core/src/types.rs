@@ -36,6 +36,35 @@ impl Rgba {
             Err(format!("Invalid hex length: {}", len))
         }
     }
+
+    /// Returns the colour as a GBA BGR555 value, with 5 bits for each of red, green and blue, red
+    /// lowest. Alpha is dropped.
+    pub const fn to_bgr555(self) -> u16 {
+        const fn to_5_bits(channel: u8) -> u16 {
+            (channel as u16 * 31 + 127) / 255
+        }
+        to_5_bits(self.r) | to_5_bits(self.g) << 5 | to_5_bits(self.b) << 10
+    }
+
+    /// Returns the opaque colour of a GBA BGR555 value, ignoring its unused top bit.
+    pub const fn from_bgr555(value: u16) -> Self {
+        const fn from_5_bits(channel: u16) -> u8 {
+            let channel = (channel & 0x1f) as u8;
+            channel << 3 | channel >> 2
+        }
+        Self::new(
+            from_5_bits(value),
+            from_5_bits(value >> 5),
+            from_5_bits(value >> 10),
+            255,
+        )
+    }
+
+    /// Snaps the colour to the nearest one GBA hardware can show, keeping its alpha.
+    pub const fn to_gba(self) -> Self {
+        let Self { r, g, b, .. } = Self::from_bgr555(self.to_bgr555());
+        Self::new(r, g, b, self.a)
+    }
 }
 
 impl From<[u8; 4]> for Rgba {
@@ -95,6 +124,20 @@ mod tests {
         assert!(Rgba::from_hex("").is_err()); // Empty
     }
 
+    #[test]
+    fn test_rgba_bgr555() {
+        assert_eq!(Rgba::WHITE.to_bgr555(), 0x7fff);
+        assert_eq!(Rgba::new(255, 0, 0, 255).to_bgr555(), 0x001f);
+        assert_eq!(Rgba::new(0, 0, 255, 255).to_bgr555(), 0x7c00);
+        assert_eq!(Rgba::from_bgr555(0x7fff), Rgba::WHITE);
+        assert_eq!(Rgba::from_bgr555(0x8000), Rgba::BLACK);
+
+        // 100 is between the GBA levels 99 and 107.
+        let colour = Rgba::new(100, 4, 255, 128);
+        assert_eq!(colour.to_gba(), Rgba::new(99, 0, 255, 128));
+        assert_eq!(colour.to_gba().to_gba(), colour.to_gba());
+    }
+
     #[test]
     fn test_rgba_from_into_array() {
         let arr = [10, 20, 30, 40];
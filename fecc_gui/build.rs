@@ -11,24 +11,36 @@ fn main() {
     let manifest_path = Path::new(&out_dir).join("asset_manifest.json");
 
     let mut files = Vec::new();
+    let mut metadata = Vec::new();
     let art_dir = Path::new("../art");
 
     if art_dir.is_dir() {
         for entry in fs::read_dir(art_dir).expect("Failed to read art directory") {
             let entry = entry.expect("Failed to read directory entry");
             let path = entry.path();
-            if path.is_file()
-                && path.extension().and_then(|s| s.to_str()) == Some("png")
-                && let Some(file_name) = path.file_name().and_then(|s| s.to_str())
-            {
-                files.push(file_name.to_owned());
+            if !path.is_file() {
+                continue;
+            }
+            let Some(file_name) = path.file_name().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match path.extension().and_then(|s| s.to_str()) {
+                Some("png") => files.push(file_name.to_owned()),
+                // Sidecars describe either the whole folder or the art file of the same name.
+                Some("json")
+                    if file_name == "metadata.json" || path.with_extension("png").is_file() =>
+                {
+                    metadata.push(file_name.to_owned());
+                }
+                _ => {}
             }
         }
     }
 
     files.sort();
+    metadata.sort();
 
-    let json = serde_json::json!({ "files": files });
+    let json = serde_json::json!({ "files": files, "metadata": metadata });
     let mut file = File::create(&manifest_path).expect("Failed to create manifest file");
     file.write_all(json.to_string().as_bytes())
         .expect("Failed to write to manifest file");
@@ -3,14 +3,24 @@ use std::cmp::PartialEq;
 mod canvas_interaction;
 mod eframe_ui;
 
+use fecc_core::asset::category::Canvas;
 use fecc_core::asset::{Asset, AssetType};
-use fecc_core::character::Colourable::{
-    Accessory, Cloth, EyeAndBeard, Hair, Leather, Metal, Skin, Trim,
+use fecc_core::bundle::FeccBundle;
+use fecc_core::character::history::{EditHistory, MergeKey};
+use fecc_core::character::{
+    Character, CharacterPart, CharacterPartColours, ColourPalette, Colourable, Layer, MissingPart,
 };
-use fecc_core::character::{Character, CharacterPart, ColourPalette, Colourable};
-use fecc_core::export::ExportSize;
+use fecc_core::export::indexed::IndexedImage;
+use fecc_core::export::layered::LayeredFormat;
+use fecc_core::export::{ExportSize, MugSheet, credits_text};
+use fecc_core::fecc_file::{CoordinateSpace, FeccFile};
+use fecc_core::file_io::asset_root::AssetRoot;
 use fecc_core::file_io::{load_asset_libraries, load_colours_from_csv, load_image_bytes};
-use fecc_core::types::Point;
+use fecc_core::gba_palette::{BANK_SIZE, gba_palette_bytes, read_gba_palette};
+use fecc_core::import::extract_colour_scheme;
+use fecc_core::recolour::ColourUsage;
+use fecc_core::recolour::cache::RecolourCache;
+use fecc_core::types::{Point, Rgba};
 
 use egui::ahash::{HashMap, HashSet};
 use egui::{Align, Color32, ColorImage, Context, Pos2, Rect, Shape, Ui, Vec2, pos2, vec2};
@@ -19,16 +29,29 @@ use futures_channel::mpsc;
 use futures_util::future::join_all;
 use image::RgbaImage;
 use indexmap::IndexMap;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use strum::IntoEnumIterator as _;
 use strum_macros::EnumIter;
 
+#[cfg(not(target_arch = "wasm32"))]
+use fecc_core::file_io::watcher::{AssetWatcher, merge_reloaded_libraries};
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::runtime::Runtime;
 
-type ImageReceiver = Option<mpsc::UnboundedReceiver<(String, Result<Arc<RgbaImage>, String>)>>;
-pub(crate) type ImageSender = mpsc::UnboundedSender<(String, Result<Arc<RgbaImage>, String>)>;
+/// How often the asset roots are checked for changed art.
+#[cfg(not(target_arch = "wasm32"))]
+const ASSET_WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+type AssetLibraries = std::collections::HashMap<AssetType, IndexMap<String, Asset>>;
+type ColourScheme = std::collections::HashMap<Colourable, CharacterPartColours>;
+type GbaPalette = (Colourable, Result<Vec<Rgba>, String>);
+
+/// An asset's id, and the asset with its image loaded, see `Asset::load_image`.
+type LoadedImage = (String, Result<Asset, String>);
+type ImageReceiver = Option<mpsc::UnboundedReceiver<LoadedImage>>;
+pub(crate) type ImageSender = mpsc::UnboundedSender<LoadedImage>;
 
 #[derive(Debug, PartialEq, Clone, Copy, EnumIter, Eq, Hash)]
 pub enum Corner {
@@ -38,12 +61,6 @@ pub enum Corner {
     BottomLeft,
 }
 
-#[derive(PartialEq)]
-enum CanvasType {
-    Portrait,
-    Token,
-}
-
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Interaction {
     Move,
@@ -74,14 +91,20 @@ pub struct FitResult {
 pub struct FECharacterCreator {
     character: Character,
     #[serde(skip)]
-    asset_libraries: std::collections::HashMap<AssetType, IndexMap<String, Asset>>,
+    history: EditHistory,
+    #[serde(skip)]
+    asset_libraries: AssetLibraries,
     #[serde(skip)]
-    texture_cache: HashMap<String, egui::TextureHandle>,
+    texture_cache: RecolourCache<egui::TextureHandle>,
 
     active_tab: AssetType,
     new_active_tab: bool,
     randomise_used: bool,
     randomise_colours_too: bool,
+    /// Whether choosing a part adds it as a new layer rather than replacing one.
+    add_as_new_layer: bool,
+    /// Comma-separated tags that randomised parts must have, see `AssetMetadata::parse_tags`.
+    randomise_tags: String,
 
     #[serde(skip)]
     search_queries: HashMap<AssetType, String>,
@@ -90,7 +113,19 @@ pub struct FECharacterCreator {
     portrait_rect: Rect,
     token_rect: Rect,
 
-    export_size_selection: ExportSize,
+    #[serde(alias = "export_size_selection")]
+    export_size: ExportSize,
+    mug_sheet: MugSheet,
+    /// The format of exports with each part on its own layer.
+    layered_format: LayeredFormat,
+    /// Whether images are exported as indexed PNGs, see `IndexedImage`.
+    indexed_export: bool,
+    /// Whether indexed exports are quantised to `max_colours`.
+    quantise_colours: bool,
+    max_colours: usize,
+    /// What fitting the portrait into the indexed palette did, from the last check.
+    #[serde(skip)]
+    palette_report: Option<Vec<String>>,
 
     #[serde(skip)]
     colour_palettes: std::collections::HashMap<Colourable, ColourPalette>,
@@ -99,11 +134,7 @@ pub struct FECharacterCreator {
         futures_channel::oneshot::Receiver<std::collections::HashMap<Colourable, ColourPalette>>,
     >,
     #[serde(skip)]
-    asset_libraries_receiver: Option<
-        futures_channel::oneshot::Receiver<
-            std::collections::HashMap<AssetType, IndexMap<String, Asset>>,
-        >,
-    >,
+    asset_libraries_receiver: Option<futures_channel::oneshot::Receiver<AssetLibraries>>,
     #[serde(skip)]
     image_receiver: ImageReceiver,
     #[serde(skip)]
@@ -118,12 +149,16 @@ pub struct FECharacterCreator {
     #[serde(skip)]
     tokio_runtime: Arc<Runtime>,
 
+    /// The index of the layer selected on the portrait canvas.
     #[serde(skip)]
-    selected_part: Option<AssetType>,
+    selected_part: Option<usize>,
     #[serde(skip)]
     pub interaction: Option<Interaction>,
     #[serde(skip)]
     pub content_bounds_cache: HashMap<PathBuf, Rect>,
+    /// The colours each part's art uses, by asset ID, see `ColourUsage`.
+    #[serde(skip)]
+    colour_usage_cache: HashMap<String, ColourUsage>,
 
     assets_panel_expanded: bool,
     colour_panel_expanded: bool,
@@ -136,9 +171,21 @@ pub struct FECharacterCreator {
     is_character_normalised: bool,
 
     #[serde(skip)]
-    loaded_character_receiver: Option<mpsc::UnboundedReceiver<Result<Character, String>>>,
+    loaded_character_receiver: Option<mpsc::UnboundedReceiver<Result<FeccBundle, String>>>,
     #[serde(skip)]
-    loaded_character_sender: mpsc::UnboundedSender<Result<Character, String>>,
+    loaded_character_sender: mpsc::UnboundedSender<Result<FeccBundle, String>>,
+
+    /// Colours taken from a reference image, see `load_colour_reference`.
+    #[serde(skip)]
+    colour_reference_receiver: Option<mpsc::UnboundedReceiver<Result<ColourScheme, String>>>,
+    #[serde(skip)]
+    colour_reference_sender: mpsc::UnboundedSender<Result<ColourScheme, String>>,
+
+    /// Palette colours read from a GBA palette file, see `load_gba_palette`.
+    #[serde(skip)]
+    gba_palette_receiver: Option<mpsc::UnboundedReceiver<GbaPalette>>,
+    #[serde(skip)]
+    gba_palette_sender: mpsc::UnboundedSender<GbaPalette>,
 
     #[cfg(target_arch = "wasm32")]
     asset_upload_panel_expanded: bool,
@@ -158,8 +205,29 @@ pub struct FECharacterCreator {
     #[cfg(target_arch = "wasm32")]
     new_user_asset_sender: mpsc::UnboundedSender<Result<Asset, String>>,
 
+    /// Watches the asset roots once the first load of the asset libraries has finished, see
+    /// `watch_asset_roots`.
+    #[serde(skip)]
+    #[cfg(not(target_arch = "wasm32"))]
+    asset_watcher: Option<AssetWatcher>,
+
+    /// Asset libraries reloaded after the art in the asset roots changed, see `watch_asset_roots`.
+    #[serde(skip)]
+    #[cfg(not(target_arch = "wasm32"))]
+    reloaded_libraries_receiver: Option<mpsc::UnboundedReceiver<AssetLibraries>>,
+
     #[serde(skip)]
     about_window_open: bool,
+
+    #[serde(skip)]
+    pending_substitutions: Vec<PendingSubstitution>,
+}
+
+/// A part missing from a loaded character, and the asset chosen to replace it.
+struct PendingSubstitution {
+    missing: MissingPart,
+    /// The replacement asset, or `None` to leave the part out.
+    choice: Option<Asset>,
 }
 
 impl Default for FECharacterCreator {
@@ -167,28 +235,25 @@ impl Default for FECharacterCreator {
         #[cfg(target_arch = "wasm32")]
         let (tx, rx) = mpsc::unbounded();
         let (loaded_character_sender, loaded_character_receiver) = mpsc::unbounded();
+        let (colour_reference_sender, colour_reference_receiver) = mpsc::unbounded();
+        let (gba_palette_sender, gba_palette_receiver) = mpsc::unbounded();
 
         Self {
             character: Default::default(),
+            history: Default::default(),
             asset_libraries: Default::default(),
             texture_cache: Default::default(),
             active_tab: AssetType::Token,
             new_active_tab: true,
             randomise_used: false,
             randomise_colours_too: false,
+            add_as_new_layer: false,
+            randomise_tags: String::new(),
             search_queries: Default::default(),
-            colour_picker_open_state: [
-                (Hair, false),
-                (EyeAndBeard, false),
-                (Skin, false),
-                (Metal, false),
-                (Trim, false),
-                (Cloth, false),
-                (Leather, false),
-                (Accessory, false),
-            ]
-            .into_iter()
-            .collect(),
+            colour_picker_open_state: Colourable::iter()
+                .filter(|&colourable| colourable != Colourable::Outline)
+                .map(|colourable| (colourable, false))
+                .collect(),
             outline_picker_open_state: [
                 (AssetType::Armour, false),
                 (AssetType::Face, false),
@@ -200,7 +265,13 @@ impl Default for FECharacterCreator {
             .collect(),
             portrait_rect: Rect::NOTHING,
             token_rect: Rect::NOTHING,
-            export_size_selection: ExportSize::Original,
+            export_size: ExportSize::default(),
+            mug_sheet: MugSheet::default(),
+            layered_format: LayeredFormat::default(),
+            indexed_export: false,
+            quantise_colours: false,
+            max_colours: MugSheet::MAX_COLOURS,
+            palette_report: None,
             colour_palettes: Default::default(),
             palettes_receiver: None,
             asset_libraries_receiver: None,
@@ -212,6 +283,7 @@ impl Default for FECharacterCreator {
             selected_part: None,
             interaction: None,
             content_bounds_cache: HashMap::default(),
+            colour_usage_cache: HashMap::default(),
             assets_panel_expanded: true,
             colour_panel_expanded: true,
             export_panel_expanded: false,
@@ -220,6 +292,10 @@ impl Default for FECharacterCreator {
             is_character_normalised: false,
             loaded_character_receiver: Some(loaded_character_receiver),
             loaded_character_sender,
+            colour_reference_receiver: Some(colour_reference_receiver),
+            colour_reference_sender,
+            gba_palette_receiver: Some(gba_palette_receiver),
+            gba_palette_sender,
             #[cfg(target_arch = "wasm32")]
             asset_upload_panel_expanded: false,
 
@@ -235,8 +311,15 @@ impl Default for FECharacterCreator {
             #[cfg(target_arch = "wasm32")]
             new_user_asset_sender: tx,
 
+            #[cfg(not(target_arch = "wasm32"))]
+            asset_watcher: None,
+
+            #[cfg(not(target_arch = "wasm32"))]
+            reloaded_libraries_receiver: None,
+
             toasts: Toasts::new().with_anchor(Anchor::BottomRight),
             about_window_open: false,
+            pending_substitutions: Vec::new(),
         }
     }
 }
@@ -247,6 +330,10 @@ impl FECharacterCreator {
             if let Some(mut saved_app) = eframe::get_value::<Self>(storage, eframe::APP_KEY) {
                 saved_app.is_character_normalised = true;
                 saved_app.character_needs_asset_refresh = true;
+                // Colourables added since the app was last run are given unsnapped default colours.
+                if saved_app.character.gba_colours {
+                    saved_app.character.set_gba_colours(true);
+                }
                 saved_app
             } else {
                 Default::default()
@@ -293,8 +380,14 @@ impl FECharacterCreator {
         };
 
         let (assets_tx, assets_rx) = futures_channel::oneshot::channel();
+        let asset_roots = AssetRoot::configured();
+        // Files are stamped before the first load, so changes made during it are picked up.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            fe_character_creator.asset_watcher = Some(AssetWatcher::new(asset_roots.clone()));
+        }
         let assets_task = async move {
-            match load_asset_libraries().await {
+            match load_asset_libraries(&asset_roots).await {
                 Ok(libs) => {
                     if assets_tx.send(libs).is_err() {
                         log::warn!("Assets receiver dropped before libraries were sent");
@@ -320,6 +413,8 @@ impl FECharacterCreator {
 
         let (image_sender, image_receiver) = mpsc::unbounded();
         let (loaded_character_sender, loaded_character_receiver) = mpsc::unbounded();
+        let (colour_reference_sender, colour_reference_receiver) = mpsc::unbounded();
+        let (gba_palette_sender, gba_palette_receiver) = mpsc::unbounded();
 
         fe_character_creator.palettes_receiver = Some(palettes_rx);
         fe_character_creator.asset_libraries_receiver = Some(assets_rx);
@@ -327,29 +422,106 @@ impl FECharacterCreator {
         fe_character_creator.image_sender = image_sender;
         fe_character_creator.loaded_character_sender = loaded_character_sender;
         fe_character_creator.loaded_character_receiver = Some(loaded_character_receiver);
+        fe_character_creator.colour_reference_sender = colour_reference_sender;
+        fe_character_creator.colour_reference_receiver = Some(colour_reference_receiver);
+        fe_character_creator.gba_palette_sender = gba_palette_sender;
+        fe_character_creator.gba_palette_receiver = Some(gba_palette_receiver);
 
         fe_character_creator
     }
 
+    /// Starts reloading the asset libraries on a background thread whenever the art in the asset
+    /// roots changes, sending them to `update_reloaded_asset_libraries`.
+    ///
+    /// Called once the first load has finished, so that only the files changed since then are read.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn watch_asset_roots(&mut self, ctx: &Context) {
+        let Some(mut watcher) = self.asset_watcher.take() else {
+            return;
+        };
+        watcher.set_loaded(&self.asset_libraries);
+        let (sender, receiver) = mpsc::unbounded();
+        self.reloaded_libraries_receiver = Some(receiver);
+
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(ASSET_WATCH_INTERVAL);
+                let changed = watcher.poll();
+                if changed.is_empty() {
+                    continue;
+                }
+
+                if sender.unbounded_send(watcher.reload(&changed)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Merges in reloaded asset libraries, so that art edited while the app runs is redrawn.
+    ///
+    /// The cached textures and bounds of changed and removed assets are dropped, and layers using a
+    /// changed asset are updated to it, so they are loaded again when next drawn. Layers using a
+    /// removed asset keep their art until replaced.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn update_reloaded_asset_libraries(&mut self) {
+        let Some(rx) = self.reloaded_libraries_receiver.as_mut() else {
+            return;
+        };
+        let mut reloaded = None;
+        while let Ok(Some(libs)) = rx.try_next() {
+            reloaded = Some(libs);
+        }
+        let Some(reloaded) = reloaded else {
+            return;
+        };
+
+        let changes = merge_reloaded_libraries(&mut self.asset_libraries, reloaded);
+        if changes.is_empty() {
+            return;
+        }
+
+        for asset in changes.changed.iter().chain(&changes.removed) {
+            self.texture_cache.remove(&asset.id);
+            self.images_in_flight.remove(&asset.id);
+            self.content_bounds_cache.remove(&asset.path);
+            self.colour_usage_cache.remove(&asset.id);
+        }
+        for layer in &mut self.character.layers {
+            if let Some(asset) = changes
+                .changed
+                .iter()
+                .find(|asset| asset.id == layer.part.asset.id)
+            {
+                layer.part.asset = asset.clone();
+            }
+        }
+        self.toasts.info(format!(
+            "Reloaded art: {} added, {} changed, {} removed.",
+            changes.added.len(),
+            changes.changed.len(),
+            changes.removed.len()
+        ));
+    }
+
     fn get_or_load_texture(&mut self, ctx: &Context, asset: &Asset) -> Option<egui::TextureHandle> {
-        if self.texture_cache.contains_key(&asset.id) {
-            return self.texture_cache.get(&asset.id).cloned();
+        if let Some(texture) = self.texture_cache.get(asset, &self.character) {
+            return Some(texture.clone());
         }
 
         if asset.image_data.is_none() {
             if !self.images_in_flight.contains(&asset.id) {
                 self.images_in_flight.insert(asset.id.clone());
                 let sender = self.image_sender.clone();
-                let path_buf = asset.path.clone();
+                let mut loaded_asset = asset.clone();
                 let ctx_clone = ctx.clone();
                 let asset_id = asset.id.clone();
 
                 let task = async move {
-                    let result = match load_image_bytes(&path_buf).await {
-                        Ok(bytes) => match image::load_from_memory(&bytes) {
-                            Ok(img) => Ok(Arc::new(img.to_rgba8())),
-                            Err(e) => Err(e.to_string()),
-                        },
+                    let result = match load_image_bytes(&loaded_asset.path).await {
+                        Ok(bytes) => loaded_asset.load_image(&bytes).map(|()| loaded_asset),
                         Err(e) => Err(e.to_string()),
                     };
                     sender
@@ -373,20 +545,14 @@ impl FECharacterCreator {
             fecc_core::recolour::recolour(
                 &mut rgba_image,
                 asset.asset_type,
+                asset.metadata.uses_extended_keys(),
                 &self.character.character_colours,
                 &self.character.outline_colours,
             );
 
+            // egui blends premultiplied colours, so semi-transparent pixels are premultiplied here.
             let size = [rgba_image.width() as usize, rgba_image.height() as usize];
-            let pixels: Vec<Color32> = rgba_image
-                .pixels()
-                .map(|p| Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
-                .collect();
-            let colour_image = ColorImage {
-                size,
-                pixels,
-                ..Default::default()
-            };
+            let colour_image = ColorImage::from_rgba_unmultiplied(size, rgba_image.as_raw());
 
             let options = egui::TextureOptions {
                 magnification: egui::TextureFilter::Nearest,
@@ -395,7 +561,8 @@ impl FECharacterCreator {
                 mipmap_mode: Default::default(),
             };
             let texture = ctx.load_texture(&asset.id, colour_image, options);
-            self.texture_cache.insert(asset.id.clone(), texture.clone());
+            self.texture_cache
+                .insert(asset, &self.character, texture.clone());
             Some(texture)
         } else {
             None
@@ -412,11 +579,10 @@ impl FECharacterCreator {
         let mut clicked_asset = None;
 
         let available_width = ui.available_width();
-        let is_token = library
+        let base_size = library
             .first()
-            .map(|(_, a)| a.asset_type == AssetType::Token)
-            .unwrap_or(false);
-        let base_size = if is_token { 64.0 } else { 96.0 };
+            .map_or(Canvas::Portrait, |(_, a)| a.asset_type.category().canvas)
+            .art_size() as f32;
 
         let button_size_val = (available_width / base_size).floor().max(1.0) * base_size;
         let button_size = Vec2::splat(button_size_val);
@@ -425,20 +591,16 @@ impl FECharacterCreator {
         let label_height = 20.0;
         let total_item_size = vec2(button_size.x, button_size.y + spacing + label_height);
 
-        for asset in library.iter().filter(|asset| {
-            search_query.is_empty() || asset.1.name.to_lowercase().contains(search_query)
-        }) {
+        for asset in library
+            .iter()
+            .filter(|asset| asset.1.matches_search(search_query))
+        {
             let (rect, response) = ui.allocate_at_least(total_item_size, egui::Sense::click());
 
             if ui.is_rect_visible(rect) {
                 ui.scope_builder(egui::UiBuilder::new().max_rect(rect), |ui| {
                     ui.vertical(|ui| {
-                        let mut selected = false;
-                        if let Some(part) = self.character.get_character_part(&asset.1.asset_type)
-                            && part.asset == *asset.1
-                        {
-                            selected = true;
-                        }
+                        let selected = self.layer_using_asset(asset.1).is_some();
 
                         let main_texture_opt = self.get_or_load_texture(ctx, asset.1);
 
@@ -452,11 +614,8 @@ impl FECharacterCreator {
                             let rect = button_response.rect;
                             let painter = ui.painter_at(rect);
 
-                            if asset.1.asset_type == AssetType::Hair
-                                && let Some(back_part_id) = &asset.1.back_part
-                                && let Some(back_asset) = self.asset_libraries[&AssetType::HairBack]
-                                    .get(back_part_id)
-                                    .cloned()
+                            if let Some(back_asset) =
+                                asset.1.find_companion(&self.asset_libraries).cloned()
                                 && let Some(back_texture) =
                                     self.get_or_load_texture(ctx, &back_asset)
                             {
@@ -487,6 +646,10 @@ impl FECharacterCreator {
                         if selected && (self.randomise_used || self.new_active_tab) {
                             button_response.scroll_to_me(Some(Align::TOP));
                         }
+                        let button_response = match &asset.1.pack {
+                            Some(pack) => button_response.on_hover_text(format!("From {pack}")),
+                            None => button_response,
+                        };
                         if button_response.clicked() {
                             clicked_asset = Some(asset.1.clone());
                         }
@@ -494,12 +657,7 @@ impl FECharacterCreator {
                     });
                 });
             } else {
-                let mut selected = false;
-                if let Some(part) = self.character.get_character_part(&asset.1.asset_type)
-                    && part.asset == *asset.1
-                {
-                    selected = true;
-                }
+                let selected = self.layer_using_asset(asset.1).is_some();
 
                 if selected && (self.randomise_used || self.new_active_tab) {
                     response.scroll_to_me(Some(Align::TOP));
@@ -510,143 +668,275 @@ impl FECharacterCreator {
     }
 
     fn select_asset(&mut self, asset: &Asset, asset_type: AssetType) {
-        if self.is_asset_already_selected(asset) {
-            self.deselect_asset(&asset.clone());
+        if let Some(index) = self.layer_using_asset(asset) {
+            self.character.remove_layer(index);
+            self.selected_part = None;
             return;
         }
-        if asset_type == AssetType::Token {
-            let rect = self.token_rect;
-            let center = rect.center() - rect.min;
-            self.character.set_character_part(
-                &AssetType::Token,
-                CharacterPart {
-                    position: Point::new(center.x, center.y),
-                    scale: (rect.height() / 64.0).floor().max(1.0),
-                    rotation: 0.0,
-                    flipped: false,
-                    asset: asset.clone(),
-                },
-            );
-        } else if asset_type == AssetType::Armour {
-            let rect = self.portrait_rect;
-            let scale = (rect.height() / 96.0).floor().max(1.0);
-            let scaled_asset_height = 96.0 * scale;
 
-            self.character.set_character_part(
-                &AssetType::Armour,
-                CharacterPart {
-                    position: Point::new(
-                        rect.width() / 2.0,
-                        rect.height() - (scaled_asset_height / 2.0),
-                    ),
-                    scale,
-                    rotation: 0.0,
-                    flipped: false,
-                    asset: asset.clone(),
-                },
-            );
+        let canvas_size = self.canvas_size(asset_type.category().canvas);
+        let part = CharacterPart::placed(asset.clone(), canvas_size);
+
+        // Replace the selected layer if it is of this type, otherwise the lowest one of this type.
+        let replaced = if self.add_as_new_layer {
+            None
         } else {
-            let rect = self.portrait_rect;
-            let center = rect.center() - rect.min;
-            self.character.set_character_part(
-                &asset_type,
+            self.selected_part
+                .filter(|&index| {
+                    self.character
+                        .layers
+                        .get(index)
+                        .is_some_and(|layer| layer.asset_type == asset_type)
+                })
+                .or_else(|| self.character.layer_index(&asset_type))
+        };
+
+        let mut index = match replaced {
+            Some(index) => {
+                let index = match self.character.companion_layer_index(index) {
+                    Some(companion) => {
+                        self.character.layers.remove(companion);
+                        if companion < index { index - 1 } else { index }
+                    }
+                    None => index,
+                };
+                self.character.layers[index].part = part.clone();
+                index
+            }
+            None => self
+                .character
+                .add_layer(Layer::new(asset_type, part.clone())),
+        };
+
+        if let Some(back_asset) = asset.find_companion(&self.asset_libraries) {
+            let back_index = self.character.add_layer(Layer::new(
+                back_asset.asset_type,
                 CharacterPart {
-                    position: Point::new(center.x, center.y),
-                    scale: (rect.height() / 96.0).floor().max(1.0),
-                    rotation: 0.0,
-                    flipped: false,
-                    asset: asset.clone(),
+                    asset: back_asset.clone(),
+                    ..part
                 },
-            );
+            ));
+            if back_index <= index {
+                index += 1;
+            }
+        }
 
-            if asset_type == AssetType::Hair
-                && let Some(back_part_id) = &asset.back_part
-                && let Some(hair_part) = self.character.get_character_part(&AssetType::Hair)
-                && let Some(back_asset) =
-                    self.asset_libraries[&AssetType::HairBack].get(back_part_id)
-            {
-                self.character.set_character_part(
-                    &AssetType::HairBack,
-                    CharacterPart {
-                        asset: back_asset.clone(),
-                        flipped: hair_part.flipped,
-                        ..hair_part.clone()
-                    },
-                );
+        self.selected_part = (asset_type.category().canvas == Canvas::Portrait).then_some(index);
+    }
+
+    /// Returns the index of the layer drawing the given asset, if any.
+    fn layer_using_asset(&self, asset: &Asset) -> Option<usize> {
+        self.character
+            .layers
+            .iter()
+            .position(|layer| layer.part.asset == *asset)
+    }
+
+    fn scale_character_parts(&mut self, scale_factor: f32, canvas: Canvas) {
+        let scale_part = |part: &mut CharacterPart| {
+            part.position.x *= scale_factor;
+            part.position.y *= scale_factor;
+            part.scale *= scale_factor;
+        };
+
+        for layer in &mut self.character.layers {
+            if layer.asset_type.category().canvas == canvas {
+                scale_part(&mut layer.part);
             }
         }
+
+        self.history.for_each_part_mut(|asset_type, part| {
+            if asset_type.category().canvas == canvas {
+                scale_part(part);
+            }
+        });
     }
 
-    fn deselect_asset(&mut self, asset: &Asset) {
-        self.character.remove_character_part(&asset.asset_type);
+    /// Returns the size of the canvas in pixels, as positions on it are measured.
+    fn canvas_size(&self, canvas: Canvas) -> Point {
+        let rect = match canvas {
+            Canvas::Portrait => self.portrait_rect,
+            Canvas::Token => self.token_rect,
+        };
+        Point::new(rect.width(), rect.height())
+    }
 
-        if asset.asset_type == AssetType::Hair {
-            self.character.remove_character_part(&AssetType::HairBack);
+    /// Returns the types of the visible parts that use each colourable, or `None` until the art
+    /// of every visible part has been loaded.
+    fn colourable_users(&mut self) -> Option<BTreeMap<Colourable, Vec<AssetType>>> {
+        let mut users: BTreeMap<Colourable, Vec<AssetType>> = BTreeMap::new();
+        for layer in self.character.layers.iter().filter(|layer| layer.visible) {
+            let asset = &layer.part.asset;
+            if !self.colour_usage_cache.contains_key(&asset.id) {
+                let usage = asset.colour_usage()?;
+                self.colour_usage_cache.insert(asset.id.clone(), usage);
+            }
+            let usage = &self.colour_usage_cache[&asset.id];
+            for colourable in usage.colourables() {
+                let types = users.entry(colourable).or_default();
+                if !types.contains(&layer.asset_type) {
+                    types.push(layer.asset_type);
+                }
+            }
         }
+        Some(users)
     }
 
-    fn is_asset_already_selected(&self, asset: &Asset) -> bool {
-        matches!(
-            self.character.get_character_part(&asset.asset_type),
-            Some(selected_asset) if asset == &selected_asset.asset
+    /// Converts an exported image to an indexed one with the chosen number of colours.
+    fn index_image(&self, image: &RgbaImage) -> Result<IndexedImage, String> {
+        IndexedImage::from_image(
+            image,
+            &self.character,
+            self.quantise_colours.then_some(self.max_colours),
         )
     }
 
-    fn scale_character_parts(&mut self, scale_factor: f32, is_token: bool) {
-        let asset_types_to_scale = if is_token {
-            vec![AssetType::Token]
+    /// Saves an exported image as a PNG, indexed if that's chosen, along with the credits for its
+    /// art. Colours merged to fit the palette are reported.
+    fn save_image(&mut self, image: &RgbaImage, filename_stem: String, credits: Option<String>) {
+        let bytes = if self.indexed_export {
+            self.index_image(image).and_then(|indexed| {
+                for merge in &indexed.merges {
+                    self.toasts.warning(merge.to_string());
+                }
+                indexed.to_png()
+            })
         } else {
-            vec![
-                AssetType::Armour,
-                AssetType::Face,
-                AssetType::Hair,
-                AssetType::HairBack,
-                AssetType::Accessory,
-            ]
+            let mut bytes = Vec::new();
+            image
+                .write_to(
+                    &mut std::io::Cursor::new(&mut bytes),
+                    image::ImageFormat::Png,
+                )
+                .map(|()| bytes)
+                .map_err(|e| e.to_string())
         };
 
-        for asset_type in asset_types_to_scale {
-            if let Some(mut part) = self.character.get_character_part(&asset_type) {
-                part.position.x *= scale_factor;
-                part.position.y *= scale_factor;
-                part.scale *= scale_factor;
-                self.character.set_character_part(&asset_type, part);
+        match bytes {
+            Ok(bytes) => Self::save_export(&bytes, filename_stem, ("PNG Image", "png"), credits),
+            Err(e) => {
+                log::error!("Failed to encode image as PNG: {e}");
+                self.toasts.error(e);
             }
         }
     }
 
-    fn get_normalised_character(&self) -> Character {
-        let mut normalised_character = self.character.clone();
+    /// Exports the portrait or token at the chosen size, see `ExportSize::export`.
+    fn export_canvas(&self, canvas: Canvas) -> Option<RgbaImage> {
+        self.export_size
+            .export(&self.character, canvas, self.canvas_size(canvas))
+    }
 
-        let portrait_size = self.portrait_rect.size();
-        if portrait_size.x > 0.0 && portrait_size.y > 0.0 {
-            for asset_type in [
-                AssetType::Armour,
-                AssetType::Face,
-                AssetType::Hair,
-                AssetType::HairBack,
-                AssetType::Accessory,
-            ] {
-                if let Some(mut part) = normalised_character.get_character_part(&asset_type) {
-                    part.position.x /= portrait_size.x;
-                    part.position.y /= portrait_size.y;
-                    part.scale /= portrait_size.y;
-                    normalised_character.set_character_part(&asset_type, part);
+    /// Saves the portrait or token with each part on its own layer, in the chosen layered format.
+    fn export_layered(&mut self, canvas: Canvas) {
+        let suffix = match canvas {
+            Canvas::Portrait => "_portrait",
+            Canvas::Token => "_token",
+        };
+        let Some(layered) =
+            self.export_size
+                .export_layers(&self.character, canvas, self.canvas_size(canvas))
+        else {
+            return;
+        };
+
+        let format = self.layered_format;
+        match layered.encode(format) {
+            Ok(bytes) => Self::save_export(
+                &bytes,
+                self.character.name.clone() + suffix,
+                (&format.to_string(), format.extension()),
+                credits_text(&self.character, &AssetType::on_canvas(canvas)),
+            ),
+            Err(e) => {
+                log::error!("Failed to encode layered image: {e}");
+                self.toasts.error(e);
+            }
+        }
+    }
+
+    /// Saves the palette of the indexed portrait as a raw GBA palette, for ROM tools.
+    fn export_gba_palette(&mut self) {
+        let indexed = self
+            .export_canvas(Canvas::Portrait)
+            .ok_or_else(|| "The portrait hasn't been drawn yet.".to_owned())
+            .and_then(|image| self.index_image(&image));
+
+        match indexed {
+            Ok(indexed) => {
+                if indexed.palette.len() > BANK_SIZE {
+                    self.toasts.warning(format!(
+                        "The portrait uses {} colours, more than fit in a GBA palette bank.",
+                        indexed.palette.len() - 1
+                    ));
                 }
+                Self::save_gba_palette(
+                    &gba_palette_bytes(&indexed.palette),
+                    self.character.name.clone() + "_palette",
+                );
+            }
+            Err(e) => {
+                log::error!("Failed to create GBA palette: {e}");
+                self.toasts.error(e);
             }
         }
+    }
 
-        let token_size = self.token_rect.size();
-        if token_size.x > 0.0
-            && token_size.y > 0.0
-            && let Some(mut part) = normalised_character.get_character_part(&AssetType::Token)
-        {
-            part.position.x /= token_size.x;
-            part.position.y /= token_size.y;
-            part.scale /= token_size.y;
-            normalised_character.set_character_part(&AssetType::Token, part);
+    /// Replaces the palette of a colourable with the colours read from a GBA palette file. The
+    /// first colour of each bank is left out, as the GBA draws it as transparency.
+    fn receive_gba_palette(&mut self, (colourable, result): GbaPalette) {
+        let colours: Vec<Rgba> = match result {
+            Ok(colours) => colours
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| !index.is_multiple_of(BANK_SIZE))
+                .map(|(_, colour)| colour)
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to read GBA palette: {e}");
+                self.toasts.error(e);
+                return;
+            }
+        };
+
+        if colours.is_empty() {
+            self.toasts
+                .error("The palette only has its transparent colour.");
+            return;
+        }
+        self.toasts.success(format!(
+            "Loaded {} colour(s) into the {colourable} palette.",
+            colours.len()
+        ));
+        self.colour_palettes
+            .insert(colourable, ColourPalette::new(colours));
+    }
+
+    /// Records the changes made to the character since `before` in the edit history.
+    fn record_edit(&mut self, before: &Character, merge_key: Option<MergeKey>) {
+        self.history.record(before, &self.character, merge_key);
+    }
+
+    fn undo(&mut self) {
+        let layer_count = self.character.layers.len();
+        if self.history.undo(&mut self.character) && self.character.layers.len() != layer_count {
+            self.selected_part = None;
+        }
+    }
+
+    fn redo(&mut self) {
+        let layer_count = self.character.layers.len();
+        if self.history.redo(&mut self.character) && self.character.layers.len() != layer_count {
+            self.selected_part = None;
         }
+    }
 
+    fn get_normalised_character(&self) -> Character {
+        let mut normalised_character = self.character.clone();
+        normalised_character.normalise(
+            Point::new(self.portrait_rect.width(), self.portrait_rect.height()),
+            Point::new(self.token_rect.width(), self.token_rect.height()),
+        );
         normalised_character
     }
 
@@ -673,7 +963,7 @@ impl FECharacterCreator {
             let _portrait_rect = egui::Frame::canvas(ui.style())
                 .inner_margin(0.0)
                 .show(ui, |ui| {
-                    self.paint_canvas(ctx, ui, CanvasType::Portrait, canvas_size)
+                    self.paint_canvas(ctx, ui, Canvas::Portrait, canvas_size)
                 })
                 .inner;
 
@@ -684,7 +974,7 @@ impl FECharacterCreator {
             let token_rect = egui::Frame::canvas(ui.style())
                 .inner_margin(0.0)
                 .show(ui, |ui| {
-                    self.paint_canvas(ctx, ui, CanvasType::Token, canvas_size)
+                    self.paint_canvas(ctx, ui, Canvas::Token, canvas_size)
                 })
                 .inner;
 
@@ -693,35 +983,10 @@ impl FECharacterCreator {
         });
 
         if self.is_character_normalised && self.portrait_rect.width() > 0.0 {
-            let portrait_size = self.portrait_rect.size();
-            if portrait_size.x > 0.0 && portrait_size.y > 0.0 {
-                let portrait_parts = [
-                    AssetType::Armour,
-                    AssetType::Face,
-                    AssetType::Hair,
-                    AssetType::HairBack,
-                    AssetType::Accessory,
-                ];
-                for asset_type in portrait_parts {
-                    if let Some(mut part) = self.character.get_character_part(&asset_type) {
-                        part.position.x *= portrait_size.x;
-                        part.position.y *= portrait_size.y;
-                        part.scale *= portrait_size.y;
-                        self.character.set_character_part(&asset_type, part);
-                    }
-                }
-            }
-
-            let token_size = self.token_rect.size();
-            if token_size.x > 0.0
-                && token_size.y > 0.0
-                && let Some(mut part) = self.character.get_character_part(&AssetType::Token)
-            {
-                part.position.x *= token_size.x;
-                part.position.y *= token_size.y;
-                part.scale *= token_size.y;
-                self.character.set_character_part(&AssetType::Token, part);
-            }
+            self.character.denormalise(
+                Point::new(self.portrait_rect.width(), self.portrait_rect.height()),
+                Point::new(self.token_rect.width(), self.token_rect.height()),
+            );
             self.is_character_normalised = false;
             old_portrait_rect = self.portrait_rect;
             old_token_rect = self.token_rect;
@@ -731,13 +996,13 @@ impl FECharacterCreator {
             && (old_portrait_rect.width() - self.portrait_rect.width()).abs() > 1.0
         {
             let scale_factor = self.portrait_rect.width() / old_portrait_rect.width();
-            self.scale_character_parts(scale_factor, false);
+            self.scale_character_parts(scale_factor, Canvas::Portrait);
         }
         if old_token_rect.width() > 0.0
             && (old_token_rect.width() - self.token_rect.width()).abs() > 1.0
         {
             let scale_factor = self.token_rect.width() / old_token_rect.width();
-            self.scale_character_parts(scale_factor, true);
+            self.scale_character_parts(scale_factor, Canvas::Token);
         }
     }
 
@@ -745,7 +1010,7 @@ impl FECharacterCreator {
         &mut self,
         ctx: &Context,
         ui: &mut Ui,
-        canvas_type: CanvasType,
+        canvas: Canvas,
         canvas_size: Vec2,
     ) -> Rect {
         let (response, painter) = ui.allocate_painter(canvas_size, egui::Sense::click_and_drag());
@@ -756,36 +1021,39 @@ impl FECharacterCreator {
 
         painter.rect_filled(canvas_rect, 0.0, ui.style().visuals.extreme_bg_color);
 
-        let parts_to_draw = if canvas_type == CanvasType::Token {
-            vec![AssetType::Token]
-        } else {
-            vec![
-                AssetType::HairBack,
-                AssetType::Armour,
-                AssetType::Face,
-                AssetType::Hair,
-                AssetType::Accessory,
-            ]
-        };
+        let layers_to_draw: Vec<usize> = (0..self.character.layers.len())
+            .filter(|&index| {
+                let layer = &self.character.layers[index];
+                layer.visible && layer.asset_type.category().canvas == canvas
+            })
+            .collect();
 
+        let character_before_interaction = self.character.clone();
         self.handle_multi_touch(ctx);
 
-        for &part_type in &parts_to_draw {
-            if let Some(part) = self.character.get_character_part(&part_type)
-                && let Some(texture) = self.get_or_load_texture(ctx, &part.asset)
-            {
+        for &index in &layers_to_draw {
+            let Some(layer) = self.character.layers.get(index) else {
+                continue;
+            };
+            let part = layer.part.clone();
+            let is_companion = layer.asset_type.companion_of().is_some();
+            if let Some(texture) = self.get_or_load_texture(ctx, &part.asset) {
                 let rect = Self::paint_transformed_part(&painter, &part, &texture, canvas_rect);
 
-                if self.selected_part == Some(part_type) && part_type != AssetType::HairBack {
+                if self.selected_part == Some(index) && !is_companion && canvas == Canvas::Portrait
+                {
                     self.draw_interaction_handles(ui, rect, &part, response.rect, ctx);
                 }
             }
         }
 
-        if !parts_to_draw.contains(&AssetType::Token) {
-            self.handle_interaction_beginning(&response, canvas_rect, &parts_to_draw);
+        if canvas == Canvas::Portrait {
+            self.handle_interaction_beginning(&response, canvas_rect, &layers_to_draw);
             self.handle_ongoing_interactions(ctx, &response);
         }
+
+        let merge_key = self.selected_part.map(MergeKey::Transform);
+        self.record_edit(&character_before_interaction, merge_key);
         canvas_rect
     }
 
@@ -850,56 +1118,137 @@ impl FECharacterCreator {
         false
     }
 
-    fn is_character_valid(&self, character: &Character) -> bool {
-        let all_part_options = [
-            character.get_character_part(&AssetType::Armour),
-            character.get_character_part(&AssetType::Face),
-            character.get_character_part(&AssetType::Hair),
-            character.get_character_part(&AssetType::HairBack),
-            character.get_character_part(&AssetType::Accessory),
-            character.get_character_part(&AssetType::Token),
-        ];
-
-        for part in all_part_options.iter().flatten() {
-            if self
-                .asset_libraries
-                .get(&part.asset.asset_type)
-                .and_then(|lib| lib.get(&part.asset.id))
-                .is_none()
-            {
-                log::error!(
-                    "Validation failed: Asset '{}' of type '{:?}' not found in libraries.",
-                    part.asset.id,
-                    part.asset.asset_type
-                );
-                return false;
+    fn to_fecc_file(&self) -> FeccFile {
+        FeccFile::new(
+            self.get_normalised_character(),
+            CoordinateSpace::Normalised,
+            env!("CARGO_PKG_VERSION"),
+        )
+    }
+
+    /// Points a loaded character's parts at the current assets, matching renamed art by content.
+    ///
+    /// Parts whose art can't be found are removed and queued for the user to pick a substitute.
+    /// Returns the number of missing parts.
+    fn resolve_character_assets(
+        &mut self,
+        character: &mut Character,
+        is_normalised: bool,
+    ) -> usize {
+        let report = character.resolve_assets(&self.asset_libraries);
+
+        for (saved_id, id) in &report.renamed {
+            log::info!("Asset '{saved_id}' was found as '{id}'.");
+        }
+        for id in &report.changed {
+            log::warn!("The art for '{id}' has changed since the character was saved.");
+            self.toasts
+                .warning(format!("The art for {id} has changed since it was saved."));
+        }
+        for missing in &report.missing {
+            log::warn!(
+                "Asset '{}' not found in libraries.",
+                missing.layer.part.asset.id
+            );
+        }
+
+        // Queued parts are kept normalised, so they can be placed whatever the canvas size is later.
+        let mut missing_parts = report.missing;
+        if !is_normalised {
+            let mut parts = Character {
+                layers: missing_parts.iter().map(|m| m.layer.clone()).collect(),
+                ..Default::default()
+            };
+            parts.normalise(
+                Point::new(self.portrait_rect.width(), self.portrait_rect.height()),
+                Point::new(self.token_rect.width(), self.token_rect.height()),
+            );
+            for (missing, layer) in missing_parts.iter_mut().zip(parts.layers) {
+                missing.layer = layer;
             }
         }
-        true
+
+        self.pending_substitutions = missing_parts
+            .into_iter()
+            .map(|missing| PendingSubstitution {
+                choice: missing.substitutes.first().map(|s| s.asset.clone()),
+                missing,
+            })
+            .collect();
+        self.pending_substitutions.len()
+    }
+
+    /// Adds the chosen substitutes for a loaded character's missing parts to the character.
+    fn apply_substitutions(&mut self) {
+        let character_before = self.character.clone();
+
+        let mut indices = Vec::new();
+        let mut substituted = Character::default();
+        for pending in std::mem::take(&mut self.pending_substitutions) {
+            if let Some(asset) = pending.choice {
+                let mut layer = pending.missing.layer;
+                layer.part.asset = asset;
+                indices.push(pending.missing.index);
+                substituted.layers.push(layer);
+            }
+        }
+        if !self.is_character_normalised {
+            substituted.denormalise(
+                Point::new(self.portrait_rect.width(), self.portrait_rect.height()),
+                Point::new(self.token_rect.width(), self.token_rect.height()),
+            );
+        }
+
+        // Missing layers are in ascending order of where they were, so each can go back in place.
+        for (index, layer) in indices.into_iter().zip(substituted.layers) {
+            let index = index.min(self.character.layers.len());
+            self.character.layers.insert(index, layer);
+        }
+        self.selected_part = None;
+        self.record_edit(&character_before, None);
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl FECharacterCreator {
-    fn save_image(image: &image::RgbaImage, filename_stem: String) {
-        if let Some(path) = rfd::FileDialog::new()
-            .add_filter("PNG Image", &["png"])
+    /// Saves an exported file of the named format, along with the credits for its art next to it
+    /// if there are any.
+    fn save_export(
+        bytes: &[u8],
+        filename_stem: String,
+        (format_name, extension): (&str, &str),
+        credits: Option<String>,
+    ) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(format_name, &[extension])
             .set_file_name(&filename_stem)
             .save_file()
-            && let Err(e) = image.save(path)
-        {
+        else {
+            return;
+        };
+
+        if let Err(e) = std::fs::write(&path, bytes) {
             log::error!("Failed to save image: {e}");
+            return;
+        }
+
+        if let Some(credits) = credits
+            && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+            && let Err(e) =
+                std::fs::write(path.with_file_name(format!("{stem}_credits.txt")), credits)
+        {
+            log::error!("Failed to save credits: {e}");
         }
     }
 
     fn save_fecc(&self, filename_stem: String) {
-        let normalised_character = self.get_normalised_character();
+        let fecc_file = self.to_fecc_file();
         if let Some(path) = rfd::FileDialog::new()
             .add_filter("FECC Character", &["fecc"])
             .set_file_name(&filename_stem)
             .save_file()
         {
-            match serde_json::to_string_pretty(&normalised_character) {
+            match fecc_file.to_json() {
                 Ok(json) => {
                     if let Err(e) = std::fs::write(path, json) {
                         log::error!("Failed to save FECC file: {e}");
@@ -912,44 +1261,117 @@ impl FECharacterCreator {
         }
     }
 
+    fn save_fecc_bundle(&mut self, filename_stem: String) {
+        let bytes = match FeccBundle::new(self.to_fecc_file()).and_then(|b| b.to_bytes()) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::error!("Failed to create FECC bundle: {e}");
+                self.toasts.error("Failed to create bundle.");
+                return;
+            }
+        };
+
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("FECC Bundle", &["feccz"])
+            .set_file_name(&filename_stem)
+            .save_file()
+            && let Err(e) = std::fs::write(path, bytes)
+        {
+            log::error!("Failed to save FECC bundle: {e}");
+        }
+    }
+
     fn load_fecc(&self) {
         let sender = self.loaded_character_sender.clone();
         if let Some(path) = rfd::FileDialog::new()
-            .add_filter("FECC Character", &["fecc"])
+            .add_filter("FECC Character", &["fecc", "feccz"])
             .pick_file()
         {
-            let result = std::fs::read_to_string(path)
+            let result = std::fs::read(path)
                 .map_err(|e| e.to_string())
-                .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()));
+                .and_then(|bytes| FeccBundle::from_bytes(&bytes));
 
             sender
                 .unbounded_send(result)
                 .expect("Failed to send loaded character");
         }
     }
+
+    /// Takes the colours of the character from an image the user picks, such as an exported
+    /// portrait.
+    fn load_colour_reference(&self) {
+        let sender = self.colour_reference_sender.clone();
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG Image", &["png"])
+            .pick_file()
+        {
+            let result = image::open(path)
+                .map_err(|e| e.to_string())
+                .and_then(|image| extract_colour_scheme(&image.to_rgba8()));
+
+            sender
+                .unbounded_send(result)
+                .expect("Failed to send reference colours");
+        }
+    }
+
+    fn save_gba_palette(bytes: &[u8], filename_stem: String) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("GBA Palette", &["gbapal", "dmp"])
+            .set_file_name(&filename_stem)
+            .save_file()
+            && let Err(e) = std::fs::write(path, bytes)
+        {
+            log::error!("Failed to save GBA palette: {e}");
+        }
+    }
+
+    /// Reads the palette of a colourable from a raw GBA palette file the user picks.
+    fn load_gba_palette(&self, colourable: Colourable) {
+        let sender = self.gba_palette_sender.clone();
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("GBA Palette", &["gbapal", "dmp"])
+            .pick_file()
+        {
+            let result = std::fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| read_gba_palette(&bytes));
+
+            sender
+                .unbounded_send((colourable, result))
+                .expect("Failed to send GBA palette");
+        }
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
 impl FECharacterCreator {
-    fn save_image(image: &image::RgbaImage, filename_stem: String) {
-        use std::io::Cursor;
-
-        let mut bytes: Vec<u8> = Vec::new();
-        if let Err(e) = image.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png) {
-            log::error!("Failed to encode image as PNG: {e}");
-            return;
+    /// Downloads an exported file of the named format, along with the credits for its art if
+    /// there are any.
+    fn save_export(
+        bytes: &[u8],
+        filename_stem: String,
+        (_, extension): (&str, &str),
+        credits: Option<String>,
+    ) {
+        let filename = format!("{filename_stem}.{extension}");
+
+        if let Err(e) = fecc_core::file_io::trigger_download(bytes, &filename) {
+            log::error!("Failed to trigger download: {e}");
         }
 
-        let filename = format!("{}.png", filename_stem);
-
-        if let Err(e) = fecc_core::file_io::trigger_download(&bytes, &filename) {
+        if let Some(credits) = credits
+            && let Err(e) = fecc_core::file_io::trigger_download(
+                credits.as_bytes(),
+                &format!("{filename_stem}_credits.txt"),
+            )
+        {
             log::error!("Failed to trigger download: {e}");
         }
     }
 
     fn save_fecc(&self, filename_stem: String) {
-        let normalised_character = self.get_normalised_character();
-        match serde_json::to_string_pretty(&normalised_character) {
+        match self.to_fecc_file().to_json() {
             Ok(json) => {
                 let filename = format!("{}.fecc", filename_stem);
                 if let Err(e) = fecc_core::file_io::trigger_download(json.as_bytes(), &filename) {
@@ -962,20 +1384,81 @@ impl FECharacterCreator {
         }
     }
 
+    fn save_fecc_bundle(&mut self, filename_stem: String) {
+        match FeccBundle::new(self.to_fecc_file()).and_then(|b| b.to_bytes()) {
+            Ok(bytes) => {
+                let filename = format!("{}.feccz", filename_stem);
+                if let Err(e) = fecc_core::file_io::trigger_download(&bytes, &filename) {
+                    log::error!("Failed to trigger download: {e}");
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to create FECC bundle: {e}");
+                self.toasts.error("Failed to create bundle.");
+            }
+        }
+    }
+
     fn load_fecc(&self) {
         let sender = self.loaded_character_sender.clone();
         wasm_bindgen_futures::spawn_local(async move {
             if let Some(file) = rfd::AsyncFileDialog::new()
-                .add_filter("FECC Character", &["fecc"])
+                .add_filter("FECC Character", &["fecc", "feccz"])
                 .pick_file()
                 .await
             {
                 let bytes = file.read().await;
-                let result = serde_json::from_slice(&bytes).map_err(|e| e.to_string());
+                let result = FeccBundle::from_bytes(&bytes);
                 sender.unbounded_send(result).unwrap();
             }
         });
     }
+
+    /// Takes the colours of the character from an image the user picks, such as an exported
+    /// portrait.
+    fn load_colour_reference(&self) {
+        let sender = self.colour_reference_sender.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("PNG Image", &["png"])
+                .pick_file()
+                .await
+            {
+                let bytes = file.read().await;
+                let result = image::load_from_memory(&bytes)
+                    .map_err(|e| e.to_string())
+                    .and_then(|image| extract_colour_scheme(&image.to_rgba8()));
+                if let Err(e) = sender.unbounded_send(result) {
+                    log::error!("Failed to send reference colours: {e}");
+                }
+            }
+        });
+    }
+
+    fn save_gba_palette(bytes: &[u8], filename_stem: String) {
+        let filename = format!("{}.gbapal", filename_stem);
+        if let Err(e) = fecc_core::file_io::trigger_download(bytes, &filename) {
+            log::error!("Failed to trigger download: {e}");
+        }
+    }
+
+    /// Reads the palette of a colourable from a raw GBA palette file the user picks.
+    fn load_gba_palette(&self, colourable: Colourable) {
+        let sender = self.gba_palette_sender.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("GBA Palette", &["gbapal", "dmp"])
+                .pick_file()
+                .await
+            {
+                let bytes = file.read().await;
+                let result = read_gba_palette(&bytes);
+                if let Err(e) = sender.unbounded_send((colourable, result)) {
+                    log::error!("Failed to send GBA palette: {e}");
+                }
+            }
+        });
+    }
 }
 
 fn find_max_square_side(x: f32, y: f32, padding_x: f32, padding_y: f32) -> FitResult {
@@ -997,3 +1480,44 @@ fn find_max_square_side(x: f32, y: f32, padding_x: f32, padding_y: f32) -> FitRe
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// App state as it was stored before characters had layers.
+    const V1_STATE: &str = include_str!("../fixtures/app_state_v1.ron");
+
+    #[test]
+    fn test_restore_v1_state() {
+        let app: FECharacterCreator = ron::from_str(V1_STATE).expect("Failed to restore v1 state");
+
+        assert_eq!(app.character.name, "Old");
+        let layers: Vec<_> = app
+            .character
+            .layers
+            .iter()
+            .map(|layer| (layer.asset_type, layer.part.asset.id.as_str()))
+            .collect();
+        assert_eq!(
+            layers,
+            [
+                (AssetType::HairBack, "Long_HairBack"),
+                (AssetType::Face, "Aias_Face"),
+                (AssetType::Hair, "Long_Hair"),
+            ]
+        );
+        assert_eq!(
+            app.character.layers[2].part.asset.back_part.as_deref(),
+            Some("Long_HairBack")
+        );
+        assert_eq!(app.active_tab, AssetType::Hair);
+        assert_eq!(app.export_size, ExportSize::DOUBLE);
+
+        let stored = ron::ser::to_string(&app).expect("Failed to store state");
+        let restored: FECharacterCreator =
+            ron::from_str(&stored).expect("Failed to restore stored state");
+        assert_eq!(restored.character.layers.len(), 3);
+        assert_eq!(restored.export_size, ExportSize::DOUBLE);
+    }
+}
@@ -7,7 +7,6 @@ use eframe::epaint::{Color32, Stroke};
 use egui::Order::Background;
 use egui::ahash::HashMap;
 use egui::{Context, Id, LayerId, Painter, Response, Ui};
-use fecc_core::asset::AssetType;
 use fecc_core::character::CharacterPart;
 use std::f32::consts::TAU;
 use strum::IntoEnumIterator as _;
@@ -336,50 +335,43 @@ impl FECharacterCreator {
         &mut self,
         response: &Response,
         canvas_rect: Rect,
-        parts_to_draw: &[AssetType],
+        layers: &[usize],
     ) {
         if response.drag_started() {
             if self.interaction.is_none()
                 && let Some(hover_pos) = response.hover_pos()
             {
-                let mut part_found = None;
-                for part_type in parts_to_draw.iter().rev() {
-                    if let Some(part) = self.character.get_character_part(part_type)
-                        && Self::is_pixel_opaque(&part, hover_pos, canvas_rect)
-                    {
-                        part_found = Some(*part_type);
-                        break;
-                    }
-                }
-
-                if let Some(part_type) = part_found {
-                    let actual_part_type = if part_type == AssetType::HairBack {
-                        AssetType::Hair
-                    } else {
-                        part_type
-                    };
-                    self.selected_part = Some(actual_part_type);
+                self.selected_part = self.layer_at(hover_pos, canvas_rect, layers);
+                if self.selected_part.is_some() {
                     self.interaction = Some(Interaction::Move);
-                } else {
-                    self.selected_part = None;
                 }
             }
         } else if response.clicked()
             && self.interaction.is_none()
             && let Some(hover_pos) = response.hover_pos()
         {
-            let part_found = parts_to_draw.iter().rev().find(|&&part_type| {
-                let part = self.character.get_character_part(&part_type);
-                part.is_some_and(|p| Self::is_pixel_opaque(&p, hover_pos, canvas_rect))
-            });
+            self.selected_part = self.layer_at(hover_pos, canvas_rect, layers);
+        }
+    }
 
-            self.selected_part = part_found.map(|&part_type| {
-                if part_type == AssetType::HairBack {
-                    AssetType::Hair
-                } else {
-                    part_type
-                }
-            });
+    /// Returns the topmost layer with an opaque pixel at the given position.
+    ///
+    /// Companion layers, such as a `HairBack`, select the layer they belong to.
+    fn layer_at(&self, pos: Pos2, canvas_rect: Rect, layers: &[usize]) -> Option<usize> {
+        let index = *layers.iter().rev().find(|&&index| {
+            self.character
+                .layers
+                .get(index)
+                .is_some_and(|layer| Self::is_pixel_opaque(&layer.part, pos, canvas_rect))
+        })?;
+
+        Some(self.character.companion_owner_index(index).unwrap_or(index))
+    }
+
+    /// Applies a change made to a layer to its companion layer too, if it has one.
+    fn update_companion(&mut self, index: usize, update: impl FnOnce(&mut CharacterPart)) {
+        if let Some(companion) = self.character.companion_layer_index(index) {
+            update(&mut self.character.layers[companion].part);
         }
     }
 
@@ -389,9 +381,10 @@ impl FECharacterCreator {
         canvas_response: &Response,
     ) {
         if self.interaction == Some(Interaction::Flip) {
-            if let Some(selected_type) = self.selected_part
-                && let Some(mut part) = self.character.get_character_part(&selected_type)
+            if let Some(selected) = self.selected_part
+                && let Some(layer) = self.character.layers.get(selected)
             {
+                let mut part = layer.part.clone();
                 part.flipped = !part.flipped;
 
                 let content_bounds = self.get_content_bounds(&part, Rect::ZERO);
@@ -407,23 +400,18 @@ impl FECharacterCreator {
                 part.position.x += pos_correction.x;
                 part.position.y += pos_correction.y;
 
-                if selected_type == AssetType::Hair
-                    && let Some(mut hair_back) =
-                        self.character.get_character_part(&AssetType::HairBack)
-                {
-                    hair_back.flipped = !hair_back.flipped;
-                    hair_back.position.x += pos_correction.x;
-                    hair_back.position.y += pos_correction.y;
-                    self.character
-                        .set_character_part(&AssetType::HairBack, hair_back);
-                }
-                self.character.set_character_part(&selected_type, part);
+                self.update_companion(selected, |companion| {
+                    companion.flipped = !companion.flipped;
+                    companion.position.x += pos_correction.x;
+                    companion.position.y += pos_correction.y;
+                });
+                self.character.layers[selected].part = part;
             }
             self.interaction = None;
             return;
         }
 
-        let Some(selected_type) = self.selected_part else {
+        let Some(selected) = self.selected_part else {
             return;
         };
 
@@ -434,9 +422,10 @@ impl FECharacterCreator {
         {
             let interaction_copy = *interaction;
 
-            if let Some(mut part) = self.character.get_character_part(&selected_type)
+            if let Some(layer) = self.character.layers.get(selected)
                 && ctx.input(|i| i.pointer.any_down())
             {
+                let mut part = layer.part.clone();
                 let part_pos_vec = vec2(part.position.x, part.position.y);
                 let geometric_centre_abs = canvas_rect.min + part_pos_vec;
                 let old_rot = Rot2::from_angle(part.rotation);
@@ -451,15 +440,10 @@ impl FECharacterCreator {
                 match interaction_copy {
                     Interaction::Move => {
                         let delta = canvas_response.drag_delta();
-                        if selected_type == AssetType::Hair
-                            && let Some(mut hair_back) =
-                                self.character.get_character_part(&AssetType::HairBack)
-                        {
-                            hair_back.position.x += delta.x;
-                            hair_back.position.y += delta.y;
-                            self.character
-                                .set_character_part(&AssetType::HairBack, hair_back);
-                        }
+                        self.update_companion(selected, |companion| {
+                            companion.position.x += delta.x;
+                            companion.position.y += delta.y;
+                        });
                         part.position.x += delta.x;
                         part.position.y += delta.y;
                     }
@@ -478,16 +462,11 @@ impl FECharacterCreator {
                             part.position.x += pos_correction.x;
                             part.position.y += pos_correction.y;
 
-                            if selected_type == AssetType::Hair
-                                && let Some(mut hair_back) =
-                                    self.character.get_character_part(&AssetType::HairBack)
-                            {
-                                hair_back.scale *= scale_delta;
-                                hair_back.position.x += pos_correction.x;
-                                hair_back.position.y += pos_correction.y;
-                                self.character
-                                    .set_character_part(&AssetType::HairBack, hair_back);
-                            }
+                            self.update_companion(selected, |companion| {
+                                companion.scale *= scale_delta;
+                                companion.position.x += pos_correction.x;
+                                companion.position.y += pos_correction.y;
+                            });
                         }
                         self.interaction = Some(Interaction::Scale {
                             corner,
@@ -508,16 +487,12 @@ impl FECharacterCreator {
                         part.position.x += pos_correction.x;
                         part.position.y += pos_correction.y;
 
-                        if selected_type == AssetType::Hair
-                            && let Some(mut hair_back) =
-                                self.character.get_character_part(&AssetType::HairBack)
-                        {
-                            hair_back.rotation = part.rotation;
-                            hair_back.position.x += pos_correction.x;
-                            hair_back.position.y += pos_correction.y;
-                            self.character
-                                .set_character_part(&AssetType::HairBack, hair_back);
-                        }
+                        let rotation = part.rotation;
+                        self.update_companion(selected, |companion| {
+                            companion.rotation = rotation;
+                            companion.position.x += pos_correction.x;
+                            companion.position.y += pos_correction.y;
+                        });
 
                         self.interaction = Some(Interaction::Rotate {
                             start_grab_vec: current_pos.to_vec2(),
@@ -528,7 +503,7 @@ impl FECharacterCreator {
                     }
                 }
 
-                self.character.set_character_part(&selected_type, part);
+                self.character.layers[selected].part = part;
             } else {
                 self.interaction = None; // Drag released
             }
@@ -538,8 +513,9 @@ impl FECharacterCreator {
     pub(crate) fn handle_multi_touch(&mut self, ctx: &Context) {
         if let Some(multi_touch) = ctx.input(|i| i.multi_touch())
             && let Some(selected) = self.selected_part
-            && let Some(mut part) = self.character.get_character_part(&selected)
+            && let Some(layer) = self.character.layers.get(selected)
         {
+            let mut part = layer.part.clone();
             let old_rot = Rot2::from_angle(part.rotation);
             let old_scale = part.scale;
 
@@ -556,18 +532,14 @@ impl FECharacterCreator {
             part.position.x += pos_correction.x;
             part.position.y += pos_correction.y;
 
-            self.character.set_character_part(&selected, part.clone());
+            self.character.layers[selected].part = part;
 
-            if selected == AssetType::Hair
-                && let Some(mut hair_back) = self.character.get_character_part(&AssetType::HairBack)
-            {
-                hair_back.scale = new_scale;
-                hair_back.rotation = new_rot.angle();
-                hair_back.position.x += pos_correction.x;
-                hair_back.position.y += pos_correction.y;
-                self.character
-                    .set_character_part(&AssetType::HairBack, hair_back);
-            }
+            self.update_companion(selected, |companion| {
+                companion.scale = new_scale;
+                companion.rotation = new_rot.angle();
+                companion.position.x += pos_correction.x;
+                companion.position.y += pos_correction.y;
+            });
         }
     }
 }
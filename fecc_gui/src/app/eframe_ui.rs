@@ -4,19 +4,36 @@ use crate::extensions::toggle_switch::toggle;
 use crate::FECharacterCreator;
 use eframe::emath::vec2;
 use eframe::epaint::{Color32, Stroke};
-use egui::ahash::HashSet;
-use egui::{Button, Context, Image, RichText, Ui};
+use egui::{Button, Context, Image, Key, KeyboardShortcut, Modifiers, RichText, Ui};
 use egui_extras::install_image_loaders;
 use egui_extras::{Column, TableBuilder};
-use fecc_core::asset::AssetType;
+use fecc_core::asset::category::Canvas;
+use fecc_core::asset::metadata::AssetMetadata;
+use fecc_core::asset::{Asset, AssetType, SubstituteReason};
+use fecc_core::character::Colourable;
 use fecc_core::character::Colourable::Skin;
-use fecc_core::character::{CharacterPartColours, Colourable};
-use fecc_core::export::{ExportSize, export_character};
+use fecc_core::character::Shade;
+use fecc_core::character::history::MergeKey;
+use fecc_core::export::indexed::MAX_PALETTE_SIZE;
+use fecc_core::export::layered::LayeredFormat;
+use fecc_core::export::{
+    Anchor, ColourAnalysis, ExportSize, MugSheet, analyse_colours, credits_text, export_character,
+};
+use fecc_core::extensions::rgba::{ColourRamp, RampStrategy};
+use fecc_core::file_io::register_bundled_assets;
 use fecc_core::random::{randomize_assets, randomize_colours};
 use fecc_core::types::Rgba;
 use image::RgbaImage;
 use strum::IntoEnumIterator as _;
 
+fn substitute_reason_label(reason: SubstituteReason) -> &'static str {
+    match reason {
+        SubstituteReason::SameContent => "same art",
+        SubstituteReason::DifferentCasing => "different casing",
+        SubstituteReason::SimilarName(_) => "similar name",
+    }
+}
+
 // Helper functions for colour conversion
 fn to_c32(c: Rgba) -> Color32 {
     Color32::from_rgba_unmultiplied(c.r, c.g, c.b, c.a)
@@ -28,30 +45,20 @@ fn from_c32(c: Color32) -> Rgba {
 
 impl eframe::App for FECharacterCreator {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
-        {
-            let mut character = self.character.clone();
-            let asset_libraries = &self.asset_libraries;
-            let mut changed = false;
-
-            for asset_type in AssetType::iter() {
-                if let Some(part) = character.get_character_part(&asset_type)
-                    && let Some(asset_from_lib) = asset_libraries
-                        .get(&asset_type)
-                        .and_then(|lib| lib.get(&part.asset.id))
-                    && part.asset.image_data.is_none()
-                    && asset_from_lib.image_data.is_some()
-                {
-                    let new_part = fecc_core::character::CharacterPart {
-                        asset: asset_from_lib.clone(),
-                        ..part
-                    };
-                    character.set_character_part(&asset_type, new_part);
-                    changed = true;
-                }
-            }
-
-            if changed {
-                self.character = character;
+        // Received whether or not the colour panel is shown, as the parts panel needs them too.
+        self.update_stored_asset_libraries(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.update_reloaded_asset_libraries();
+
+        for layer in &mut self.character.layers {
+            if layer.part.asset.image_data.is_none()
+                && let Some(asset_from_lib) = self
+                    .asset_libraries
+                    .get(&layer.asset_type)
+                    .and_then(|lib| lib.get(&layer.part.asset.id))
+                && asset_from_lib.image_data.is_some()
+            {
+                layer.part.asset = asset_from_lib.clone();
             }
         }
 
@@ -77,16 +84,21 @@ impl eframe::App for FECharacterCreator {
         if let Some(mut rx) = self.loaded_character_receiver.take() {
             if let Ok(Some(result)) = rx.try_next() {
                 match result {
-                    Ok(loaded_character) => {
-                        if self.is_character_valid(&loaded_character) {
-                            self.character = loaded_character;
-                            self.is_character_normalised = true;
-                            self.character_needs_asset_refresh = true;
-                            self.texture_cache.clear();
+                    Ok(bundle) => {
+                        register_bundled_assets(&mut self.asset_libraries, bundle.assets);
+                        let mut loaded_character = bundle.file.into_normalised_character();
+                        let missing = self.resolve_character_assets(&mut loaded_character, true);
+                        self.character = loaded_character;
+                        self.history.clear();
+                        self.selected_part = None;
+                        self.is_character_normalised = true;
+                        self.character_needs_asset_refresh = true;
+                        if missing == 0 {
                             self.toasts.success("Successfully loaded character.");
                         } else {
-                            log::error!("Loaded character is invalid.");
-                            self.toasts.error("Character is invalid.");
+                            self.toasts.warning(format!(
+                                "Loaded character, but {missing} part(s) are missing."
+                            ));
                         }
                     }
                     Err(e) => {
@@ -98,6 +110,57 @@ impl eframe::App for FECharacterCreator {
             self.loaded_character_receiver = Some(rx);
         }
 
+        if let Some(mut rx) = self.colour_reference_receiver.take() {
+            while let Ok(Some(result)) = rx.try_next() {
+                match result {
+                    Ok(colours) => {
+                        let character_before = self.character.clone();
+                        let found = colours.len();
+                        // Keep each colourable's ramp, so its shading stays as the user chose.
+                        for (colourable, colours) in Colourable::iter()
+                            .filter_map(|colourable| Some((colourable, colours.get(&colourable)?)))
+                        {
+                            self.character
+                                .character_colours
+                                .entry(colourable)
+                                .or_default()
+                                .set(colours.base, self.character.gba_colours);
+                        }
+                        self.record_edit(&character_before, None);
+                        self.toasts
+                            .success(format!("Took {found} colour(s) from the image."));
+                    }
+                    Err(e) => {
+                        log::error!("Failed to take colours from image: {e}");
+                        self.toasts
+                            .error("No colours could be taken from the image.");
+                    }
+                }
+            }
+            self.colour_reference_receiver = Some(rx);
+        }
+
+        if let Some(mut rx) = self.gba_palette_receiver.take() {
+            while let Ok(Some(palette)) = rx.try_next() {
+                self.receive_gba_palette(palette);
+            }
+            self.gba_palette_receiver = Some(rx);
+        }
+
+        if !ctx.wants_keyboard_input() {
+            const UNDO: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+            const REDO: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Y);
+            const REDO_ALT: KeyboardShortcut =
+                KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+
+            // Check the longer shortcut first, as Ctrl+Z would also match Ctrl+Shift+Z.
+            if ctx.input_mut(|i| i.consume_shortcut(&REDO_ALT) || i.consume_shortcut(&REDO)) {
+                self.redo();
+            } else if ctx.input_mut(|i| i.consume_shortcut(&UNDO)) {
+                self.undo();
+            }
+        }
+
         egui::TopBottomPanel::top("top_toggle_bar")
             .resizable(false)
             .show(ctx, |ui| {
@@ -137,6 +200,24 @@ impl eframe::App for FECharacterCreator {
                         }
                     }
 
+                    ui.separator();
+
+                    if ui
+                        .add_enabled(self.history.can_undo(), Button::new("Undo"))
+                        .on_hover_text("Undo (Ctrl+Z)")
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+
+                    if ui
+                        .add_enabled(self.history.can_redo(), Button::new("Redo"))
+                        .on_hover_text("Redo (Ctrl+Y)")
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+
                     #[cfg(target_arch = "wasm32")]
                     if ui
                         .selectable_label(self.add_art_window_open, "Add Art")
@@ -187,29 +268,30 @@ impl eframe::App for FECharacterCreator {
                     }
                     if ui.add(Button::new("Randomise")).clicked() {
                         self.randomise_used = true;
+                        let character_before = self.character.clone();
+                        let tags = AssetMetadata::parse_tags(&self.randomise_tags);
 
-                        let types_to_randomize: Vec<AssetType> =
-                            AssetType::get_selectable_part_types()
-                                .filter(|asset_type| asset_type != &AssetType::Accessory)
+                        for canvas in [Canvas::Portrait, Canvas::Token] {
+                            let types_to_randomize: Vec<AssetType> = AssetType::randomised()
+                                .into_iter()
+                                .filter(|asset_type| asset_type.category().canvas == canvas)
                                 .collect();
 
-                        let canvas_size = fecc_core::types::Point::new(
-                            self.portrait_rect.width(),
-                            self.portrait_rect.height(),
-                        );
-
-                        randomize_assets(
-                            &mut self.character,
-                            &self.asset_libraries,
-                            &types_to_randomize,
-                            canvas_size,
-                        );
+                            let canvas_size = self.canvas_size(canvas);
+                            randomize_assets(
+                                &mut self.character,
+                                &self.asset_libraries,
+                                &types_to_randomize,
+                                canvas_size,
+                                &tags,
+                            );
+                        }
 
                         if self.randomise_colours_too {
                             randomize_colours(&mut self.character, &self.colour_palettes);
-                            self.texture_cache.clear();
                         }
 
+                        self.record_edit(&character_before, None);
                         self.character_needs_asset_refresh = true;
                     }
 
@@ -221,6 +303,18 @@ impl eframe::App for FECharacterCreator {
                         "Parts Only"
                     });
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Tags:");
+                    ui.text_edit_singleline(&mut self.randomise_tags)
+                        .on_hover_text(
+                            "Only randomise to parts with all of these comma-separated tags.",
+                        );
+                });
+                ui.separator();
+
+                egui::CollapsingHeader::new("Layers").show(ui, |ui| {
+                    self.show_layers(ui);
+                });
                 ui.separator();
 
                 let search_query = self.search_queries.entry(self.active_tab).or_default();
@@ -239,25 +333,18 @@ impl eframe::App for FECharacterCreator {
                     .clicked()
                 {
                     self.randomise_used = true;
+                    let character_before = self.character.clone();
                     let asset_type = self.active_tab;
-                    let canvas_size = if asset_type == AssetType::Token {
-                        fecc_core::types::Point::new(
-                            self.token_rect.width(),
-                            self.token_rect.height(),
-                        )
-                    } else {
-                        fecc_core::types::Point::new(
-                            self.portrait_rect.width(),
-                            self.portrait_rect.height(),
-                        )
-                    };
+                    let canvas_size = self.canvas_size(asset_type.category().canvas);
 
                     randomize_assets(
                         &mut self.character,
                         &self.asset_libraries,
                         &[asset_type],
                         canvas_size,
+                        &AssetMetadata::parse_tags(&self.randomise_tags),
                     );
+                    self.record_edit(&character_before, None);
                     self.character_needs_asset_refresh = true;
                 }
 
@@ -268,7 +355,9 @@ impl eframe::App for FECharacterCreator {
                         && let Some(asset) =
                             self.display_assets(ctx, ui, &library.clone(), &search_query_cleaned)
                     {
+                        let character_before = self.character.clone();
                         self.select_asset(&asset.clone(), asset_type);
+                        self.record_edit(&character_before, None);
                     }
                 });
             },
@@ -279,18 +368,40 @@ impl eframe::App for FECharacterCreator {
 
         self.show_about_window(ctx);
 
+        self.missing_art_window(ctx);
+
         egui::SidePanel::right("colour_selection")
             .default_width(0.0)
             .show_animated(ctx, self.colour_panel_expanded, |ui| {
                 self.update_stored_colour_palettes();
-                self.update_stored_asset_libraries(ctx, ui);
                 self.update_stored_image_data_cache();
 
                 ui.add_space(5.0);
 
                 if ui.button("Randomise Colours").clicked() {
+                    let character_before = self.character.clone();
                     randomize_colours(&mut self.character, &self.colour_palettes);
-                    self.texture_cache.clear();
+                    self.record_edit(&character_before, None);
+                }
+                if ui
+                    .button("Colours From Image...")
+                    .on_hover_text("Use the colours of an exported portrait or sprite")
+                    .clicked()
+                {
+                    self.load_colour_reference();
+                }
+                let mut gba_colours = self.character.gba_colours;
+                if ui
+                    .checkbox(&mut gba_colours, "GBA Colours")
+                    .on_hover_text(
+                        "Snap colours to the 15-bit colours that GBA hardware can show, so they \
+                         look the same in-game",
+                    )
+                    .changed()
+                {
+                    let character_before = self.character.clone();
+                    self.character.set_gba_colours(gba_colours);
+                    self.record_edit(&character_before, None);
                 }
 
                 ui.add_space(5.0);
@@ -302,11 +413,33 @@ impl eframe::App for FECharacterCreator {
                     ..Default::default()
                 };
 
+                // Colours that no visible part uses are dimmed, once all their art has loaded.
+                let colourable_users = self.colourable_users();
+                let collapsed_shades = self.character.collapsed_shades();
                 for colourable in
                     Colourable::iter().filter(|&colourable| colourable != Colourable::Outline)
                 {
+                    let used_by = colourable_users
+                        .as_ref()
+                        .map(|users| users.get(&colourable));
+                    let used_by_hint = used_by.map(|types| match types {
+                        Some(types) => format!(
+                            "Used by: {}",
+                            types
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        None => "Not used by any visible part".to_owned(),
+                    });
+
+                    let character_before = self.character.clone();
                     colour_picker_frame.show(ui, |ui| {
                         ui.vertical_centered(|ui| {
+                            if used_by == Some(None) {
+                                ui.multiply_opacity(0.4);
+                            }
                             let base_colour_c32 =
                                 to_c32(self.character.character_colours[&colourable].base);
                             let text_colour = base_colour_c32
@@ -324,7 +457,11 @@ impl eframe::App for FECharacterCreator {
                                     .stroke(Stroke::new(1.0, Color32::GRAY))
                                     .min_size(vec2(100.0, 20.0));
 
-                                if ui.add(button).clicked() {
+                                let mut response = ui.add(button);
+                                if let Some(hint) = &used_by_hint {
+                                    response = response.on_hover_text(hint);
+                                }
+                                if response.clicked() {
                                     *self
                                         .colour_picker_open_state
                                         .entry(colourable)
@@ -341,57 +478,69 @@ impl eframe::App for FECharacterCreator {
                                         .fill(to_c32(peek_colour));
 
                                     if ui.add(cycle_colours).clicked() {
-                                        self.character.character_colours.insert(
-                                            colourable,
-                                            CharacterPartColours::new(
-                                                self.colour_palettes
+                                        self.character
+                                            .character_colours
+                                            .entry(colourable)
+                                            .or_default()
+                                            .set(
+                                                *self
+                                                    .colour_palettes
                                                     .entry(colourable)
                                                     .or_default()
                                                     .next_cyclic(),
-                                            ),
-                                        );
-
-                                        self.texture_cache.clear();
+                                                self.character.gba_colours,
+                                            );
                                     }
                                 }
                             });
 
-                            if self.colour_picker_open_state[&colourable] {
+                            if self.colour_picker_open_state.get(&colourable) == Some(&true) {
                                 self.present_colour_picker(ctx, &colourable);
                             }
 
                             egui::Grid::new(colourable).show(ui, |ui| {
+                                let gba_colours = self.character.gba_colours;
                                 let colour_part = self
                                     .character
                                     .character_colours
                                     .entry(colourable)
                                     .or_default();
-                                let mut changed = false;
-
                                 let mut lighter = to_c32(colour_part.lighter);
                                 if ui.color_edit_button_srgba(&mut lighter).changed() {
-                                    colour_part.lighter = from_c32(lighter);
-                                    changed = true;
+                                    colour_part.set_shade(
+                                        Shade::Lighter,
+                                        from_c32(lighter),
+                                        gba_colours,
+                                    );
                                 }
 
                                 let mut neutral = to_c32(colour_part.neutral);
                                 if ui.color_edit_button_srgba(&mut neutral).changed() {
-                                    colour_part.neutral = from_c32(neutral);
-                                    changed = true;
+                                    colour_part.set_shade(
+                                        Shade::Neutral,
+                                        from_c32(neutral),
+                                        gba_colours,
+                                    );
                                 }
 
                                 let mut darker = to_c32(colour_part.darker);
                                 if ui.color_edit_button_srgba(&mut darker).changed() {
-                                    colour_part.darker = from_c32(darker);
-                                    changed = true;
+                                    colour_part.set_shade(
+                                        Shade::Darker,
+                                        from_c32(darker),
+                                        gba_colours,
+                                    );
                                 }
 
                                 if colourable == Skin {
                                     ui.end_row();
                                     let mut darker_darker = to_c32(colour_part.darker_darker);
                                     if ui.color_edit_button_srgba(&mut darker_darker).changed() {
-                                        colour_part.darker_darker = from_c32(darker_darker);
-                                        changed = true;
+                                        colour_part.set_shade(
+                                            Shade::DarkerDarker,
+                                            from_c32(darker_darker),
+                                            gba_colours,
+                                        );
                                     }
 
                                     let mut darker_darker_darker =
@@ -400,19 +549,35 @@ impl eframe::App for FECharacterCreator {
                                         .color_edit_button_srgba(&mut darker_darker_darker)
                                         .changed()
                                     {
-                                        colour_part.darker_darker_darker =
-                                            from_c32(darker_darker_darker);
-                                        changed = true;
+                                        colour_part.set_shade(
+                                            Shade::DarkerDarkerDarker,
+                                            from_c32(darker_darker_darker),
+                                            gba_colours,
+                                        );
                                     }
                                 }
-                                if changed {
-                                    self.texture_cache.clear();
-                                }
                             });
+
+                            let warn_colour = ui.visuals().warn_fg_color;
+                            for (_, lighter, darker) in collapsed_shades
+                                .iter()
+                                .filter(|(collapsed, ..)| *collapsed == colourable)
+                            {
+                                ui.colored_label(
+                                    warn_colour,
+                                    format!(
+                                        "The {} and {} shades are the same colour",
+                                        lighter.label(),
+                                        darker.label()
+                                    ),
+                                );
+                            }
                         });
                     });
+                    self.record_edit(&character_before, Some(MergeKey::Colour(colourable)));
                 }
 
+                let character_before = self.character.clone();
                 colour_picker_frame.show(ui, |ui| {
                     let outline_rgba = self
                         .character
@@ -438,8 +603,8 @@ impl eframe::App for FECharacterCreator {
                     egui::Window::new(self.active_tab.to_string() + " Outline Colour")
                         .open(
                             self.outline_picker_open_state
-                                .get_mut(&self.active_tab)
-                                .expect("Missing active_tab entry in outline_picker_open_state"),
+                                .entry(self.active_tab)
+                                .or_default(),
                         )
                         .show(ctx, |ui| {
                             ui.label(
@@ -462,14 +627,17 @@ impl eframe::App for FECharacterCreator {
                             );
 
                             if colour_changed {
-                                self.character.outline_colours.set_outline_colour(
-                                    self.active_tab,
-                                    &from_c32(current_outline_colour),
-                                );
-                                self.texture_cache.clear();
+                                let mut colour = from_c32(current_outline_colour);
+                                if self.character.gba_colours {
+                                    colour = colour.to_gba();
+                                }
+                                self.character
+                                    .outline_colours
+                                    .set_outline_colour(self.active_tab, &colour);
                             }
                         });
                 });
+                self.record_edit(&character_before, Some(MergeKey::Outline(self.active_tab)));
 
                 ui.separator();
 
@@ -478,13 +646,7 @@ impl eframe::App for FECharacterCreator {
                     let result = Self::analyse_combined_colours(
                         &export_character(
                             &self.character,
-                            &[
-                                AssetType::HairBack,
-                                AssetType::Armour,
-                                AssetType::Face,
-                                AssetType::Hair,
-                                AssetType::Accessory,
-                            ],
+                            &AssetType::on_canvas(Canvas::Portrait),
                             (96, 96),
                             fecc_core::types::Point::new(
                                 self.portrait_rect.width(),
@@ -493,7 +655,7 @@ impl eframe::App for FECharacterCreator {
                         ),
                         &export_character(
                             &self.character,
-                            &[AssetType::Token],
+                            &AssetType::on_canvas(Canvas::Token),
                             (64, 64),
                             fecc_core::types::Point::new(
                                 self.token_rect.width(),
@@ -503,23 +665,23 @@ impl eframe::App for FECharacterCreator {
                     );
 
                     match result {
-                        Ok((colour_count, has_semi_transparency)) => {
-                            let count_colour = if colour_count > 15 {
+                        Ok(analysis) => {
+                            let count_colour = if analysis.unique_colours > MugSheet::MAX_COLOURS {
                                 Color32::RED
                             } else {
                                 Color32::GREEN
                             };
                             ui.label(
-                                RichText::new(colour_count.to_string())
+                                RichText::new(analysis.unique_colours.to_string())
                                     .color(count_colour)
                                     .strong(),
-                            );
+                            )
+                            .on_hover_text("GBA mugs can use 15 colours and transparency");
 
-                            if has_semi_transparency {
-                                ui.label(
-                                    RichText::new(" (has semi-transparency!)")
-                                        .color(Color32::RED)
-                                        .strong(),
+                            if analysis.semi_transparent {
+                                ui.weak(" (has semi-transparency)").on_hover_text(
+                                    "Semi-transparent pixels are kept in exports, but can't be \
+                                     shown by indexed palettes.",
                                 );
                             }
                         }
@@ -548,78 +710,218 @@ impl eframe::App for FECharacterCreator {
                 ui.horizontal(|ui| {
                     ui.label("Select Output Size:");
                     egui::ComboBox::from_label("")
-                        .selected_text(self.export_size_selection.display_name())
+                        .selected_text(self.export_size.display_name())
                         .show_ui(ui, |ui| {
-                            ui.selectable_value(
-                                &mut self.export_size_selection,
-                                ExportSize::Half,
-                                ExportSize::Half.display_name(),
-                            );
-                            ui.selectable_value(
-                                &mut self.export_size_selection,
-                                ExportSize::Original,
-                                ExportSize::Original.display_name(),
-                            );
-                            ui.selectable_value(
-                                &mut self.export_size_selection,
-                                ExportSize::Double,
-                                ExportSize::Double.display_name(),
-                            );
+                            for (_, size) in ExportSize::PRESETS {
+                                ui.selectable_value(
+                                    &mut self.export_size,
+                                    size,
+                                    size.display_name(),
+                                );
+                            }
                         });
                 });
 
+                egui::CollapsingHeader::new("Custom Size").show(ui, |ui| {
+                    egui::Grid::new("custom_export_size").show(ui, |ui| {
+                        for (label, (width, height)) in [
+                            ("Portrait", &mut self.export_size.portrait),
+                            ("Token", &mut self.export_size.token),
+                        ] {
+                            ui.label(label);
+                            ui.add(egui::DragValue::new(width).range(1..=1024).prefix("w: "));
+                            ui.add(egui::DragValue::new(height).range(1..=1024).prefix("h: "));
+                            ui.end_row();
+                        }
+
+                        ui.label("Scale");
+                        ui.add(
+                            egui::DragValue::new(&mut self.export_size.scale)
+                                .range(1..=8)
+                                .suffix("x"),
+                        )
+                        .on_hover_text("Each pixel is drawn as a square this many pixels wide");
+                        ui.end_row();
+
+                        ui.label("Anchor");
+                        egui::ComboBox::from_id_salt("export_anchor")
+                            .selected_text(self.export_size.anchor.to_string())
+                            .show_ui(ui, |ui| {
+                                for anchor in Anchor::iter() {
+                                    ui.selectable_value(
+                                        &mut self.export_size.anchor,
+                                        anchor,
+                                        anchor.to_string(),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Where the character is placed on canvases that aren't square",
+                            );
+                        ui.end_row();
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.indexed_export, "Indexed PNG")
+                        .on_hover_text(
+                            "Save with an ordered palette, transparency first, for game engines \
+                             and ROM tools",
+                        );
+                    ui.add_enabled_ui(self.indexed_export, |ui| {
+                        ui.checkbox(&mut self.quantise_colours, "Limit colours to");
+                        ui.add_enabled(
+                            self.quantise_colours,
+                            egui::DragValue::new(&mut self.max_colours)
+                                .range(1..=MAX_PALETTE_SIZE - 1),
+                        );
+                        if ui
+                            .button("Check Palette")
+                            .on_hover_text("List the colours that would be merged in the portrait")
+                            .clicked()
+                        {
+                            let report = self
+                                .export_canvas(Canvas::Portrait)
+                                .ok_or_else(|| "The portrait hasn't been drawn yet.".to_owned())
+                                .and_then(|image| self.index_image(&image));
+                            self.palette_report = Some(match report {
+                                Ok(indexed) if indexed.merges.is_empty() => vec![format!(
+                                    "The portrait fits in {} colours.",
+                                    indexed.palette.len() - 1
+                                )],
+                                Ok(indexed) => {
+                                    indexed.merges.iter().map(ToString::to_string).collect()
+                                }
+                                Err(e) => vec![e],
+                            });
+                        }
+                    });
+                });
+                if self.indexed_export
+                    && let Some(report) = &self.palette_report
+                {
+                    for line in report {
+                        ui.label(line);
+                    }
+                }
+                if ui
+                    .button("Export GBA Palette")
+                    .on_hover_text(
+                        "Save the portrait's palette as a raw .gbapal file for ROM tools, \
+                         transparency first",
+                    )
+                    .clicked()
+                {
+                    self.export_gba_palette();
+                }
+
                 ui.separator();
 
                 if ui
                     .button(format!(
                         "Export Portrait ({}x{})",
-                        self.export_size_selection.portrait().0,
-                        self.export_size_selection.portrait().1
+                        self.export_size.portrait().0,
+                        self.export_size.portrait().1
                     ))
                     .clicked()
-                    && let Some(image) = export_character(
-                        &self.character,
-                        &[
-                            AssetType::HairBack,
-                            AssetType::Armour,
-                            AssetType::Face,
-                            AssetType::Hair,
-                            AssetType::Accessory,
-                        ],
-                        (
-                            self.export_size_selection.portrait().0,
-                            self.export_size_selection.portrait().1,
-                        ),
-                        fecc_core::types::Point::new(
-                            self.portrait_rect.width(),
-                            self.portrait_rect.height(),
-                        ),
-                    )
+                    && let Some(image) = self.export_canvas(Canvas::Portrait)
                 {
-                    Self::save_image(&image, self.character.name.clone() + "_portrait");
+                    self.save_image(
+                        &image,
+                        self.character.name.clone() + "_portrait",
+                        credits_text(&self.character, &AssetType::on_canvas(Canvas::Portrait)),
+                    );
                 }
 
                 if ui
                     .button(format!(
                         "Export Token ({}x{})",
-                        self.export_size_selection.token().0,
-                        self.export_size_selection.token().1
+                        self.export_size.token().0,
+                        self.export_size.token().1
                     ))
                     .clicked()
-                    && let Some(image) = export_character(
+                    && let Some(image) = self.export_canvas(Canvas::Token)
+                {
+                    self.save_image(
+                        &image,
+                        self.character.name.clone() + "token",
+                        credits_text(&self.character, &AssetType::on_canvas(Canvas::Token)),
+                    );
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Layered:");
+                    egui::ComboBox::from_id_salt("layered_format")
+                        .selected_text(self.layered_format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in LayeredFormat::iter() {
+                                ui.selectable_value(
+                                    &mut self.layered_format,
+                                    format,
+                                    format.to_string(),
+                                );
+                            }
+                        });
+                    let hint = "Each part on its own layer, recoloured and placed, for touching \
+                                up in Krita, GIMP or Aseprite";
+                    if ui.button("Portrait").on_hover_text(hint).clicked() {
+                        self.export_layered(Canvas::Portrait);
+                    }
+                    if ui.button("Token").on_hover_text(hint).clicked() {
+                        self.export_layered(Canvas::Token);
+                    }
+                });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("GBA Mug Sheet Layout").show(ui, |ui| {
+                    ui.label("Where the frames are cut from the 96x80 portrait:");
+                    for (label, (x, y)) in [
+                        ("Eyes", &mut self.mug_sheet.eyes),
+                        ("Mouth", &mut self.mug_sheet.mouth),
+                        ("Minimug", &mut self.mug_sheet.minimug),
+                    ] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            ui.add(
+                                egui::DragValue::new(x)
+                                    .range(0..=MugSheet::PORTRAIT.0)
+                                    .prefix("x: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(y)
+                                    .range(0..=MugSheet::PORTRAIT.1)
+                                    .prefix("y: "),
+                            );
+                        });
+                    }
+                });
+
+                if ui
+                    .button(format!("Export {}", MugSheet::display_name()))
+                    .on_hover_text(
+                        "The portrait, minimug and eye and mouth frames laid out for GBA Fire \
+                         Emblem hacking tools",
+                    )
+                    .clicked()
+                {
+                    match self.mug_sheet.export(
                         &self.character,
-                        &[AssetType::Token],
-                        (
-                            self.export_size_selection.token().0,
-                            self.export_size_selection.token().1,
-                        ),
                         fecc_core::types::Point::new(
-                            self.token_rect.width(),
-                            self.token_rect.height(),
+                            self.portrait_rect.width(),
+                            self.portrait_rect.height(),
                         ),
-                    )
-                {
-                    Self::save_image(&image, self.character.name.clone() + "token");
+                    ) {
+                        Ok(image) => self.save_image(
+                            &image,
+                            self.character.name.clone() + "_mug",
+                            credits_text(&self.character, &AssetType::on_canvas(Canvas::Portrait)),
+                        ),
+                        Err(e) => {
+                            self.toasts.error(e);
+                        }
+                    }
                 }
             },
         );
@@ -640,6 +942,14 @@ impl eframe::App for FECharacterCreator {
                     self.save_fecc(self.character.name.clone());
                 }
 
+                if ui
+                    .button("Save FECC Bundle")
+                    .on_hover_text("Save the character together with the art it uses.")
+                    .clicked()
+                {
+                    self.save_fecc_bundle(self.character.name.clone());
+                }
+
                 if ui.button("Load FECC").clicked() {
                     self.load_fecc();
                 }
@@ -650,6 +960,10 @@ impl eframe::App for FECharacterCreator {
             self.update_rect(ctx, ui);
         });
 
+        if ctx.input(|i| i.pointer.any_released()) {
+            self.history.end_merge();
+        }
+
         self.new_active_tab = false;
         self.randomise_used = false;
         self.toasts.show(ctx);
@@ -706,6 +1020,154 @@ impl FECharacterCreator {
             });
     }
 
+    /// Lists the layers of the character, topmost first, with controls to reorder, hide and remove
+    /// them.
+    fn show_layers(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.add_as_new_layer, "Add parts as new layers")
+            .on_hover_text("Add chosen parts on top instead of replacing one of the same type");
+
+        let character_before = self.character.clone();
+        let layer_count = self.character.layers.len();
+        let mut move_layer = None;
+        let mut remove_layer = None;
+
+        for index in (0..layer_count).rev() {
+            let can_raise = self.character.can_move_layer(index, index + 1);
+            let can_lower = index > 0 && self.character.can_move_layer(index, index - 1);
+            let layer = &mut self.character.layers[index];
+            let selected_part = &mut self.selected_part;
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut layer.visible, "")
+                    .on_hover_text("Show this layer");
+                if ui
+                    .selectable_label(
+                        *selected_part == Some(index),
+                        format!("{}: {}", layer.asset_type, layer.part.asset.name),
+                    )
+                    .clicked()
+                {
+                    *selected_part = Some(index);
+                }
+                if ui
+                    .add_enabled(can_raise, Button::new("⬆").small())
+                    .on_hover_text("Draw above the next layer")
+                    .clicked()
+                {
+                    move_layer = Some((index, index + 1));
+                }
+                if ui
+                    .add_enabled(can_lower, Button::new("⬇").small())
+                    .on_hover_text("Draw below the previous layer")
+                    .clicked()
+                {
+                    move_layer = Some((index, index - 1));
+                }
+                if ui
+                    .add(Button::new("🗑").small())
+                    .on_hover_text("Remove this layer")
+                    .clicked()
+                {
+                    remove_layer = Some(index);
+                }
+            });
+        }
+
+        if let Some((from, to)) = move_layer {
+            self.character.move_layer(from, to);
+            self.selected_part = match self.selected_part {
+                Some(selected) if selected == from => Some(to),
+                Some(selected) if selected == to => Some(from),
+                selected => selected,
+            };
+        }
+        if let Some(index) = remove_layer {
+            self.character.remove_layer(index);
+            self.selected_part = None;
+        }
+        // Companion layers move with the layer they belong to, so select that one instead.
+        if let Some(selected) = self.selected_part
+            && let Some(owner) = self.character.companion_owner_index(selected)
+        {
+            self.selected_part = Some(owner);
+        }
+
+        self.record_edit(&character_before, None);
+    }
+
+    fn missing_art_window(&mut self, ctx: &Context) {
+        if self.pending_substitutions.is_empty() {
+            return;
+        }
+
+        let mut apply = false;
+        let mut leave_out = false;
+        egui::Window::new("Missing Art")
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label("Some of the loaded character's art couldn't be found.");
+                ui.label("Choose a replacement for each part, or leave it out.");
+                ui.separator();
+
+                egui::Grid::new("missing_art").striped(true).show(ui, |ui| {
+                    for (i, pending) in self.pending_substitutions.iter_mut().enumerate() {
+                        ui.label(format!(
+                            "{}: {}",
+                            pending.missing.layer.asset_type, pending.missing.layer.part.asset.id
+                        ));
+
+                        let selected_text = pending
+                            .choice
+                            .as_ref()
+                            .map_or("Leave out", |asset| asset.id.as_str())
+                            .to_owned();
+                        egui::ComboBox::from_id_salt(("missing_art", i))
+                            .selected_text(selected_text)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut pending.choice, None, "Leave out");
+                                for substitute in &pending.missing.substitutes {
+                                    ui.selectable_value(
+                                        &mut pending.choice,
+                                        Some(substitute.asset.clone()),
+                                        format!(
+                                            "{} ({})",
+                                            substitute.asset.id,
+                                            substitute_reason_label(substitute.reason)
+                                        ),
+                                    );
+                                }
+
+                                ui.separator();
+                                if let Some(library) =
+                                    self.asset_libraries.get(&pending.missing.layer.asset_type)
+                                {
+                                    for asset in library.values() {
+                                        ui.selectable_value(
+                                            &mut pending.choice,
+                                            Some(asset.clone()),
+                                            &asset.id,
+                                        );
+                                    }
+                                }
+                            });
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    leave_out = ui.button("Leave All Out").clicked();
+                });
+            });
+
+        if apply {
+            self.apply_substitutions();
+        } else if leave_out {
+            self.pending_substitutions.clear();
+        }
+    }
+
     fn show_about_window(&mut self, ctx: &Context) {
         egui::Window::new("About")
             .open(&mut self.about_window_open)
@@ -747,7 +1209,6 @@ impl FECharacterCreator {
             match rx.try_recv() {
                 Ok(Some(palettes)) => {
                     self.colour_palettes = palettes;
-                    self.texture_cache.clear();
                 }
                 Ok(None) => {
                     self.palettes_receiver = Some(rx);
@@ -759,54 +1220,48 @@ impl FECharacterCreator {
         }
     }
 
-    fn update_stored_asset_libraries(&mut self, ctx: &Context, _ui: &mut Ui) {
+    fn update_stored_asset_libraries(&mut self, ctx: &Context) {
         if let Some(mut rx) = self.asset_libraries_receiver.take() {
             match rx.try_recv() {
                 Ok(Some(libs)) => {
                     self.asset_libraries = libs;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    self.watch_asset_roots(ctx);
 
                     if self.character_needs_asset_refresh {
-                        for asset_type in AssetType::iter() {
-                            if let Some(part) = self.character.get_character_part(&asset_type)
-                                && let Some(asset_from_lib) = self
-                                    .asset_libraries
-                                    .get(&asset_type)
-                                    .and_then(|lib| lib.get(&part.asset.id))
-                            {
-                                let new_part = fecc_core::character::CharacterPart {
-                                    asset: asset_from_lib.clone(),
-                                    ..part
-                                };
-                                self.character.set_character_part(&asset_type, new_part);
-                            }
+                        let mut character = std::mem::take(&mut self.character);
+                        let missing = self
+                            .resolve_character_assets(&mut character, self.is_character_normalised);
+                        if missing > 0 {
+                            self.toasts.warning(format!(
+                                "{missing} of the character's parts are missing."
+                            ));
                         }
+                        self.character = character;
                         self.character_needs_asset_refresh = false;
 
                         // Also trigger image loading
-                        let assets_to_load: Vec<_> = [
-                            self.character.get_character_part(&AssetType::Armour),
-                            self.character.get_character_part(&AssetType::Face),
-                            self.character.get_character_part(&AssetType::Hair),
-                            self.character.get_character_part(&AssetType::HairBack),
-                            self.character.get_character_part(&AssetType::Accessory),
-                            self.character.get_character_part(&AssetType::Token),
-                        ]
-                        .iter()
-                        .flatten()
-                        .map(|part| part.asset.clone())
-                        .collect();
+                        let assets_to_load: Vec<_> = self
+                            .character
+                            .layers
+                            .iter()
+                            .map(|layer| layer.part.asset.clone())
+                            .collect();
 
                         for asset in assets_to_load {
                             self.get_or_load_texture(ctx, &asset);
                         }
                     }
 
-                    if let Some(hair_back_library) =
-                        self.asset_libraries.get(&AssetType::HairBack).cloned()
-                    {
-                        for asset in hair_back_library.values() {
-                            self.get_or_load_texture(ctx, asset);
-                        }
+                    // Companion parts are drawn behind their parent's thumbnail.
+                    let companion_assets: Vec<Asset> = self
+                        .asset_libraries
+                        .iter()
+                        .filter(|(asset_type, _)| asset_type.companion_of().is_some())
+                        .flat_map(|(_, library)| library.values().cloned())
+                        .collect();
+                    for asset in &companion_assets {
+                        self.get_or_load_texture(ctx, asset);
                     }
                 }
                 Ok(None) => {
@@ -822,23 +1277,17 @@ impl FECharacterCreator {
             while let Ok(Some((id, result))) = rx.try_next() {
                 self.images_in_flight.remove(&id);
                 match result {
-                    Ok(image_data) => {
-                        let asset_type: Result<AssetType, _> =
-                            id.rsplit_once('_').expect("REASON").1.parse();
-
-                        match asset_type {
-                            Ok(asset_type) => {
-                                if let Some(asset) = self
-                                    .asset_libraries
-                                    .get_mut(&asset_type)
-                                    .and_then(|lib| lib.get_mut(&id))
-                                {
-                                    asset.image_data = Some(image_data);
-                                } else {
-                                    log::warn!("Received image for unknown asset: {id}");
-                                }
-                            }
-                            Err(e) => println!("Failed to parse: {e}"),
+                    Ok(loaded) => {
+                        if let Some(asset) = self
+                            .asset_libraries
+                            .get_mut(&loaded.asset_type)
+                            .and_then(|lib| lib.get_mut(&id))
+                        {
+                            asset.image_data = loaded.image_data;
+                            asset.content_hash = loaded.content_hash;
+                            asset.file_bytes = loaded.file_bytes;
+                        } else {
+                            log::warn!("Received image for unknown asset: {id}");
                         }
                     }
                     Err(e) => {
@@ -850,6 +1299,7 @@ impl FECharacterCreator {
     }
 
     fn present_colour_picker(&mut self, ctx: &Context, colourable: &Colourable) {
+        let mut import_gba_palette = false;
         egui::Window::new(colourable.to_string() + " Colour")
             .open(
                 self.colour_picker_open_state
@@ -860,6 +1310,7 @@ impl FECharacterCreator {
                 ui.label("Select a new ".to_owned() + &*colourable.to_string() + " colour:");
                 ui.spacing_mut().slider_width = 275.0;
 
+                let gba_colours = self.character.gba_colours;
                 let colour_part = self
                     .character
                     .character_colours
@@ -874,12 +1325,41 @@ impl FECharacterCreator {
                 );
 
                 if colour_changed {
-                    colour_part.set(from_c32(base_c32));
+                    colour_part.set(from_c32(base_c32), gba_colours);
                     // derive_all_colours called inside set()
-                    self.texture_cache.clear();
                 }
 
+                egui::CollapsingHeader::new("Shading").show(ui, |ui| {
+                    let mut ramp = colour_part.ramp;
+                    egui::ComboBox::from_label("Ramp")
+                        .selected_text(ramp.strategy.to_string())
+                        .show_ui(ui, |ui| {
+                            for strategy in RampStrategy::iter() {
+                                ui.selectable_value(
+                                    &mut ramp.strategy,
+                                    strategy,
+                                    strategy.to_string(),
+                                );
+                            }
+                        });
+                    ui.add(
+                        egui::Slider::new(&mut ramp.strength, 0..=ColourRamp::MAX_STRENGTH)
+                            .text("Strength")
+                            .suffix("%"),
+                    )
+                    .on_hover_text("How far apart the lighter and darker shades are");
+
+                    if ramp != colour_part.ramp {
+                        colour_part.set_ramp(ramp, gba_colours);
+                    }
+                });
+
                 egui::CollapsingHeader::new("Colour Palette").show(ui, |ui| {
+                    import_gba_palette = ui
+                        .button("Import GBA Palette...")
+                        .on_hover_text("Use the colours of a .gbapal or .dmp palette file")
+                        .clicked();
+
                     let columns = 9;
                     let palette_colours = self.colour_palettes[colourable].colours();
                     let rows = (palette_colours.len() as f32 / columns as f32).ceil() as usize;
@@ -911,8 +1391,7 @@ impl FECharacterCreator {
                                                     .character_colours
                                                     .entry(*colourable)
                                                     .or_default()
-                                                    .set(colour);
-                                                self.texture_cache.clear();
+                                                    .set(colour, gba_colours);
                                             }
                                         }
                                     });
@@ -922,27 +1401,18 @@ impl FECharacterCreator {
                     });
                 });
             });
+
+        if import_gba_palette {
+            self.load_gba_palette(*colourable);
+        }
     }
 
     fn analyse_combined_colours(
         img_a: &Option<RgbaImage>,
         img_b: &Option<RgbaImage>,
-    ) -> Result<(usize, bool), &'static str> {
+    ) -> Result<ColourAnalysis, &'static str> {
         let img_a = img_a.as_ref().ok_or("Image A missing")?;
         let img_b = img_b.as_ref().ok_or("Image B missing")?;
-        let mut unique_colours = HashSet::default();
-        let mut has_semi_transparency = false;
-
-        for pixel in img_a.pixels().chain(img_b.pixels()) {
-            let alpha = pixel[3];
-            if alpha != 0 {
-                unique_colours.insert(pixel);
-            }
-            if !has_semi_transparency && (alpha > 0 && alpha < 255) {
-                has_semi_transparency = true;
-            }
-        }
-
-        Ok((unique_colours.len(), has_semi_transparency))
+        Ok(analyse_colours([img_a, img_b]))
     }
 }